@@ -20,7 +20,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         ap.parse_args_or_exit();
     }
 
-    let mut client = Client::new().await?;
+    let client = Client::new().await?;
 
     let data = json!({
         "key": "value",
@@ -1,13 +1,15 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
+use crate::notification::Localized;
+
 use super::{
     android_fcm_options::{AndroidFcmOptions, AndroidFcmOptionsInternal},
     android_message_priority::AndroidMessagePriority,
     android_notification::{AndroidNotification, AndroidNotificationInternal},
 };
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, Default)]
 pub(crate) struct AndroidConfigInternal<'m> {
     #[serde(skip_serializing_if = "Option::is_none")]
     collapse_key: Option<&'m str>,
@@ -34,7 +36,7 @@ pub(crate) struct AndroidConfigInternal<'m> {
     direct_boot_ok: Option<bool>,
 }
 
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
 /// https://firebase.google.com/docs/reference/fcm/rest/v1/projects.messages?authuser=0#androidconfig
 pub struct AndroidConfig {
     /// An identifier of a group of messages that can be collapsed, so that only the last message gets
@@ -65,7 +67,7 @@ pub struct AndroidConfig {
 }
 
 impl AndroidConfig {
-    pub(crate) fn finalize(&self) -> AndroidConfigInternal {
+    pub(crate) fn finalize(&self) -> AndroidConfigInternal<'_> {
         AndroidConfigInternal {
             collapse_key: self.collapse_key.as_deref(),
             priority: self.priority,
@@ -78,3 +80,12 @@ impl AndroidConfig {
         }
     }
 }
+
+impl<'m> AndroidConfigInternal<'m> {
+    /// Fill the localization keys that are not set explicitly from the common notification.
+    pub(crate) fn apply_localized(&mut self, title: Option<&'m Localized>, body: Option<&'m Localized>) {
+        self.notification
+            .get_or_insert_with(Default::default)
+            .apply_localized(title, body);
+    }
+}
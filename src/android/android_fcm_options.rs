@@ -1,11 +1,11 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Debug)]
 pub(crate) struct AndroidFcmOptionsInternal<'m> {
     analytics_label: &'m str,
 }
 
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
 /// https://firebase.google.com/docs/reference/fcm/rest/v1/projects.messages?authuser=0#androidconfig
 pub struct AndroidFcmOptions {
     /// Label associated with the message's analytics data.
@@ -13,7 +13,7 @@ pub struct AndroidFcmOptions {
 }
 
 impl AndroidFcmOptions {
-    pub(crate) fn finalize(&self) -> AndroidFcmOptionsInternal {
+    pub(crate) fn finalize(&self) -> AndroidFcmOptionsInternal<'_> {
         AndroidFcmOptionsInternal {
             analytics_label: &self.analytics_label,
         }
@@ -1,4 +1,6 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+
+use crate::notification::Localized;
 
 use super::{
     light_settings::{LightSettings, LightSettingsInternal},
@@ -6,7 +8,7 @@ use super::{
     visibility::Visibility,
 };
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, Default)]
 /// https://firebase.google.com/docs/reference/fcm/rest/v1/projects.messages?authuser=0#androidnotification
 pub(crate) struct AndroidNotificationInternal<'m> {
     /// The notification's title.
@@ -116,7 +118,7 @@ pub(crate) struct AndroidNotificationInternal<'m> {
     image: Option<&'m str>,
 }
 
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
 /// https://firebase.google.com/docs/reference/fcm/rest/v1/projects.messages?authuser=0#androidnotification
 pub struct AndroidNotification {
     /// The notification's title.
@@ -202,7 +204,7 @@ pub struct AndroidNotification {
 }
 
 impl AndroidNotification {
-    pub(crate) fn finalize(&self) -> AndroidNotificationInternal {
+    pub(crate) fn finalize(&self) -> AndroidNotificationInternal<'_> {
         AndroidNotificationInternal {
             title: self.title.as_deref(),
             body: self.body.as_deref(),
@@ -232,3 +234,17 @@ impl AndroidNotification {
         }
     }
 }
+
+impl<'m> AndroidNotificationInternal<'m> {
+    /// Fill the localization keys that are not set explicitly from the common notification.
+    pub(crate) fn apply_localized(&mut self, title: Option<&'m Localized>, body: Option<&'m Localized>) {
+        if let (None, Some(title)) = (self.title_loc_key, title) {
+            self.title_loc_key = Some(&title.key);
+            self.title_loc_args = Some(&title.args).filter(|args| !args.is_empty()).map(|args| &args[..]);
+        }
+        if let (None, Some(body)) = (self.body_loc_key, body) {
+            self.body_loc_key = Some(&body.key);
+            self.body_loc_args = Some(&body.args).filter(|args| !args.is_empty()).map(|args| &args[..]);
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Debug)]
 /// https://firebase.google.com/docs/reference/fcm/rest/v1/projects.messages?authuser=0#Color
@@ -16,7 +16,7 @@ pub(crate) struct ColorInternal {
     alpha: f32,
 }
 
-#[derive(Debug, Default, Serialize)]
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
 /// https://firebase.google.com/docs/reference/fcm/rest/v1/projects.messages?authuser=0#Color
 pub struct Color {
     /// The amount of red in the color as a value in the interval [0, 1].
@@ -1,4 +1,4 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 use super::color::{Color, ColorInternal};
 
@@ -17,7 +17,7 @@ pub(crate) struct LightSettingsInternal<'m> {
     light_off_duration: &'m str,
 }
 
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
 /// https://firebase.google.com/docs/reference/fcm/rest/v1/projects.messages?authuser=0#LightSettings
 pub struct LightSettings {
     /// Set color of the LED with google.type.Color.
@@ -33,7 +33,7 @@ pub struct LightSettings {
 }
 
 impl LightSettings {
-    pub(crate) fn finalize(&self) -> LightSettingsInternal {
+    pub(crate) fn finalize(&self) -> LightSettingsInternal<'_> {
         LightSettingsInternal {
             color: self.color.finalize(),
             light_on_duration: &self.light_on_duration,
@@ -1,7 +1,7 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 #[allow(dead_code)]
-#[derive(Clone, Copy, Serialize, Debug)]
+#[derive(Clone, Copy, Serialize, Debug, PartialEq, Deserialize)]
 #[serde(rename_all = "UPPERCASE")]
 /// https://firebase.google.com/docs/reference/fcm/rest/v1/projects.messages?authuser=0#visibility
 pub enum Visibility {
@@ -1,9 +1,14 @@
-use serde::Serialize;
-use serde_json::Value;
+use std::borrow::Cow;
+
+use serde::ser::Error as _;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::notification::Localized;
 
 use super::apns_fcm_options::{ApnsFcmOptions, ApnsFcmOptionsInternal};
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, Default)]
 /// https://firebase.google.com/docs/reference/fcm/rest/v1/projects.messages?authuser=0#apnsconfig
 pub(crate) struct ApnsConfigInternal<'m> {
     /// HTTP request headers defined in Apple Push Notification Service.
@@ -12,14 +17,14 @@ pub(crate) struct ApnsConfigInternal<'m> {
 
     /// APNs payload as a JSON object, including both aps dictionary and custom payload.
     #[serde(skip_serializing_if = "Option::is_none")]
-    payload: Option<&'m Value>,
+    payload: Option<Cow<'m, Value>>,
 
     /// Options for features provided by the FCM SDK for iOS.
     #[serde(skip_serializing_if = "Option::is_none")]
     fcm_options: Option<ApnsFcmOptionsInternal<'m>>,
 }
 
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
 /// https://firebase.google.com/docs/reference/fcm/rest/v1/projects.messages?authuser=0#apnsconfig
 pub struct ApnsConfig {
     /// HTTP request headers defined in Apple Push Notification Service.
@@ -31,11 +36,56 @@ pub struct ApnsConfig {
 }
 
 impl ApnsConfig {
-    pub(crate) fn finalize(&self) -> ApnsConfigInternal {
+    pub(crate) fn finalize(&self) -> ApnsConfigInternal<'_> {
         ApnsConfigInternal {
             headers: self.headers.as_ref(),
-            payload: self.payload.as_ref(),
+            payload: self.payload.as_ref().map(Cow::Borrowed),
             fcm_options: self.fcm_options.as_ref().map(|fcm_options| fcm_options.finalize()),
         }
     }
 }
+
+impl<'m> ApnsConfigInternal<'m> {
+    /// Fill the `aps.alert` localization keys that are not set explicitly from the common notification.
+    /// Fails if the payload, `aps` or `alert` is not a JSON object the keys can be added to.
+    pub(crate) fn apply_localized(
+        &mut self,
+        title: Option<&'m Localized>,
+        body: Option<&'m Localized>,
+    ) -> serde_json::Result<()> {
+        let not_an_object = |field| serde_json::Error::custom(format!("cannot localize APNs {field}: not an object"));
+
+        let payload = self.payload.get_or_insert_with(|| Cow::Owned(json!({}))).to_mut();
+        let aps = match payload.as_object_mut() {
+            Some(payload) => payload.entry("aps").or_insert_with(|| json!({})),
+            None => return Err(not_an_object("payload")),
+        };
+        let alert = match aps.as_object_mut() {
+            Some(aps) => aps.entry("alert").or_insert_with(|| json!({})),
+            None => return Err(not_an_object("payload.aps")),
+        };
+        if let Value::String(body) = alert {
+            *alert = json!({ "body": body });
+        }
+        let alert = match alert.as_object_mut() {
+            Some(alert) => alert,
+            None => return Err(not_an_object("payload.aps.alert")),
+        };
+
+        for (localized, key_field, args_field) in [
+            (title, "title-loc-key", "title-loc-args"),
+            (body, "loc-key", "loc-args"),
+        ] {
+            if let Some(localized) = localized {
+                if !alert.contains_key(key_field) {
+                    alert.insert(key_field.to_string(), json!(localized.key));
+                    if !localized.args.is_empty() {
+                        alert.insert(args_field.to_string(), json!(localized.args));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
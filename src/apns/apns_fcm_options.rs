@@ -1,4 +1,4 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Debug)]
 /// https://firebase.google.com/docs/reference/fcm/rest/v1/projects.messages?authuser=0#apnsfcmoptions
@@ -10,7 +10,7 @@ pub(crate) struct ApnsFcmOptionsInternal<'m> {
     image: Option<&'m str>,
 }
 
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
 /// https://firebase.google.com/docs/reference/fcm/rest/v1/projects.messages?authuser=0#apnsfcmoptions
 pub struct ApnsFcmOptions {
     /// Label associated with the message's analytics data.
@@ -21,7 +21,7 @@ pub struct ApnsFcmOptions {
 }
 
 impl ApnsFcmOptions {
-    pub(crate) fn finalize(&self) -> ApnsFcmOptionsInternal {
+    pub(crate) fn finalize(&self) -> ApnsFcmOptionsInternal<'_> {
         ApnsFcmOptionsInternal {
             analytics_label: self.analytics_label.as_deref(),
             image: self.image.as_deref(),
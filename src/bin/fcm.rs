@@ -0,0 +1,480 @@
+//! Command line tool to send and validate FCM messages and manage topic subscriptions.
+//!
+//! Build with `cargo install fcm --features cli`. Results are written to stdout as JSON, one
+//! object per line.
+
+use std::io::{BufRead, BufReader, Read};
+use std::process::ExitCode;
+
+use clap::{Args, Parser, Subcommand};
+use fcm::bulk::{read_rows, BulkSend, Format, Template};
+use fcm::{Client, Message, Notification, Target};
+use serde_json::{json, Value};
+
+#[derive(Parser)]
+#[command(name = "fcm", version, about = "Send and validate Firebase Cloud Messaging messages")]
+struct Cli {
+    /// Path of the service account key file.
+    #[arg(long, global = true, env = "GOOGLE_APPLICATION_CREDENTIALS")]
+    credentials: Option<String>,
+
+    /// Base URL of the FCM API.
+    #[arg(long, global = true)]
+    endpoint: Option<String>,
+
+    /// Base URL of the instance id API.
+    #[arg(long, global = true)]
+    iid_endpoint: Option<String>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Send a message.
+    Send(MessageArgs),
+
+    /// Validate a message without delivering it.
+    Validate(MessageArgs),
+
+    /// Subscribe registration tokens to a topic.
+    Subscribe(TopicArgs),
+
+    /// Unsubscribe registration tokens from a topic.
+    Unsubscribe(TopicArgs),
+
+    /// Show the app, platform and topics of a registration token.
+    TokenInfo {
+        /// The registration token.
+        token: String,
+    },
+
+    /// Send every message of a newline delimited JSON file, `-` for stdin.
+    Batch {
+        /// The file with one message per line.
+        file: String,
+
+        /// Only validate the messages.
+        #[arg(long)]
+        validate: bool,
+    },
+
+    /// Send a personalized message per row of a CSV or NDJSON file, resuming after the last row
+    /// in the report.
+    Bulk(BulkArgs),
+}
+
+#[derive(Args)]
+struct BulkArgs {
+    /// JSON file with the message template, with `{column}` placeholders and `{{`/`}}` for literal braces.
+    #[arg(long)]
+    template: String,
+
+    /// CSV or NDJSON file with one row per message, `-` for stdin.
+    rows: String,
+
+    /// Format of the rows, guessed from the file extension by default.
+    #[arg(long, value_parser = ["csv", "ndjson"])]
+    format: Option<String>,
+
+    /// NDJSON file the outcome of each row is appended to.
+    #[arg(long)]
+    report: String,
+
+    /// Messages sent at a time.
+    #[arg(long, default_value_t = 10)]
+    concurrency: usize,
+
+    /// Only validate the messages.
+    #[arg(long)]
+    validate: bool,
+}
+
+#[derive(Args)]
+struct MessageArgs {
+    /// JSON file with the message, in the format of the FCM v1 API.
+    #[arg(long)]
+    message: Option<String>,
+
+    /// Registration token to send to.
+    #[arg(long, conflicts_with_all = ["topic", "condition"])]
+    token: Option<String>,
+
+    /// Topic to send to.
+    #[arg(long, conflicts_with = "condition")]
+    topic: Option<String>,
+
+    /// Condition to send to, e.g. `'news' in topics`.
+    #[arg(long)]
+    condition: Option<String>,
+
+    /// Title of the notification.
+    #[arg(long)]
+    title: Option<String>,
+
+    /// Body of the notification.
+    #[arg(long)]
+    body: Option<String>,
+
+    /// Image URL of the notification.
+    #[arg(long)]
+    image: Option<String>,
+
+    /// Data entry as `key=value`, may be repeated.
+    #[arg(long = "data", value_parser = parse_key_value)]
+    data: Vec<(String, String)>,
+}
+
+#[derive(Args)]
+struct TopicArgs {
+    /// The topic.
+    #[arg(long)]
+    topic: String,
+
+    /// The registration tokens.
+    #[arg(required = true)]
+    tokens: Vec<String>,
+}
+
+fn parse_key_value(entry: &str) -> Result<(String, String), String> {
+    entry
+        .split_once('=')
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .ok_or_else(|| format!("expected key=value, got {entry:?}"))
+}
+
+/// Read a message in the format of the FCM v1 API, optionally wrapped in `{"message": ...}`. Fields
+/// the message types do not know are rejected rather than silently dropped.
+fn parse_message(mut value: Value) -> Result<Message, String> {
+    if let Some(message) = value.get_mut("message") {
+        value = message.take();
+    }
+
+    if let Value::Object(fields) = &mut value {
+        let targets: Vec<&str> = ["target", "token", "topic", "condition"]
+            .iter()
+            .copied()
+            .filter(|kind| fields.contains_key(*kind))
+            .collect();
+        if targets.len() > 1 {
+            return Err(format!(
+                "invalid message: only one target allowed, got {}",
+                targets.join(", ")
+            ));
+        }
+        for kind in ["token", "topic", "condition"] {
+            if let Some(target) = fields.remove(kind) {
+                fields.insert("target".to_string(), json!({ kind: target }));
+            }
+        }
+    }
+
+    let message: Message = serde_json::from_value(value.clone()).map_err(|err| format!("invalid message: {err}"))?;
+
+    let parsed = serde_json::to_value(&message).map_err(|err| format!("invalid message: {err}"))?;
+    let mut unknown = Vec::new();
+    unknown_fields(&value, &parsed, "", &mut unknown);
+    if !unknown.is_empty() {
+        return Err(format!("invalid message: unknown field {}", unknown.join(", ")));
+    }
+
+    Ok(message)
+}
+
+/// Collect the paths of the fields of `input` that are missing from `parsed`, its round trip
+/// through the message types.
+fn unknown_fields(input: &Value, parsed: &Value, path: &str, unknown: &mut Vec<String>) {
+    if let (Value::Object(input), Value::Object(parsed)) = (input, parsed) {
+        for (key, value) in input {
+            let field = if path.is_empty() {
+                key.clone()
+            } else {
+                format!("{path}.{key}")
+            };
+            match parsed.get(key) {
+                Some(parsed) => unknown_fields(value, parsed, &field, unknown),
+                None if value.is_null() => {}
+                None => unknown.push(field),
+            }
+        }
+    }
+}
+
+impl MessageArgs {
+    /// The target given on the command line, if any.
+    fn target(&self) -> Option<Target> {
+        match (&self.token, &self.topic, &self.condition) {
+            (Some(token), _, _) => Some(Target::Token(token.clone())),
+            (_, Some(topic), _) => Some(Target::Topic(topic.clone())),
+            (_, _, Some(condition)) => Some(Target::Condition(condition.clone())),
+            _ => None,
+        }
+    }
+
+    fn into_message(self) -> Result<Message, String> {
+        let target = self.target();
+        let mut value = match &self.message {
+            Some(path) => {
+                let content = std::fs::read_to_string(path).map_err(|err| format!("{path}: {err}"))?;
+                serde_json::from_str(&content).map_err(|err| format!("{path}: {err}"))?
+            }
+            None if target.is_some() => json!({}),
+            None => return Err("one of --token, --topic, --condition or --message is required".to_string()),
+        };
+
+        // a target on the command line replaces the one in the file
+        if let (Some(target), Value::Object(fields)) = (&target, &mut value) {
+            let fields = match fields.get_mut("message") {
+                Some(Value::Object(message)) => message,
+                _ => fields,
+            };
+            for kind in ["token", "topic", "condition"] {
+                fields.remove(kind);
+            }
+            fields.insert("target".to_string(), json!(target));
+        }
+
+        let mut message = parse_message(value)?;
+
+        if self.title.is_some() || self.body.is_some() || self.image.is_some() {
+            let notification = message.notification.get_or_insert_with(Notification::default);
+            notification.title = self.title.or(notification.title.take());
+            notification.body = self.body.or(notification.body.take());
+            notification.image = self.image.or(notification.image.take());
+        }
+
+        if !self.data.is_empty() {
+            let data = message.data.get_or_insert_with(|| json!({}));
+            for (key, value) in self.data {
+                data[key] = Value::String(value);
+            }
+        }
+
+        Ok(message)
+    }
+}
+
+fn result_json(result: &Result<fcm::FcmResponse, fcm::Error>) -> Value {
+    match result {
+        Ok(response) => json!({ "name": response.name }),
+        Err(error) => json!({ "error": error.kind(), "message": error.to_string() }),
+    }
+}
+
+async fn client(cli: &Cli) -> Result<Client, fcm::Error> {
+    let mut builder = Client::builder();
+    if let Some(credentials) = &cli.credentials {
+        builder = builder.service_account_key_path(credentials);
+    }
+    if let Some(endpoint) = &cli.endpoint {
+        builder = builder.fcm_endpoint(endpoint);
+    }
+    if let Some(iid_endpoint) = &cli.iid_endpoint {
+        builder = builder.iid_endpoint(iid_endpoint);
+    }
+    builder.build().await
+}
+
+fn read_lines(file: &str) -> Result<Vec<String>, String> {
+    let reader: Box<dyn BufRead> = if file == "-" {
+        Box::new(BufReader::new(std::io::stdin()))
+    } else {
+        Box::new(BufReader::new(
+            std::fs::File::open(file).map_err(|err| format!("{file}: {err}"))?,
+        ))
+    };
+
+    reader
+        .lines()
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| format!("{file}: {err}"))
+}
+
+async fn run(cli: Cli) -> Result<bool, String> {
+    let client = client(&cli).await.map_err(|err| err.to_string())?;
+
+    match cli.command {
+        Command::Send(args) => {
+            let result = client.send(&args.into_message()?).await;
+            println!("{}", result_json(&result));
+            Ok(result.is_ok())
+        }
+        Command::Validate(args) => {
+            let result = client.validate(&args.into_message()?).await;
+            println!("{}", result_json(&result));
+            Ok(result.is_ok())
+        }
+        Command::Subscribe(TopicArgs { topic, tokens }) => topic_result(client.subscribe(&topic, &tokens).await),
+        Command::Unsubscribe(TopicArgs { topic, tokens }) => topic_result(client.unsubscribe(&topic, &tokens).await),
+        Command::TokenInfo { token } => match client.token_info(&token).await {
+            Ok(info) => {
+                let output = json!({
+                    "application": info.application,
+                    "application_version": info.application_version,
+                    "authorized_entity": info.authorized_entity,
+                    "platform": info.platform,
+                    "topics": info.topics,
+                });
+                println!("{output}");
+                Ok(true)
+            }
+            Err(error) => {
+                println!("{}", result_json(&Err(error)));
+                Ok(false)
+            }
+        },
+        Command::Batch { file, validate } => {
+            let mut all_ok = true;
+            let mut messages = Vec::new();
+            for (index, line) in read_lines(&file)?.into_iter().enumerate() {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let message = serde_json::from_str(&line)
+                    .map_err(|err| format!("invalid JSON: {err}"))
+                    .and_then(parse_message);
+                match message {
+                    Ok(message) => messages.push((index + 1, message)),
+                    Err(error) => {
+                        all_ok = false;
+                        println!(
+                            "{}",
+                            json!({ "line": index + 1, "error": "INVALID_INPUT", "message": error })
+                        );
+                    }
+                }
+            }
+
+            let results = if validate {
+                let mut results = Vec::with_capacity(messages.len());
+                for (_, message) in &messages {
+                    results.push(client.validate(message).await);
+                }
+                results
+            } else {
+                let batch: Vec<Message> = messages.iter().map(|(_, message)| message.clone()).collect();
+                client.send_all(&batch).await
+            };
+
+            for ((line, _), result) in messages.iter().zip(results.iter()) {
+                let mut output = result_json(result);
+                output["line"] = json!(line);
+                println!("{output}");
+                all_ok &= result.is_ok();
+            }
+            Ok(all_ok)
+        }
+        Command::Bulk(args) => {
+            let content = std::fs::read_to_string(&args.template).map_err(|err| format!("{}: {err}", args.template))?;
+            let value = serde_json::from_str(&content).map_err(|err| format!("{}: {err}", args.template))?;
+            let template = Template::new(&parse_message(value)?).map_err(|err| err.to_string())?;
+
+            let format = match args.format.as_deref() {
+                Some("csv") => Format::Csv,
+                Some(_) => Format::Ndjson,
+                None => Format::from_path(&args.rows).ok_or("cannot tell the format of the rows, use --format")?,
+            };
+            let reader: Box<dyn Read> = if args.rows == "-" {
+                Box::new(std::io::stdin())
+            } else {
+                Box::new(std::fs::File::open(&args.rows).map_err(|err| format!("{}: {err}", args.rows))?)
+            };
+            let rows = read_rows(reader, format).map_err(|err| err.to_string())?;
+
+            let report = BulkSend::new(template)
+                .concurrency(args.concurrency)
+                .validate_only(args.validate)
+                .run(&client, rows, &args.report)
+                .await
+                .map_err(|err| err.to_string())?;
+            let output = json!({
+                "sent": report.sent,
+                "failed": report.failed,
+                "skipped": report.skipped,
+                "invalid_tokens": report.invalid_tokens,
+            });
+            println!("{output}");
+            Ok(report.failed == 0)
+        }
+    }
+}
+
+fn topic_result(result: Result<fcm::TopicManagementResponse, fcm::Error>) -> Result<bool, String> {
+    match result {
+        Ok(response) => {
+            let errors: Vec<Value> = response
+                .errors
+                .iter()
+                .map(|error| json!({ "index": error.index, "token": error.token, "reason": error.reason }))
+                .collect();
+            println!(
+                "{}",
+                json!({ "success_count": response.success_count, "errors": errors })
+            );
+            Ok(response.errors.is_empty())
+        }
+        Err(error) => {
+            println!("{}", result_json(&Err(error)));
+            Ok(false)
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    match run(Cli::parse()).await {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(error) => {
+            eprintln!("fcm: {error}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_parse_messages_in_the_api_format() {
+        let wrapped = json!({ "message": { "topic": "news", "data": { "key": "value" } } });
+        let message = parse_message(wrapped).unwrap();
+        assert_eq!(Target::Topic("news".to_string()), message.target);
+        assert_eq!(Some(json!({ "key": "value" })), message.data);
+
+        let plain = json!({ "token": "abc", "notification": { "title": "Hello" } });
+        let message = parse_message(plain).unwrap();
+        assert_eq!(Target::Token("abc".to_string()), message.target);
+        assert_eq!(Some("Hello".to_string()), message.notification.unwrap().title);
+
+        assert!(parse_message(json!({ "data": {} })).is_err());
+        assert_eq!(
+            Err("invalid message: only one target allowed, got token, topic".to_string()),
+            parse_message(json!({ "token": "abc", "topic": "news" })).map(|_| ())
+        );
+        assert!(parse_message(json!({ "target": { "topic": "news" }, "condition": "'a' in topics" })).is_err());
+    }
+
+    #[test]
+    fn should_reject_unknown_fields() {
+        assert_eq!(
+            Err("invalid message: unknown field notification.titel".to_string()),
+            parse_message(json!({ "topic": "news", "notification": { "titel": "Hello" } })).map(|_| ())
+        );
+        assert_eq!(
+            Err("invalid message: unknown field android.ttl_seconds".to_string()),
+            parse_message(json!({ "message": { "token": "abc", "android": { "ttl_seconds": 60 } } })).map(|_| ())
+        );
+        assert!(parse_message(json!({ "token": "abc", "priority": "high" })).is_err());
+
+        let message = parse_message(json!({
+            "token": "abc",
+            "data": { "anything": "goes" },
+            "android": { "priority": "HIGH", "notification": { "title": "Hi" } },
+            "apns": { "headers": { "apns-priority": "10" }, "payload": { "aps": { "badge": 1 } } },
+        }));
+        assert!(message.is_ok());
+    }
+}
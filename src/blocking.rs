@@ -0,0 +1,170 @@
+//! A synchronous client, available with the `blocking` feature.
+//!
+//! [`Client`] wraps the asynchronous [`crate::Client`] and drives it on an internal single threaded
+//! runtime, so it shares message types, authentication, middleware and error handling with it. The
+//! caller does not need a runtime of its own; calling the blocking client from within an
+//! asynchronous context panics, like any nested runtime.
+//!
+//! ```rust,no_run
+//! # fn run() -> Result<(), fcm::Error> {
+//! use fcm::{Message, Target};
+//!
+//! let client = fcm::blocking::Client::new()?;
+//! let response = client.send(&Message {
+//!     data: None,
+//!     notification: None,
+//!     target: Target::Topic("news".to_string()),
+//!     android: None,
+//!     webpush: None,
+//!     apns: None,
+//!     fcm_options: None,
+//! })?;
+//! println!("Sent: {:?}", response.name);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt;
+
+use tokio::runtime::Runtime;
+
+use crate::client::circuit_breaker::CircuitState;
+use crate::client::rate_limit::RateLimiterStats;
+use crate::client::response::{FcmError, FcmResponse};
+use crate::client::topics::{TokenInfo, TopicManagementResponse};
+use crate::client::ClientBuilder;
+use crate::message::Message;
+use crate::token_store::TokenStoreError;
+
+/// A synchronous FCM client, see the [module documentation](self).
+pub struct Client {
+    inner: crate::Client,
+    runtime: Runtime,
+}
+
+impl fmt::Debug for Client {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Client")
+            .field("project_id", &self.inner.project_id())
+            .finish()
+    }
+}
+
+impl Client {
+    /// Get a new instance of Client, configured from the `GOOGLE_APPLICATION_CREDENTIALS` environment variable.
+    pub fn new() -> Result<Client, FcmError> {
+        Client::from_builder(ClientBuilder::new())
+    }
+
+    /// Build a client configured by `builder`, see [`crate::Client::builder`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the internal runtime cannot be started, or if called from within an asynchronous
+    /// context.
+    pub fn from_builder(builder: ClientBuilder) -> Result<Client, FcmError> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("failed to start the runtime of the blocking client");
+        let inner = runtime.block_on(builder.build())?;
+
+        Ok(Client { inner, runtime })
+    }
+
+    /// The id of the Firebase project messages are sent to.
+    pub fn project_id(&self) -> &str {
+        self.inner.project_id()
+    }
+
+    /// How much sending was slowed down by the rate limiter, if one is configured.
+    pub fn rate_limiter_stats(&self) -> Option<RateLimiterStats> {
+        self.inner.rate_limiter_stats()
+    }
+
+    /// State of the circuit breaker, if one is configured.
+    pub fn circuit_state(&self) -> Option<CircuitState> {
+        self.inner.circuit_state()
+    }
+
+    /// Send a message through the FCM API.
+    pub fn send(&self, message: &Message) -> Result<FcmResponse, FcmError> {
+        self.runtime.block_on(self.inner.send(message))
+    }
+
+    /// Validate a message against the FCM API without delivering it to any device.
+    pub fn validate(&self, message: &Message) -> Result<FcmResponse, FcmError> {
+        self.runtime.block_on(self.inner.validate(message))
+    }
+
+    /// Send a message at most once per idempotency `key`, see [`crate::Client::send_idempotent`].
+    pub fn send_idempotent(&self, key: &str, message: &Message) -> Result<FcmResponse, FcmError> {
+        self.runtime.block_on(self.inner.send_idempotent(key, message))
+    }
+
+    /// Send `message` to each of `tokens`, returning the results in the order of the tokens.
+    pub fn send_multicast(&self, message: &Message, tokens: &[String]) -> Vec<Result<FcmResponse, FcmError>> {
+        self.runtime.block_on(self.inner.send_multicast(message, tokens))
+    }
+
+    /// Send `message` to every token of `user_id` that is not stale, see
+    /// [`crate::Client::send_to_user`].
+    #[allow(clippy::type_complexity)]
+    pub fn send_to_user(
+        &self,
+        user_id: &str,
+        message: &Message,
+    ) -> Result<Vec<(String, Result<FcmResponse, FcmError>)>, TokenStoreError> {
+        self.runtime.block_on(self.inner.send_to_user(user_id, message))
+    }
+
+    /// Send every message, returning the results in the same order.
+    pub fn send_all(&self, messages: &[Message]) -> Vec<Result<FcmResponse, FcmError>> {
+        self.runtime.block_on(self.inner.send_all(messages))
+    }
+
+    /// Subscribe up to 1000 registration tokens to `topic`.
+    pub fn subscribe(&self, topic: &str, tokens: &[String]) -> Result<TopicManagementResponse, FcmError> {
+        self.runtime.block_on(self.inner.subscribe(topic, tokens))
+    }
+
+    /// Unsubscribe up to 1000 registration tokens from `topic`.
+    pub fn unsubscribe(&self, topic: &str, tokens: &[String]) -> Result<TopicManagementResponse, FcmError> {
+        self.runtime.block_on(self.inner.unsubscribe(topic, tokens))
+    }
+
+    /// Look up the app, platform and topic subscriptions of a registration token.
+    pub fn token_info(&self, token: &str) -> Result<TokenInfo, FcmError> {
+        self.runtime.block_on(self.inner.token_info(token))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{message, MockResponse, MockServer, PROJECT_ID};
+    use crate::{Error, ErrorCode};
+
+    #[test]
+    fn should_send_with_the_blocking_client() {
+        let server = MockServer::start();
+        server.enqueue(MockResponse::unregistered());
+        let builder = crate::Client::builder()
+            .service_account_key_path(&server.key_path().to_string_lossy())
+            .fcm_endpoint(server.url());
+        let client = super::Client::from_builder(builder).unwrap();
+
+        assert_eq!(
+            Err(Error::Rejected(
+                ErrorCode::Unregistered,
+                "Requested entity was not found.".to_string()
+            )),
+            client.send(&message("gone"))
+        );
+        let response = client.validate(&message("token")).unwrap();
+
+        assert_eq!(PROJECT_ID, client.project_id());
+        assert!(response.name.is_some());
+        assert_eq!(2, server.messages().len());
+        assert!(server.messages()[1].validate_only);
+    }
+}
@@ -0,0 +1,446 @@
+//! Sending a personalized message per row of a CSV or NDJSON file, available with the `bulk`
+//! feature.
+//!
+//! A [`Template`] is a [`Message`] whose strings contain `{variable}` placeholders, or a
+//! [`MessageTemplate`] with a [`Catalog`], rendered with the columns of each [`Row`].
+//! [`BulkSend::run`] sends the rendered messages with bounded concurrency and appends one
+//! [`RowOutcome`] per row to a report file in NDJSON, in row order. Running it again with the same
+//! report skips the rows already in it, so an interrupted campaign resumes after the last processed
+//! row.
+//!
+//! Delivery is at least once on resume: the outcomes are written in row order, so the rows in flight
+//! when the campaign was interrupted, up to `concurrency` of them, and any row after a row still
+//! waiting for its answer are not in the report yet and are sent again. Their notifications may be
+//! shown twice unless the template sets a collapse key or notification tag.
+//!
+//! ```rust,no_run
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! use fcm::bulk::{read_rows, BulkSend, Format, Template};
+//! use fcm::{Message, Notification, Target};
+//!
+//! let client = fcm::Client::new().await?;
+//! let template = Template::new(&Message {
+//!     data: None,
+//!     notification: Some(Notification {
+//!         title: Some("Hello {name}".to_string()),
+//!         ..Default::default()
+//!     }),
+//!     target: Target::Token("{token}".to_string()),
+//!     android: None,
+//!     webpush: None,
+//!     apns: None,
+//!     fcm_options: None,
+//! })?;
+//!
+//! let rows = read_rows(std::fs::File::open("users.csv")?, Format::Csv)?;
+//! let report = BulkSend::new(template).concurrency(20).run(&client, rows, "report.ndjson").await?;
+//! println!("{} sent, {} failed, {} invalid tokens", report.sent, report.failed, report.invalid_tokens.len());
+//! # Ok(())
+//! # }
+//! ```
+
+#[cfg(test)]
+mod tests;
+
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::Path;
+
+use futures_util::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::client::response::{FcmError, FcmResponse};
+use crate::client::sender::Sender;
+use crate::message::target::Target;
+use crate::message::Message;
+use crate::template::catalog::Catalog;
+use crate::template::{interpolate, MessageTemplate, TemplateError};
+
+/// Error kind recorded for rows the template could not be rendered with.
+const TEMPLATE_ERROR: &str = "TEMPLATE";
+
+/// Errors reading rows, rendering templates or writing the report.
+#[derive(Debug, PartialEq)]
+pub enum BulkError {
+    /// Reading the input or writing the report failed.
+    Io(String),
+
+    /// A row of the input is malformed.
+    Input { row: usize, error: String },
+
+    /// The template is invalid, or a row lacks one of its variables.
+    Template(String),
+}
+
+impl Error for BulkError {}
+
+impl fmt::Display for BulkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BulkError::Io(error) => write!(f, "bulk send i/o failed: {error}"),
+            BulkError::Input { row, error } => write!(f, "row {row} is invalid: {error}"),
+            BulkError::Template(error) => write!(f, "template error: {error}"),
+        }
+    }
+}
+
+impl From<TemplateError> for BulkError {
+    fn from(error: TemplateError) -> BulkError {
+        BulkError::Template(error.to_string())
+    }
+}
+
+impl From<std::io::Error> for BulkError {
+    fn from(error: std::io::Error) -> BulkError {
+        BulkError::Io(error.to_string())
+    }
+}
+
+/// Format of the input rows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// Comma separated values, the first line naming the variables.
+    Csv,
+
+    /// One JSON object per line, its fields being the variables.
+    Ndjson,
+}
+
+impl Format {
+    /// Guess the format from the extension of `path`: `.csv`, or `.ndjson`/`.jsonl`.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Format> {
+        match path.as_ref().extension()?.to_str()?.to_lowercase().as_str() {
+            "csv" => Some(Format::Csv),
+            "ndjson" | "jsonl" => Some(Format::Ndjson),
+            _ => None,
+        }
+    }
+}
+
+/// The variables of one input row.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Row {
+    /// Position of the row in the input, starting at 1 and not counting the CSV header.
+    pub number: usize,
+
+    /// Values by variable name.
+    pub variables: BTreeMap<String, String>,
+}
+
+/// Read all rows from `reader`.
+pub fn read_rows<R: Read>(reader: R, format: Format) -> Result<Vec<Row>, BulkError> {
+    match format {
+        Format::Csv => read_csv(reader),
+        Format::Ndjson => read_ndjson(reader),
+    }
+}
+
+fn read_csv<R: Read>(reader: R) -> Result<Vec<Row>, BulkError> {
+    let mut reader = csv::Reader::from_reader(reader);
+    let headers = reader.headers().map_err(|err| BulkError::Io(err.to_string()))?.clone();
+
+    let mut rows = Vec::new();
+    for (index, record) in reader.records().enumerate() {
+        let record = record.map_err(|err| BulkError::Input {
+            row: index + 1,
+            error: err.to_string(),
+        })?;
+        rows.push(Row {
+            number: index + 1,
+            variables: headers
+                .iter()
+                .zip(record.iter())
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        });
+    }
+    Ok(rows)
+}
+
+fn read_ndjson<R: Read>(reader: R) -> Result<Vec<Row>, BulkError> {
+    let mut rows = Vec::new();
+    for (index, line) in BufReader::new(reader).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let invalid = |error: String| BulkError::Input { row: index + 1, error };
+        let fields = match serde_json::from_str(&line).map_err(|err| invalid(err.to_string()))? {
+            Value::Object(fields) => fields,
+            _ => return Err(invalid("expected a JSON object".to_string())),
+        };
+        rows.push(Row {
+            number: index + 1,
+            variables: fields
+                .into_iter()
+                .map(|(name, value)| {
+                    let value = match value {
+                        Value::String(value) => value,
+                        Value::Null => String::new(),
+                        value => value.to_string(),
+                    };
+                    (name, value)
+                })
+                .collect(),
+        });
+    }
+    Ok(rows)
+}
+
+/// A message rendered per row, with `{variable}` placeholders as in [`interpolate`].
+#[derive(Clone, Debug)]
+pub struct Template {
+    kind: TemplateKind,
+}
+
+#[derive(Clone, Debug)]
+enum TemplateKind {
+    Message(Value),
+    Localized {
+        template: MessageTemplate,
+        catalog: Catalog,
+    },
+}
+
+impl Template {
+    /// Use `message` as the template, with placeholders in any of its strings, including the target.
+    pub fn new(message: &Message) -> Result<Template, BulkError> {
+        let message = serde_json::to_value(message).map_err(|err| BulkError::Template(err.to_string()))?;
+        Ok(Template {
+            kind: TemplateKind::Message(message),
+        })
+    }
+
+    /// Render `template` with `catalog` per row, sending to the `token` column in the language of the
+    /// `locale` column, if any. See [`MessageTemplate::render`].
+    pub fn localized(template: MessageTemplate, catalog: Catalog) -> Template {
+        Template {
+            kind: TemplateKind::Localized { template, catalog },
+        }
+    }
+
+    /// Replace the placeholders with the `variables`. Fails if one of them is missing.
+    pub fn render(&self, variables: &BTreeMap<String, String>) -> Result<Message, BulkError> {
+        let context: HashMap<String, String> = variables.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+
+        match &self.kind {
+            TemplateKind::Message(message) => {
+                let mut message = message.clone();
+                render_value(&mut message, &context)?;
+                serde_json::from_value(message).map_err(|err| BulkError::Template(err.to_string()))
+            }
+            TemplateKind::Localized { template, catalog } => {
+                let token = context
+                    .get("token")
+                    .ok_or_else(|| TemplateError::MissingVariable("token".to_string()))?;
+                let locale = context.get("locale").map(String::as_str).unwrap_or_default();
+                Ok(template.render(Target::Token(token.clone()), locale, &context, catalog)?)
+            }
+        }
+    }
+}
+
+fn render_value(value: &mut Value, context: &HashMap<String, String>) -> Result<(), TemplateError> {
+    match value {
+        Value::String(text) => *text = interpolate(text, context)?,
+        Value::Array(values) => {
+            for value in values {
+                render_value(value, context)?;
+            }
+        }
+        Value::Object(fields) => {
+            for value in fields.values_mut() {
+                render_value(value, context)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// The outcome of one row, a line of the report.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct RowOutcome {
+    /// The row number, see [`Row::number`].
+    pub row: usize,
+
+    /// The registration token the message was sent to, if it targets one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+
+    /// The message name assigned by FCM.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message_name: Option<String>,
+
+    /// The kind of error, see [`FcmError::kind`], or `TEMPLATE` if the row could not be rendered.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_kind: Option<String>,
+
+    /// The error message.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+
+    /// Whether FCM reported the token as invalid.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub token_invalid: bool,
+}
+
+impl RowOutcome {
+    fn new(row: usize, message: Option<&Message>, result: Result<FcmResponse, FcmError>) -> RowOutcome {
+        let token = match message.map(|message| &message.target) {
+            Some(Target::Token(token)) => Some(token.clone()),
+            _ => None,
+        };
+
+        match result {
+            Ok(response) => RowOutcome {
+                row,
+                token,
+                message_name: response.name,
+                ..Default::default()
+            },
+            Err(error) => RowOutcome {
+                row,
+                token_invalid: token.is_some() && error.token_invalid_reason().is_some(),
+                token,
+                error_kind: Some(error.kind().to_string()),
+                error: Some(error.to_string()),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+/// What a call to [`BulkSend::run`] did.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BulkReport {
+    /// Rows sent successfully.
+    pub sent: usize,
+
+    /// Rows that failed, including those the template could not be rendered with.
+    pub failed: usize,
+
+    /// Rows skipped because the report already contains them.
+    pub skipped: usize,
+
+    /// Tokens FCM reported as invalid, to be deleted.
+    pub invalid_tokens: Vec<String>,
+}
+
+/// Sends a rendered [`Template`] per row, see the [module documentation](self).
+#[derive(Clone, Debug)]
+pub struct BulkSend {
+    template: Template,
+    concurrency: usize,
+    validate_only: bool,
+}
+
+impl BulkSend {
+    /// Send `template`, 10 messages at a time.
+    pub fn new(template: Template) -> BulkSend {
+        BulkSend {
+            template,
+            concurrency: 10,
+            validate_only: false,
+        }
+    }
+
+    /// Send at most `concurrency` messages at a time.
+    pub fn concurrency(mut self, concurrency: usize) -> BulkSend {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Only validate the messages instead of delivering them.
+    pub fn validate_only(mut self, validate_only: bool) -> BulkSend {
+        self.validate_only = validate_only;
+        self
+    }
+
+    /// Send a message per row through `sender`, appending the outcomes to the report at
+    /// `report_path`. Rows up to the last one already in the report are skipped; rows that were
+    /// in flight when an earlier run stopped are sent again.
+    pub async fn run<P: AsRef<Path>>(
+        &self,
+        sender: &dyn Sender,
+        rows: Vec<Row>,
+        report_path: P,
+    ) -> Result<BulkReport, BulkError> {
+        let last_row = last_processed_row(report_path.as_ref())?;
+        let mut report_file = OpenOptions::new().create(true).append(true).open(report_path)?;
+
+        let mut report = BulkReport::default();
+        let (done, todo): (Vec<Row>, Vec<Row>) = rows
+            .into_iter()
+            .partition(|row| last_row.is_some_and(|last_row| row.number <= last_row));
+        report.skipped = done.len();
+
+        let mut outcomes = stream::iter(todo)
+            .map(|row| self.send_row(sender, row))
+            .buffered(self.concurrency);
+
+        while let Some(outcome) = outcomes.next().await {
+            let mut line = serde_json::to_string(&outcome).map_err(|err| BulkError::Io(err.to_string()))?;
+            line.push('\n');
+            report_file.write_all(line.as_bytes())?;
+            report_file.flush()?;
+
+            if outcome.error_kind.is_none() {
+                report.sent += 1;
+            } else {
+                report.failed += 1;
+            }
+            if outcome.token_invalid {
+                report.invalid_tokens.extend(outcome.token);
+            }
+        }
+
+        Ok(report)
+    }
+
+    async fn send_row(&self, sender: &dyn Sender, row: Row) -> RowOutcome {
+        let message = match self.template.render(&row.variables) {
+            Ok(message) => message,
+            Err(error) => {
+                return RowOutcome {
+                    row: row.number,
+                    error_kind: Some(TEMPLATE_ERROR.to_string()),
+                    error: Some(error.to_string()),
+                    ..Default::default()
+                }
+            }
+        };
+
+        let result = if self.validate_only {
+            sender.validate(&message).await
+        } else {
+            sender.send(&message).await
+        };
+        RowOutcome::new(row.number, Some(&message), result)
+    }
+}
+
+/// The number of the last row in the report at `path`, dropping a line left incomplete by a crash.
+fn last_processed_row(path: &Path) -> Result<Option<usize>, BulkError> {
+    let mut content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(error) => return Err(error.into()),
+    };
+
+    if !content.is_empty() && !content.ends_with('\n') {
+        content.truncate(content.rfind('\n').map_or(0, |end| end + 1));
+        File::create(path)?.write_all(content.as_bytes())?;
+    }
+
+    Ok(content
+        .lines()
+        .filter_map(|line| serde_json::from_str::<RowOutcome>(line).ok())
+        .map(|outcome| outcome.row)
+        .max())
+}
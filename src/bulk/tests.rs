@@ -0,0 +1,180 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use serde_json::json;
+
+use crate::bulk::{read_rows, BulkError, BulkReport, BulkSend, Format, Row, RowOutcome, Template};
+use crate::{
+    Catalog, Error, ErrorCode, FcmResponse, Message, MessageTemplate, Notification, RecordingSender, ScriptedSender,
+    Target,
+};
+
+fn template() -> Template {
+    Template::new(&Message {
+        data: Some(json!({ "coupon": "{coupon}", "braces": "{{literal}}" })),
+        notification: Some(Notification {
+            title: Some("Hello { name }!".to_string()),
+            ..Default::default()
+        }),
+        target: Target::Token("{token}".to_string()),
+        android: None,
+        webpush: None,
+        apns: None,
+        fcm_options: None,
+    })
+    .unwrap()
+}
+
+fn report_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("fcm-bulk-{}-{}.ndjson", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+fn read_report(path: &PathBuf) -> Vec<RowOutcome> {
+    std::fs::read_to_string(path)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+const CSV: &str = "token,name,coupon\na,Ann,X1\nb,\"Bob, Jr.\",X2\nc,Cid,X3\n";
+
+#[test]
+fn test_read_rows() {
+    let rows = read_rows(CSV.as_bytes(), Format::Csv).unwrap();
+    assert_eq!(3, rows.len());
+    assert_eq!(2, rows[1].number);
+    assert_eq!("Bob, Jr.", rows[1].variables["name"]);
+
+    let ndjson = "{\"token\": \"a\", \"points\": 3}\n\n{\"token\": \"b\", \"points\": null}\n";
+    let rows = read_rows(ndjson.as_bytes(), Format::Ndjson).unwrap();
+    assert_eq!(vec![1, 3], rows.iter().map(|row| row.number).collect::<Vec<_>>());
+    assert_eq!("3", rows[0].variables["points"]);
+    assert_eq!("", rows[1].variables["points"]);
+
+    assert!(matches!(
+        read_rows("[1]\n".as_bytes(), Format::Ndjson),
+        Err(BulkError::Input { row: 1, .. })
+    ));
+    assert_eq!(Some(Format::Ndjson), Format::from_path("rows.jsonl"));
+}
+
+#[test]
+fn test_render_template() {
+    let variables: BTreeMap<String, String> = [("token", "a"), ("name", "Ann"), ("coupon", "X1")]
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+
+    let message = template().render(&variables).unwrap();
+    assert_eq!(Target::Token("a".to_string()), message.target);
+    assert_eq!(Some("Hello Ann!".to_string()), message.notification.unwrap().title);
+    assert_eq!(Some(json!({ "coupon": "X1", "braces": "{literal}" })), message.data);
+
+    let mut incomplete = variables.clone();
+    incomplete.remove("coupon");
+    assert_eq!(
+        Err(BulkError::Template("missing template variable coupon".to_string())),
+        template().render(&incomplete)
+    );
+}
+
+#[test]
+fn test_render_localized_template() {
+    let mut catalog = Catalog::new();
+    catalog.insert("de", "welcome", "Willkommen, {name}!");
+    let template = Template::localized(
+        MessageTemplate {
+            title_key: Some("welcome".to_string()),
+            title_args: vec!["name".to_string()],
+            ..Default::default()
+        },
+        catalog,
+    );
+    let variables: BTreeMap<String, String> = [("token", "a"), ("name", "Ann"), ("locale", "de")]
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+
+    let message = template.render(&variables).unwrap();
+    assert_eq!(Target::Token("a".to_string()), message.target);
+    assert_eq!(
+        Some("Willkommen, Ann!".to_string()),
+        message.notification.unwrap().title
+    );
+
+    let mut anonymous = variables.clone();
+    anonymous.remove("token");
+    assert_eq!(
+        Err(BulkError::Template("missing template variable token".to_string())),
+        template.render(&anonymous)
+    );
+}
+
+#[tokio::test]
+async fn test_bulk_send_reports_outcomes() {
+    let path = report_path("outcomes");
+    let mut rows = read_rows(CSV.as_bytes(), Format::Csv).unwrap();
+    rows.push(Row {
+        number: 4,
+        variables: BTreeMap::new(),
+    });
+
+    let sender = ScriptedSender::new();
+    sender.push_ok(FcmResponse {
+        name: Some("projects/p/messages/1".to_string()),
+        ..Default::default()
+    });
+    sender.push_err(Error::Rejected(ErrorCode::Unregistered, "gone".to_string()));
+    sender.push_ok(FcmResponse::default());
+
+    let report = BulkSend::new(template())
+        .concurrency(2)
+        .run(&sender, rows, &path)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        BulkReport {
+            sent: 2,
+            failed: 2,
+            skipped: 0,
+            invalid_tokens: vec!["b".to_string()],
+        },
+        report
+    );
+
+    let outcomes = read_report(&path);
+    assert_eq!(
+        vec![1, 2, 3, 4],
+        outcomes.iter().map(|outcome| outcome.row).collect::<Vec<_>>()
+    );
+    assert_eq!(Some("projects/p/messages/1".to_string()), outcomes[0].message_name);
+    assert_eq!(Some("UNREGISTERED".to_string()), outcomes[1].error_kind);
+    assert!(outcomes[1].token_invalid);
+    assert_eq!(Some("TEMPLATE".to_string()), outcomes[3].error_kind);
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn test_bulk_send_resumes_after_last_row() {
+    let path = report_path("resume");
+    std::fs::write(&path, "{\"row\":1,\"token\":\"a\"}\n{\"row\":2,\"tok").unwrap();
+
+    let sender = RecordingSender::new();
+    let rows = read_rows(CSV.as_bytes(), Format::Csv).unwrap();
+    let report = BulkSend::new(template()).run(&sender, rows, &path).await.unwrap();
+
+    assert_eq!(1, report.skipped);
+    assert_eq!(2, report.sent);
+    assert_eq!(2, sender.messages().len());
+    assert_eq!(
+        vec![1, 2, 3],
+        read_report(&path).iter().map(|outcome| outcome.row).collect::<Vec<_>>()
+    );
+
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,346 @@
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::Mutex;
+
+use crate::client::circuit_breaker::{CircuitBreaker, CircuitBreakerConfig, CircuitState, CircuitStateCallback};
+use crate::client::classify::{TokenInvalidCallback, TokenInvalidReason};
+use crate::client::concurrency::AdaptiveConcurrency;
+use crate::client::idempotency::{IdempotencyStore, InMemoryIdempotencyStore};
+use crate::client::metrics::Metrics;
+use crate::client::middleware::Middleware;
+use crate::client::rate_limit::{RateLimit, RateLimiter};
+use crate::client::response::FcmError;
+use crate::client::service_account::ServiceAccount;
+use crate::client::transport::{HttpOptions, HttpTransport, ReqwestTransport};
+use crate::client::Client;
+use crate::token_store::TokenStore;
+
+const FCM_ENDPOINT: &str = "https://fcm.googleapis.com";
+const IID_ENDPOINT: &str = "https://iid.googleapis.com";
+const IDEMPOTENCY_WINDOW: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// A builder to configure a [`Client`].
+///
+/// ```no_run
+/// # async fn run() -> Result<(), fcm::Error> {
+/// let client = fcm::Client::builder()
+///     .service_account_key_path("/path/to/service-account.json")
+///     .build()
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Default)]
+pub struct ClientBuilder {
+    service_account_key_path: Option<String>,
+    fcm_endpoint: Option<String>,
+    iid_endpoint: Option<String>,
+    rate_limit: Option<RateLimit>,
+    concurrency: Option<AdaptiveConcurrency>,
+    circuit_breaker: Option<CircuitBreakerConfig>,
+    on_circuit_state_change: Option<CircuitStateCallback>,
+    metrics: Option<Arc<dyn Metrics>>,
+    middleware: Vec<Arc<dyn Middleware>>,
+    on_token_invalid: Option<TokenInvalidCallback>,
+    token_store: Option<Arc<dyn TokenStore>>,
+    idempotency_store: Option<Arc<dyn IdempotencyStore>>,
+    idempotency_window: Option<Duration>,
+    transport: Option<Arc<dyn HttpTransport>>,
+    http: HttpOptions,
+}
+
+impl ClientBuilder {
+    /// Get a new builder with the default configuration.
+    pub fn new() -> ClientBuilder {
+        ClientBuilder::default()
+    }
+
+    /// Path of the service account key file. Defaults to the `GOOGLE_APPLICATION_CREDENTIALS`
+    /// environment variable, which may also be set in a `.env` file.
+    pub fn service_account_key_path(mut self, path: &str) -> ClientBuilder {
+        self.service_account_key_path = Some(path.to_string());
+        self
+    }
+
+    /// Base URL of the FCM API. Defaults to `https://fcm.googleapis.com`.
+    pub fn fcm_endpoint(mut self, url: &str) -> ClientBuilder {
+        self.fcm_endpoint = Some(url.trim_end_matches('/').to_string());
+        self
+    }
+
+    /// Base URL of the instance id API used to manage topic subscriptions. Defaults to
+    /// `https://iid.googleapis.com`.
+    pub fn iid_endpoint(mut self, url: &str) -> ClientBuilder {
+        self.iid_endpoint = Some(url.trim_end_matches('/').to_string());
+        self
+    }
+
+    /// Smooth bursts locally with token buckets instead of running into FCM's quotas. Messages wait
+    /// until they fit into the global budget and the budget of their token or topic.
+    pub fn rate_limit(mut self, rate_limit: RateLimit) -> ClientBuilder {
+        self.rate_limit = Some(rate_limit);
+        self
+    }
+
+    /// Bounds of the adaptive concurrency of `Client::send_all`. Defaults to `AdaptiveConcurrency::default()`.
+    pub fn adaptive_concurrency(mut self, concurrency: AdaptiveConcurrency) -> ClientBuilder {
+        self.concurrency = Some(concurrency);
+        self
+    }
+
+    /// Fail fast with `FcmError::CircuitOpen` once too many requests end in server errors or
+    /// transport failures, probing FCM again after a while. Disabled by default.
+    pub fn circuit_breaker(mut self, config: CircuitBreakerConfig) -> ClientBuilder {
+        self.circuit_breaker = Some(config);
+        self
+    }
+
+    /// Call `callback` whenever the circuit breaker opens, half-opens or closes.
+    pub fn on_circuit_state_change<F>(mut self, callback: F) -> ClientBuilder
+    where
+        F: Fn(CircuitState) + Send + Sync + 'static,
+    {
+        self.on_circuit_state_change = Some(Arc::new(callback));
+        self
+    }
+
+    /// Report sends, latencies, payload sizes and token refreshes to `metrics`.
+    pub fn metrics<M: Metrics + 'static>(mut self, metrics: M) -> ClientBuilder {
+        self.metrics = Some(Arc::new(metrics));
+        self
+    }
+
+    /// Add a middleware seeing every message, request and response. Middlewares are called in the
+    /// order they were added, see [`Middleware`].
+    pub fn middleware<M: Middleware + 'static>(mut self, middleware: M) -> ClientBuilder {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    /// Call `callback` with the registration token and reason whenever FCM reports a token as
+    /// invalid, for every send including `send_all` and `send_multicast`, so dead tokens can be
+    /// deleted in one place.
+    pub fn on_token_invalid<F>(mut self, callback: F) -> ClientBuilder
+    where
+        F: Fn(&str, TokenInvalidReason) + Send + Sync + 'static,
+    {
+        self.on_token_invalid = Some(Arc::new(callback));
+        self
+    }
+
+    /// Remove registration tokens from `token_store` as soon as FCM reports them as invalid, and
+    /// enable `Client::send_to_user`.
+    pub fn token_store(mut self, token_store: Arc<dyn TokenStore>) -> ClientBuilder {
+        self.token_store = Some(token_store);
+        self
+    }
+
+    /// Where `Client::send_idempotent` records sent keys. Defaults to an `InMemoryIdempotencyStore`,
+    /// which only suppresses duplicates sent from the same process.
+    pub fn idempotency_store(mut self, store: Arc<dyn IdempotencyStore>) -> ClientBuilder {
+        self.idempotency_store = Some(store);
+        self
+    }
+
+    /// How long `Client::send_idempotent` suppresses duplicates of a key. Defaults to 24 hours.
+    pub fn idempotency_window(mut self, window: Duration) -> ClientBuilder {
+        self.idempotency_window = Some(window);
+        self
+    }
+
+    /// Send requests to FCM, the instance id API and the OAuth token endpoint through `transport`,
+    /// e.g. a custom connector or an in-memory transport in tests. Defaults to a [`ReqwestTransport`]
+    /// configured by the HTTP options below, which are ignored when a transport is given.
+    pub fn transport<T: HttpTransport + 'static>(mut self, transport: T) -> ClientBuilder {
+        self.transport = Some(Arc::new(transport));
+        self
+    }
+
+    /// Use a transport shared with other clients.
+    pub(crate) fn shared_transport(mut self, transport: Arc<dyn HttpTransport>) -> ClientBuilder {
+        self.transport = Some(transport);
+        self
+    }
+
+    /// Whether a transport or HTTP options are set.
+    pub(crate) fn has_transport_options(&self) -> bool {
+        self.transport.is_some() || !self.http.is_empty()
+    }
+
+    /// The HTTP options, for a transport shared with other clients.
+    pub(crate) fn into_http_options(self) -> HttpOptions {
+        self.http
+    }
+
+    /// Send requests to FCM, the instance id API and the OAuth token endpoint through the HTTP proxy
+    /// at `url`, e.g. `http://proxy.internal:3128`.
+    pub fn proxy(mut self, url: &str) -> ClientBuilder {
+        self.http.proxy = Some(url.to_string());
+        self
+    }
+
+    /// Authenticate to the proxy with basic authentication.
+    pub fn proxy_credentials(mut self, username: &str, password: &str) -> ClientBuilder {
+        self.http.proxy_credentials = Some((username.to_string(), password.to_string()));
+        self
+    }
+
+    /// Trust the certificates of a PEM bundle in addition to the system roots, e.g. the private CA
+    /// of an intercepting proxy.
+    #[cfg(any(feature = "native-tls", feature = "rustls", feature = "vendored-tls"))]
+    pub fn add_root_certificate_pem(mut self, pem: &[u8]) -> ClientBuilder {
+        self.http.root_certificates.push(pem.to_vec());
+        self
+    }
+
+    /// Authenticate with a TLS client certificate, from PEM containing the certificate chain and
+    /// the private key. Uses rustls.
+    #[cfg(feature = "rustls")]
+    pub fn identity_pem(mut self, pem: &[u8]) -> ClientBuilder {
+        self.http.identity = Some(crate::client::transport::ClientIdentity::Pem(pem.to_vec()));
+        self
+    }
+
+    /// Authenticate with a TLS client certificate, from a PEM certificate chain and a PKCS #8 PEM
+    /// private key. Uses native-tls.
+    #[cfg(any(feature = "native-tls", feature = "vendored-tls"))]
+    pub fn identity_pkcs8_pem(mut self, certificate: &[u8], key: &[u8]) -> ClientBuilder {
+        self.http.identity = Some(crate::client::transport::ClientIdentity::Pkcs8Pem {
+            certificate: certificate.to_vec(),
+            key: key.to_vec(),
+        });
+        self
+    }
+
+    /// Authenticate with a TLS client certificate, from a PKCS #12 archive encrypted with
+    /// `password`. Uses native-tls.
+    #[cfg(any(feature = "native-tls", feature = "vendored-tls"))]
+    pub fn identity_pkcs12_der(mut self, der: &[u8], password: &str) -> ClientBuilder {
+        self.http.identity = Some(crate::client::transport::ClientIdentity::Pkcs12Der {
+            der: der.to_vec(),
+            password: password.to_string(),
+        });
+        self
+    }
+
+    /// Give up connecting after `timeout`, failing with `FcmError::Timeout`. No limit by default.
+    pub fn connect_timeout(mut self, timeout: Duration) -> ClientBuilder {
+        self.http.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Give up a request after `timeout`, from connecting until the response is read, failing with
+    /// `FcmError::Timeout`. No limit by default.
+    pub fn request_timeout(mut self, timeout: Duration) -> ClientBuilder {
+        self.http.timeout = Some(timeout);
+        self
+    }
+
+    /// Speak HTTP/2 right away instead of negotiating it, e.g. for a plain text HTTP/2 proxy.
+    pub fn http2_prior_knowledge(mut self) -> ClientBuilder {
+        self.http.http2_prior_knowledge = true;
+        self
+    }
+
+    /// Send TCP keep-alive probes on idle connections every `interval`.
+    pub fn tcp_keepalive(mut self, interval: Duration) -> ClientBuilder {
+        self.http.tcp_keepalive = Some(interval);
+        self
+    }
+
+    /// Close pooled connections idle for longer than `timeout`. Defaults to 90 seconds.
+    pub fn pool_idle_timeout(mut self, timeout: Duration) -> ClientBuilder {
+        self.http.pool_idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Keep at most `max` idle connections per host. Unlimited by default.
+    pub fn pool_max_idle_per_host(mut self, max: usize) -> ClientBuilder {
+        self.http.pool_max_idle_per_host = Some(max);
+        self
+    }
+
+    /// Send HTTP/2 pings every `interval`, also on idle connections, so proxies and load balancers
+    /// do not drop them.
+    pub fn http2_keep_alive_interval(mut self, interval: Duration) -> ClientBuilder {
+        self.http.http2_keep_alive_interval = Some(interval);
+        self
+    }
+
+    /// Build the client, reading the project id from the service account key file and fetching a
+    /// first access token.
+    pub async fn build(self) -> Result<Client, FcmError> {
+        let service_key_file = match self.service_account_key_path {
+            Some(path) => path,
+            None => dotenvy::var("GOOGLE_APPLICATION_CREDENTIALS")
+                .map_err(|err| FcmError::AuthToken(format!("GOOGLE_APPLICATION_CREDENTIALS: {err}")))?,
+        };
+        let project_id = read_project_id(&service_key_file).map_err(FcmError::ProjectIdError)?;
+
+        let transport: Arc<dyn HttpTransport> = match self.transport {
+            Some(transport) => transport,
+            None => Arc::new(ReqwestTransport::new(self.http.build()?)),
+        };
+
+        let mut service_account = ServiceAccount::from_file(&service_key_file)?;
+        let access_token = service_account.access_token(transport.as_ref()).await?;
+        if let Some(metrics) = &self.metrics {
+            metrics.token_refreshed();
+        }
+
+        let on_circuit_state_change = self.on_circuit_state_change;
+        Ok(Client {
+            transport,
+            service_account: Mutex::new(service_account),
+            project_id,
+            fcm_endpoint: self.fcm_endpoint.unwrap_or_else(|| FCM_ENDPOINT.to_string()),
+            iid_endpoint: self.iid_endpoint.unwrap_or_else(|| IID_ENDPOINT.to_string()),
+            rate_limiter: self.rate_limit.map(RateLimiter::new),
+            concurrency: self.concurrency.unwrap_or_default(),
+            circuit_breaker: self
+                .circuit_breaker
+                .map(|config| CircuitBreaker::new(config, on_circuit_state_change)),
+            metrics: self.metrics,
+            middleware: self.middleware,
+            on_token_invalid: self.on_token_invalid,
+            token_store: self.token_store,
+            idempotency_store: self
+                .idempotency_store
+                .unwrap_or_else(|| Arc::new(InMemoryIdempotencyStore::new())),
+            idempotency_window: self.idempotency_window.unwrap_or(IDEMPOTENCY_WINDOW),
+            last_access_token: std::sync::Mutex::new(access_token),
+        })
+    }
+}
+
+impl fmt::Debug for ClientBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClientBuilder")
+            .field("service_account_key_path", &self.service_account_key_path)
+            .field("fcm_endpoint", &self.fcm_endpoint)
+            .field("iid_endpoint", &self.iid_endpoint)
+            .field("rate_limit", &self.rate_limit)
+            .field("concurrency", &self.concurrency)
+            .field("circuit_breaker", &self.circuit_breaker)
+            .field("metrics", &self.metrics.is_some())
+            .field("middleware", &self.middleware.len())
+            .field("token_store", &self.token_store.is_some())
+            .field("idempotency_store", &self.idempotency_store.is_some())
+            .field("idempotency_window", &self.idempotency_window)
+            .field("transport", &self.transport.is_some())
+            .field("http", &self.http)
+            .finish()
+    }
+}
+
+fn read_project_id(service_key_file: &str) -> Result<String, String> {
+    let file_content = std::fs::read_to_string(service_key_file).map_err(|err| err.to_string())?;
+    let json_content: serde_json::Value = serde_json::from_str(&file_content).map_err(|err| err.to_string())?;
+
+    match json_content["project_id"].as_str() {
+        Some(project_id) => Ok(project_id.to_string()),
+        None => Err("could not get project_id".to_string()),
+    }
+}
@@ -0,0 +1,349 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::client::response::FcmError;
+
+/// State of the client's circuit breaker.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Requests are sent normally.
+    Closed,
+
+    /// Requests fail fast with `FcmError::CircuitOpen`.
+    Open,
+
+    /// A limited number of probe requests are sent to find out whether FCM recovered.
+    HalfOpen,
+}
+
+/// Configuration of the circuit breaker, see [`ClientBuilder::circuit_breaker`](crate::ClientBuilder::circuit_breaker).
+#[derive(Clone, Debug, PartialEq)]
+pub struct CircuitBreakerConfig {
+    /// Fraction of failed requests (server errors and transport failures) that opens the circuit.
+    pub failure_rate: f64,
+
+    /// Number of most recent requests the failure rate is computed over.
+    pub window_size: usize,
+
+    /// Requests needed in the window before the circuit may open.
+    pub minimum_requests: usize,
+
+    /// How long the circuit stays open before probing.
+    pub open_duration: Duration,
+
+    /// Successful probes needed to close the circuit again.
+    pub half_open_probes: usize,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> CircuitBreakerConfig {
+        CircuitBreakerConfig {
+            failure_rate: 0.5,
+            window_size: 100,
+            minimum_requests: 20,
+            open_duration: Duration::from_secs(30),
+            half_open_probes: 3,
+        }
+    }
+}
+
+/// Callback invoked with the new state whenever the circuit changes state.
+pub type CircuitStateCallback = Arc<dyn Fn(CircuitState) + Send + Sync>;
+
+#[derive(Debug)]
+struct Circuit {
+    state: CircuitState,
+    outcomes: VecDeque<bool>,
+    opened_at: Instant,
+    probes_in_flight: usize,
+    probes_succeeded: usize,
+    /// Incremented on every state change, so probes of an earlier half-open phase are ignored.
+    generation: u64,
+}
+
+/// A request let through by `acquire`. Dropping the permit of a probe without recording its outcome,
+/// e.g. when the request is cancelled, frees the probe slot again.
+#[derive(Debug)]
+pub(crate) struct Permit {
+    probe: Option<Probe>,
+}
+
+#[derive(Debug)]
+struct Probe {
+    circuit: Arc<Mutex<Circuit>>,
+    generation: u64,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if let Some(probe) = self.probe.take() {
+            let mut circuit = probe.circuit.lock().unwrap();
+            if circuit.generation == probe.generation {
+                circuit.probes_in_flight = circuit.probes_in_flight.saturating_sub(1);
+            }
+        }
+    }
+}
+
+pub(crate) struct CircuitBreaker {
+    config: CircuitBreakerConfig,
+    circuit: Arc<Mutex<Circuit>>,
+    on_state_change: Option<CircuitStateCallback>,
+}
+
+impl fmt::Debug for CircuitBreaker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CircuitBreaker")
+            .field("config", &self.config)
+            .field("circuit", &self.circuit)
+            .finish()
+    }
+}
+
+impl CircuitBreaker {
+    pub(crate) fn new(config: CircuitBreakerConfig, on_state_change: Option<CircuitStateCallback>) -> CircuitBreaker {
+        CircuitBreaker {
+            circuit: Arc::new(Mutex::new(Circuit {
+                state: CircuitState::Closed,
+                outcomes: VecDeque::with_capacity(config.window_size),
+                opened_at: Instant::now(),
+                probes_in_flight: 0,
+                probes_succeeded: 0,
+                generation: 0,
+            })),
+            config,
+            on_state_change,
+        }
+    }
+
+    pub(crate) fn state(&self) -> CircuitState {
+        self.circuit.lock().unwrap().state
+    }
+
+    fn transition(&self, circuit: &mut Circuit, state: CircuitState) -> Option<CircuitState> {
+        if circuit.state == state {
+            return None;
+        }
+
+        circuit.state = state;
+        circuit.generation += 1;
+        circuit.probes_in_flight = 0;
+        circuit.probes_succeeded = 0;
+        match state {
+            CircuitState::Open => circuit.opened_at = Instant::now(),
+            CircuitState::Closed => circuit.outcomes.clear(),
+            CircuitState::HalfOpen => {}
+        }
+
+        Some(state)
+    }
+
+    fn notify(&self, changed: Option<CircuitState>) {
+        if let (Some(state), Some(callback)) = (changed, &self.on_state_change) {
+            callback(state);
+        }
+    }
+
+    /// Let a request through, or fail fast while the circuit is open.
+    pub(crate) fn acquire(&self) -> Result<Permit, FcmError> {
+        let mut circuit = self.circuit.lock().unwrap();
+        let mut changed = None;
+
+        if circuit.state == CircuitState::Open && circuit.opened_at.elapsed() >= self.config.open_duration {
+            changed = self.transition(&mut circuit, CircuitState::HalfOpen);
+        }
+
+        let result = match circuit.state {
+            CircuitState::Closed => Ok(Permit { probe: None }),
+            CircuitState::HalfOpen if circuit.probes_in_flight < self.config.half_open_probes.max(1) => {
+                circuit.probes_in_flight += 1;
+                Ok(Permit {
+                    probe: Some(Probe {
+                        circuit: Arc::clone(&self.circuit),
+                        generation: circuit.generation,
+                    }),
+                })
+            }
+            _ => Err(FcmError::CircuitOpen),
+        };
+
+        drop(circuit);
+        self.notify(changed);
+        result
+    }
+
+    /// Record the outcome of a request let through by `acquire`.
+    pub(crate) fn record<T>(&self, mut permit: Permit, result: &Result<T, FcmError>) {
+        let failed = matches!(
+            result,
+            Err(FcmError::ServerError(_))
+                | Err(FcmError::Timeout(_))
+                | Err(FcmError::Connect(_))
+                | Err(FcmError::Transport(_))
+        );
+        let mut circuit = self.circuit.lock().unwrap();
+        let mut changed = None;
+
+        if let Some(probe) = permit.probe.take() {
+            if circuit.generation == probe.generation {
+                circuit.probes_in_flight = circuit.probes_in_flight.saturating_sub(1);
+                if failed {
+                    changed = self.transition(&mut circuit, CircuitState::Open);
+                } else {
+                    circuit.probes_succeeded += 1;
+                    if circuit.probes_succeeded >= self.config.half_open_probes.max(1) {
+                        changed = self.transition(&mut circuit, CircuitState::Closed);
+                    }
+                }
+            }
+        } else if circuit.state == CircuitState::Closed {
+            if circuit.outcomes.len() >= self.config.window_size.max(1) {
+                circuit.outcomes.pop_front();
+            }
+            circuit.outcomes.push_back(failed);
+
+            let requests = circuit.outcomes.len();
+            let failures = circuit.outcomes.iter().filter(|failed| **failed).count();
+            if requests >= self.config.minimum_requests
+                && failures as f64 >= self.config.failure_rate * requests as f64
+                && failures > 0
+            {
+                changed = self.transition(&mut circuit, CircuitState::Open);
+            }
+        }
+
+        drop(circuit);
+        self.notify(changed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::response::{DecodeError, FcmResponse};
+
+    fn breaker(states: Arc<Mutex<Vec<CircuitState>>>) -> CircuitBreaker {
+        let config = CircuitBreakerConfig {
+            failure_rate: 0.5,
+            window_size: 4,
+            minimum_requests: 4,
+            open_duration: Duration::from_secs(10),
+            half_open_probes: 1,
+        };
+        CircuitBreaker::new(config, Some(Arc::new(move |state| states.lock().unwrap().push(state))))
+    }
+
+    fn send(breaker: &CircuitBreaker, result: Result<FcmResponse, FcmError>) -> Result<(), FcmError> {
+        let permit = breaker.acquire()?;
+        breaker.record(permit, &result);
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_opens_on_failure_rate_and_recovers() {
+        let states = Arc::new(Mutex::new(Vec::new()));
+        let breaker = breaker(Arc::clone(&states));
+
+        send(&breaker, Ok(FcmResponse::default())).unwrap();
+        send(&breaker, Err(FcmError::Unauthorized)).unwrap();
+        send(&breaker, Err(FcmError::ServerError(None))).unwrap();
+        assert_eq!(CircuitState::Closed, breaker.state());
+
+        send(&breaker, Err(FcmError::ServerError(None))).unwrap();
+        assert_eq!(CircuitState::Open, breaker.state());
+        assert_eq!(Err(FcmError::CircuitOpen), send(&breaker, Ok(FcmResponse::default())));
+
+        tokio::time::advance(Duration::from_secs(10)).await;
+        let probe = breaker.acquire().unwrap();
+        assert_eq!(CircuitState::HalfOpen, breaker.state());
+        assert!(breaker.acquire().is_err());
+        breaker.record(probe, &Ok(FcmResponse::default()));
+
+        assert_eq!(CircuitState::Closed, breaker.state());
+        assert_eq!(
+            vec![CircuitState::Open, CircuitState::HalfOpen, CircuitState::Closed],
+            *states.lock().unwrap()
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_undecodable_answers_are_not_failures() {
+        let breaker = breaker(Arc::new(Mutex::new(Vec::new())));
+
+        for _ in 0..4 {
+            let undecodable = serde_json::from_str::<serde_json::Value>("<html>").unwrap_err();
+            send(
+                &breaker,
+                Err(FcmError::Decode(DecodeError::new(undecodable, b"<html>"))),
+            )
+            .unwrap();
+        }
+
+        assert_eq!(CircuitState::Closed, breaker.state());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_failed_probe_reopens() {
+        let states = Arc::new(Mutex::new(Vec::new()));
+        let breaker = breaker(Arc::clone(&states));
+
+        for _ in 0..4 {
+            send(&breaker, Err(FcmError::ServerError(None))).unwrap();
+        }
+        tokio::time::advance(Duration::from_secs(10)).await;
+        send(&breaker, Err(FcmError::ServerError(None))).unwrap();
+
+        assert_eq!(CircuitState::Open, breaker.state());
+        assert_eq!(Err(FcmError::CircuitOpen), send(&breaker, Ok(FcmResponse::default())));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_cancelled_probe_frees_its_slot() {
+        let states = Arc::new(Mutex::new(Vec::new()));
+        let breaker = breaker(Arc::clone(&states));
+
+        for _ in 0..4 {
+            send(&breaker, Err(FcmError::ServerError(None))).unwrap();
+        }
+        tokio::time::advance(Duration::from_secs(10)).await;
+
+        let probe = async {
+            let _permit = breaker.acquire()?;
+            std::future::pending::<()>().await;
+            Ok::<_, FcmError>(())
+        };
+        assert!(tokio::time::timeout(Duration::from_secs(1), probe).await.is_err());
+        assert_eq!(CircuitState::HalfOpen, breaker.state());
+
+        send(&breaker, Ok(FcmResponse::default())).unwrap();
+        assert_eq!(CircuitState::Closed, breaker.state());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_stale_probe_does_not_free_a_later_slot() {
+        let config = CircuitBreakerConfig {
+            window_size: 1,
+            minimum_requests: 1,
+            open_duration: Duration::from_secs(10),
+            half_open_probes: 2,
+            ..Default::default()
+        };
+        let breaker = CircuitBreaker::new(config, None);
+        send(&breaker, Err(FcmError::ServerError(None))).unwrap();
+        tokio::time::advance(Duration::from_secs(10)).await;
+
+        let stale = breaker.acquire().unwrap();
+        send(&breaker, Err(FcmError::ServerError(None))).unwrap();
+        assert_eq!(CircuitState::Open, breaker.state());
+
+        tokio::time::advance(Duration::from_secs(10)).await;
+        let _first = breaker.acquire().unwrap();
+        drop(stale);
+        let _second = breaker.acquire().unwrap();
+        assert_eq!(Err(FcmError::CircuitOpen), breaker.acquire().map(|_| ()));
+    }
+}
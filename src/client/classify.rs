@@ -0,0 +1,159 @@
+use std::sync::Arc;
+
+use crate::client::response::{ErrorCode, FcmError};
+
+/// Why a registration token can no longer be used.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TokenInvalidReason {
+    /// The app was uninstalled or the token expired (`UNREGISTERED`).
+    Unregistered,
+
+    /// The token is not a valid FCM registration token (`INVALID_ARGUMENT` about the token).
+    InvalidFormat,
+
+    /// The token belongs to another sender (`SENDER_ID_MISMATCH`).
+    SenderIdMismatch,
+}
+
+/// What a failed send says about the message, its target and FCM.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ErrorClass {
+    /// The registration token is dead and should be deleted.
+    TokenInvalid(TokenInvalidReason),
+
+    /// FCM is overloaded or unreachable, sending the same message later may succeed.
+    Transient,
+
+    /// The message itself is invalid and will fail again unchanged.
+    Message,
+
+    /// The credentials or project setup are wrong, every message will fail.
+    Configuration,
+
+    /// FCM accepted the request but its answer could not be read. The message was most likely
+    /// delivered, sending it again would duplicate it.
+    Unconfirmed,
+}
+
+/// Callback invoked with the token and reason whenever FCM reports a registration token as invalid.
+pub type TokenInvalidCallback = Arc<dyn Fn(&str, TokenInvalidReason) + Send + Sync>;
+
+impl FcmError {
+    /// Classify the error into dead tokens, transient failures, invalid messages and configuration
+    /// problems.
+    pub fn class(&self) -> ErrorClass {
+        match self {
+            FcmError::Rejected(ErrorCode::Unregistered, _) => {
+                ErrorClass::TokenInvalid(TokenInvalidReason::Unregistered)
+            }
+            FcmError::Rejected(ErrorCode::SenderIdMismatch, _) => {
+                ErrorClass::TokenInvalid(TokenInvalidReason::SenderIdMismatch)
+            }
+            FcmError::InvalidToken(_) => ErrorClass::TokenInvalid(TokenInvalidReason::InvalidFormat),
+            FcmError::Rejected(ErrorCode::QuotaExceeded, _)
+            | FcmError::Rejected(ErrorCode::Unavailable, _)
+            | FcmError::Rejected(ErrorCode::Internal, _)
+            | FcmError::QuotaExceeded(_)
+            | FcmError::ServerError(_)
+            | FcmError::CircuitOpen
+            | FcmError::IdempotencyStore(_)
+            | FcmError::SendInProgress(_)
+            | FcmError::Timeout(_)
+            | FcmError::Connect(_)
+            | FcmError::Transport(_) => ErrorClass::Transient,
+            FcmError::Decode(_) => ErrorClass::Unconfirmed,
+            FcmError::Rejected(ErrorCode::ThirdPartyAuthError, _)
+            | FcmError::Unauthorized
+            | FcmError::Tls(_)
+            | FcmError::ProjectIdError(_)
+            | FcmError::UnknownProject(_)
+            | FcmError::InvalidConfiguration(_)
+            | FcmError::AuthToken(_) => ErrorClass::Configuration,
+            FcmError::Rejected(ErrorCode::InvalidArgument, _)
+            | FcmError::Rejected(ErrorCode::UnspecifiedError, _)
+            | FcmError::InvalidMessage(_) => ErrorClass::Message,
+        }
+    }
+
+    /// Whether sending the same message again later may succeed: server errors, exceeded quotas,
+    /// timeouts, connection and other transport failures, an open circuit, a failed idempotency store
+    /// and a concurrent send with the same idempotency key. Rejected messages, invalid tokens, TLS
+    /// failures, configuration problems and undecodable answers to accepted messages are final.
+    ///
+    /// After a timeout or a transport failure the message may have been delivered already, see
+    /// [`Client::send_idempotent`](crate::Client::send_idempotent) to avoid duplicates.
+    pub fn is_retryable(&self) -> bool {
+        self.class() == ErrorClass::Transient
+    }
+
+    /// The reason the registration token is invalid, if the error says so.
+    pub fn token_invalid_reason(&self) -> Option<TokenInvalidReason> {
+        match self.class() {
+            ErrorClass::TokenInvalid(reason) => Some(reason),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::response::DecodeError;
+    use crate::client::transport::{TransportError, TransportErrorKind};
+
+    #[test]
+    fn test_classify_token_errors() {
+        assert_eq!(
+            Some(TokenInvalidReason::Unregistered),
+            FcmError::Rejected(ErrorCode::Unregistered, String::new()).token_invalid_reason()
+        );
+        assert_eq!(
+            Some(TokenInvalidReason::SenderIdMismatch),
+            FcmError::Rejected(ErrorCode::SenderIdMismatch, String::new()).token_invalid_reason()
+        );
+        assert_eq!(
+            Some(TokenInvalidReason::InvalidFormat),
+            FcmError::InvalidToken(String::new()).token_invalid_reason()
+        );
+        assert_eq!(
+            None,
+            FcmError::Rejected(
+                ErrorCode::InvalidArgument,
+                "The registration token is not a valid FCM registration token".into()
+            )
+            .token_invalid_reason()
+        );
+    }
+
+    #[test]
+    fn test_classify_other_errors() {
+        assert_eq!(
+            ErrorClass::Message,
+            FcmError::Rejected(ErrorCode::InvalidArgument, "Invalid value at 'message.data'".into()).class()
+        );
+        assert_eq!(ErrorClass::Transient, FcmError::ServerError(None).class());
+        assert_eq!(
+            ErrorClass::Transient,
+            FcmError::Rejected(ErrorCode::Unavailable, String::new()).class()
+        );
+        assert_eq!(ErrorClass::Configuration, FcmError::Unauthorized.class());
+    }
+
+    #[test]
+    fn test_retryable_errors() {
+        let transport = |kind| TransportError::new(kind, "failed");
+
+        assert!(FcmError::ServerError(None).is_retryable());
+        assert!(FcmError::QuotaExceeded(None).is_retryable());
+        assert!(FcmError::from(transport(TransportErrorKind::Timeout)).is_retryable());
+        assert!(FcmError::from(transport(TransportErrorKind::Dns)).is_retryable());
+        assert!(FcmError::from(transport(TransportErrorKind::Other)).is_retryable());
+
+        assert!(!FcmError::from(transport(TransportErrorKind::Tls)).is_retryable());
+        assert!(!FcmError::Rejected(ErrorCode::Unregistered, String::new()).is_retryable());
+        assert!(!FcmError::InvalidMessage(String::new()).is_retryable());
+        assert!(!FcmError::Unauthorized.is_retryable());
+        let undecodable = serde_json::from_str::<serde_json::Value>("<html>").unwrap_err();
+        assert!(!FcmError::Decode(DecodeError::new(undecodable, b"<html>")).is_retryable());
+    }
+}
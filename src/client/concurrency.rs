@@ -0,0 +1,203 @@
+use std::convert::TryFrom;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::client::response::{ErrorCode, FcmError, FcmResponse, RetryAfter};
+
+/// Bounds of the adaptive (AIMD) concurrency used by [`Client::send_all`](crate::Client::send_all).
+///
+/// The number of in-flight requests grows by one per round of successful responses and is halved
+/// when FCM reports `QUOTA_EXCEEDED` or `UNAVAILABLE`, or when latency rises above
+/// `latency_tolerance` times the lowest latency seen. A `Retry-After` pauses all new requests.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdaptiveConcurrency {
+    /// In-flight requests when a batch starts.
+    pub initial: usize,
+
+    /// Lower bound of in-flight requests.
+    pub min: usize,
+
+    /// Upper bound of in-flight requests.
+    pub max: usize,
+
+    /// Latencies above this multiple of the lowest observed latency count as overload.
+    pub latency_tolerance: f64,
+}
+
+impl AdaptiveConcurrency {
+    /// Always keep exactly `concurrency` requests in flight.
+    pub fn fixed(concurrency: usize) -> AdaptiveConcurrency {
+        AdaptiveConcurrency {
+            initial: concurrency,
+            min: concurrency,
+            max: concurrency,
+            latency_tolerance: f64::INFINITY,
+        }
+    }
+}
+
+impl Default for AdaptiveConcurrency {
+    fn default() -> AdaptiveConcurrency {
+        AdaptiveConcurrency {
+            initial: 8,
+            min: 1,
+            max: 256,
+            latency_tolerance: 2.0,
+        }
+    }
+}
+
+impl RetryAfter {
+    /// How long to wait from now on.
+    pub fn wait_time(&self) -> Duration {
+        let wait = match self {
+            RetryAfter::Delay(delay) => *delay,
+            RetryAfter::DateTime(date) => *date - time::OffsetDateTime::now_utc(),
+        };
+        Duration::try_from(wait).unwrap_or_default()
+    }
+}
+
+/// Whether `result` means FCM is overloaded, with the `Retry-After` it asked for.
+fn overload(result: &Result<FcmResponse, FcmError>) -> Option<Option<&RetryAfter>> {
+    match result {
+        Err(FcmError::QuotaExceeded(retry_after)) | Err(FcmError::ServerError(retry_after)) => {
+            Some(retry_after.as_ref())
+        }
+        Err(FcmError::Rejected(ErrorCode::QuotaExceeded, _))
+        | Err(FcmError::Rejected(ErrorCode::Unavailable, _))
+        | Err(FcmError::Timeout(_)) => Some(None),
+        _ => None,
+    }
+}
+
+/// The AIMD state of one batch.
+#[derive(Debug)]
+pub(crate) struct ConcurrencyLimiter {
+    config: AdaptiveConcurrency,
+    limit: f64,
+    lowest_latency: Option<Duration>,
+    last_decrease: Option<Instant>,
+    paused_until: Option<Instant>,
+}
+
+impl ConcurrencyLimiter {
+    pub(crate) fn new(config: AdaptiveConcurrency) -> ConcurrencyLimiter {
+        let min = config.min.max(1);
+        let max = config.max.max(min);
+        let limit = config.initial.clamp(min, max) as f64;
+
+        ConcurrencyLimiter {
+            config: AdaptiveConcurrency { min, max, ..config },
+            limit,
+            lowest_latency: None,
+            last_decrease: None,
+            paused_until: None,
+        }
+    }
+
+    /// Current number of requests allowed in flight.
+    pub(crate) fn limit(&self) -> usize {
+        self.limit as usize
+    }
+
+    /// The end of the current `Retry-After` pause, if any.
+    pub(crate) fn paused_until(&self, now: Instant) -> Option<Instant> {
+        self.paused_until.filter(|until| *until > now)
+    }
+
+    pub(crate) fn on_response(&mut self, result: &Result<FcmResponse, FcmError>, latency: Duration, now: Instant) {
+        let lowest = *self.lowest_latency.get_or_insert(latency);
+        let slow = latency.as_secs_f64() > lowest.as_secs_f64() * self.config.latency_tolerance;
+        self.lowest_latency = Some(lowest.min(latency));
+
+        match overload(result) {
+            Some(retry_after) => {
+                if let Some(retry_after) = retry_after {
+                    let until = now + retry_after.wait_time();
+                    self.paused_until = Some(self.paused_until.map_or(until, |paused| paused.max(until)));
+                }
+                self.decrease(latency, now);
+            }
+            None if slow => self.decrease(latency, now),
+            None => {
+                self.limit = (self.limit + 1.0 / self.limit).min(self.config.max as f64);
+            }
+        }
+    }
+
+    /// Halve the limit, at most once per round trip so a burst of failures counts once.
+    fn decrease(&mut self, latency: Duration, now: Instant) {
+        if let Some(last) = self.last_decrease {
+            if now.saturating_duration_since(last) < latency {
+                return;
+            }
+        }
+        self.limit = (self.limit / 2.0).max(self.config.min as f64);
+        self.last_decrease = Some(now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ok() -> Result<FcmResponse, FcmError> {
+        Ok(FcmResponse::default())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_additive_increase() {
+        let mut limiter = ConcurrencyLimiter::new(AdaptiveConcurrency {
+            initial: 2,
+            ..Default::default()
+        });
+
+        // 2 -> 2.5 -> 2.9 -> 3.24
+        for _ in 0..3 {
+            limiter.on_response(&ok(), Duration::from_millis(10), Instant::now());
+        }
+
+        assert_eq!(3, limiter.limit());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_multiplicative_decrease_on_overload() {
+        let mut limiter = ConcurrencyLimiter::new(AdaptiveConcurrency {
+            initial: 16,
+            ..Default::default()
+        });
+        let now = Instant::now();
+
+        limiter.on_response(
+            &Err(FcmError::Rejected(ErrorCode::QuotaExceeded, String::new())),
+            Duration::from_millis(10),
+            now,
+        );
+        limiter.on_response(&Err(FcmError::ServerError(None)), Duration::from_millis(10), now);
+        assert_eq!(8, limiter.limit());
+
+        let later = now + Duration::from_millis(100);
+        limiter.on_response(&ok(), Duration::from_millis(50), later);
+        assert_eq!(4, limiter.limit());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_retry_after_pauses() {
+        let mut limiter = ConcurrencyLimiter::new(AdaptiveConcurrency::fixed(4));
+        let now = Instant::now();
+
+        limiter.on_response(
+            &Err(FcmError::QuotaExceeded(Some(RetryAfter::Delay(
+                time::Duration::seconds(5),
+            )))),
+            Duration::from_millis(10),
+            now,
+        );
+
+        assert_eq!(4, limiter.limit());
+        assert_eq!(Some(now + Duration::from_secs(5)), limiter.paused_until(now));
+        assert_eq!(None, limiter.paused_until(now + Duration::from_secs(5)));
+    }
+}
@@ -0,0 +1,118 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use time::OffsetDateTime;
+
+use crate::android::android_config::AndroidConfig;
+use crate::apns::apns_config::ApnsConfig;
+use crate::client::response::FcmError;
+use crate::Message;
+
+/// Longest idempotency key, the limit of the `apns-collapse-id` header.
+pub(crate) const MAX_KEY_LEN: usize = 64;
+
+/// How long a key is reserved while its message is sent, and kept reserved when it is unknown
+/// whether FCM accepted the message. Bounds how long a send abandoned midway blocks its key.
+pub(crate) const IN_FLIGHT_LEASE: time::Duration = time::Duration::minutes(2);
+
+/// Storage of the message names sent per idempotency key, see
+/// [`Client::send_idempotent`](crate::Client::send_idempotent).
+///
+/// A key is reserved for a short lease before its message is sent, and then either completed with
+/// the message name or released if FCM did not accept the message. When that is unknown, e.g. after
+/// a timeout, the reservation is left to expire. Reserving must be atomic, e.g. `INSERT OR IGNORE`
+/// in SQL, so that only one of several concurrent sends with the same key goes through.
+#[async_trait]
+pub trait IdempotencyStore: Send + Sync {
+    /// The message name recorded for `key`, unless it expired before `now` or is only reserved.
+    async fn get(&self, key: &str, now: OffsetDateTime) -> Result<Option<String>, FcmError>;
+
+    /// Reserve `key` until `until`, returning `false` if it is already reserved or recorded and has
+    /// not expired yet.
+    async fn try_reserve(&self, key: &str, until: OffsetDateTime) -> Result<bool, FcmError>;
+
+    /// Record that the reserved `key` was sent as `message_name`, until `expires_at`.
+    async fn complete(&self, key: &str, message_name: &str, expires_at: OffsetDateTime) -> Result<(), FcmError>;
+
+    /// Drop the reservation of `key` after FCM did not accept its message, so it can be sent again.
+    async fn release(&self, key: &str) -> Result<(), FcmError>;
+}
+
+/// An `IdempotencyStore` in memory, suppressing duplicates sent from the same process.
+#[derive(Debug, Default)]
+pub struct InMemoryIdempotencyStore {
+    sent: Mutex<HashMap<String, (Option<String>, OffsetDateTime)>>,
+}
+
+impl InMemoryIdempotencyStore {
+    /// Get an empty store.
+    pub fn new() -> InMemoryIdempotencyStore {
+        InMemoryIdempotencyStore::default()
+    }
+}
+
+#[async_trait]
+impl IdempotencyStore for InMemoryIdempotencyStore {
+    async fn get(&self, key: &str, now: OffsetDateTime) -> Result<Option<String>, FcmError> {
+        let sent = self.sent.lock().unwrap();
+        Ok(sent
+            .get(key)
+            .filter(|(_, expires_at)| *expires_at > now)
+            .and_then(|(message_name, _)| message_name.clone()))
+    }
+
+    async fn try_reserve(&self, key: &str, until: OffsetDateTime) -> Result<bool, FcmError> {
+        let mut sent = self.sent.lock().unwrap();
+        let now = OffsetDateTime::now_utc();
+        sent.retain(|_, (_, expires_at)| *expires_at > now);
+        match sent.entry(key.to_string()) {
+            Entry::Occupied(_) => Ok(false),
+            Entry::Vacant(entry) => {
+                entry.insert((None, until));
+                Ok(true)
+            }
+        }
+    }
+
+    async fn complete(&self, key: &str, message_name: &str, expires_at: OffsetDateTime) -> Result<(), FcmError> {
+        let mut sent = self.sent.lock().unwrap();
+        sent.insert(key.to_string(), (Some(message_name.to_string()), expires_at));
+        Ok(())
+    }
+
+    async fn release(&self, key: &str) -> Result<(), FcmError> {
+        let mut sent = self.sent.lock().unwrap();
+        if let Entry::Occupied(entry) = sent.entry(key.to_string()) {
+            if entry.get().0.is_none() {
+                entry.remove();
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Make devices replace an earlier notification sent with the same `key`: set the Android
+/// notification tag and the `apns-collapse-id` header, unless they are already set.
+pub(crate) fn collapse_duplicates(message: &mut Message, key: &str) {
+    let has_notification = message.notification.is_some()
+        || message
+            .android
+            .as_ref()
+            .is_some_and(|android| android.notification.is_some());
+    if has_notification {
+        let android = message.android.get_or_insert_with(AndroidConfig::default);
+        let notification = android.notification.get_or_insert_with(Default::default);
+        notification.tag.get_or_insert_with(|| key.to_string());
+    }
+
+    let apns = message.apns.get_or_insert_with(ApnsConfig::default);
+    let headers = apns.headers.get_or_insert_with(|| json!({}));
+    if let Value::Object(headers) = headers {
+        headers
+            .entry("apns-collapse-id")
+            .or_insert_with(|| Value::String(key.to_string()));
+    }
+}
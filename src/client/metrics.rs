@@ -0,0 +1,197 @@
+use std::time::Duration;
+
+use crate::client::response::{FcmError, FcmResponse};
+
+/// Hooks called by the [`Client`](crate::Client) at each stage of sending, to feed counters and
+/// histograms. Every method does nothing by default.
+///
+/// With the `metrics` feature, [`MetricsRecorder`] reports through the `metrics` crate facade (e.g.
+/// to Prometheus), and with the `opentelemetry` feature, [`OpenTelemetryMetrics`] reports to an
+/// OpenTelemetry meter.
+pub trait Metrics: Send + Sync {
+    /// A message was sent, successfully or not, after `latency`.
+    fn message_sent(&self, result: &Result<FcmResponse, FcmError>, latency: Duration) {
+        let _ = (result, latency);
+    }
+
+    /// A message was serialized into a request body of `bytes` bytes.
+    fn payload_size(&self, bytes: usize) {
+        let _ = bytes;
+    }
+
+    /// A message is sent again after a failure, for the `attempt`th time. The client itself never
+    /// retries; the `queue::Queue` and the `scheduler::Scheduler` given these metrics report it when
+    /// they reschedule a message.
+    fn message_retried(&self, attempt: u32) {
+        let _ = attempt;
+    }
+
+    /// A new OAuth access token was fetched.
+    fn token_refreshed(&self) {}
+
+    /// A message waited `wait` for its budget of the client side rate limiter.
+    fn throttled(&self, wait: Duration) {
+        let _ = wait;
+    }
+
+    /// A request to the instance id API finished after `latency`. The `operation` is `batchAdd`,
+    /// `batchRemove` or `info`, the `outcome` is labeled by [`outcome_label`].
+    fn iid_request(&self, operation: &'static str, outcome: &'static str, latency: Duration) {
+        let _ = (operation, outcome, latency);
+    }
+}
+
+/// The outcome label of a request: `"success"` or the error kind, see [`FcmError::kind`].
+pub fn outcome_label<T>(result: &Result<T, FcmError>) -> &'static str {
+    match result {
+        Ok(_) => "success",
+        Err(error) => error.kind(),
+    }
+}
+
+/// Reports to the `metrics` crate facade, available with the `metrics` feature.
+///
+/// * `fcm_messages_total` counter, labeled with `outcome`
+/// * `fcm_send_duration_seconds` histogram, labeled with `outcome`
+/// * `fcm_payload_bytes` histogram
+/// * `fcm_retries_total` counter
+/// * `fcm_token_refreshes_total` counter
+/// * `fcm_throttle_duration_seconds` histogram
+/// * `fcm_iid_requests_total` counter, labeled with `operation` and `outcome`
+/// * `fcm_iid_request_duration_seconds` histogram, labeled with `operation` and `outcome`
+#[cfg(feature = "metrics")]
+#[derive(Clone, Debug, Default)]
+pub struct MetricsRecorder;
+
+#[cfg(feature = "metrics")]
+impl Metrics for MetricsRecorder {
+    fn message_sent(&self, result: &Result<FcmResponse, FcmError>, latency: Duration) {
+        let outcome = outcome_label(result);
+        ::metrics::counter!("fcm_messages_total", "outcome" => outcome).increment(1);
+        ::metrics::histogram!("fcm_send_duration_seconds", "outcome" => outcome).record(latency.as_secs_f64());
+    }
+
+    fn payload_size(&self, bytes: usize) {
+        ::metrics::histogram!("fcm_payload_bytes").record(bytes as f64);
+    }
+
+    fn message_retried(&self, _attempt: u32) {
+        ::metrics::counter!("fcm_retries_total").increment(1);
+    }
+
+    fn token_refreshed(&self) {
+        ::metrics::counter!("fcm_token_refreshes_total").increment(1);
+    }
+
+    fn throttled(&self, wait: Duration) {
+        ::metrics::histogram!("fcm_throttle_duration_seconds").record(wait.as_secs_f64());
+    }
+
+    fn iid_request(&self, operation: &'static str, outcome: &'static str, latency: Duration) {
+        ::metrics::counter!("fcm_iid_requests_total", "operation" => operation, "outcome" => outcome).increment(1);
+        ::metrics::histogram!("fcm_iid_request_duration_seconds", "operation" => operation, "outcome" => outcome)
+            .record(latency.as_secs_f64());
+    }
+}
+
+/// Reports to an OpenTelemetry meter, available with the `opentelemetry` feature.
+///
+/// * `fcm.messages` counter, with an `outcome` attribute
+/// * `fcm.send.duration` histogram in seconds, with an `outcome` attribute
+/// * `fcm.payload.size` histogram in bytes
+/// * `fcm.retries` counter
+/// * `fcm.token.refreshes` counter
+/// * `fcm.throttle.duration` histogram in seconds
+/// * `fcm.iid.requests` counter, with `operation` and `outcome` attributes
+/// * `fcm.iid.duration` histogram in seconds, with `operation` and `outcome` attributes
+#[cfg(feature = "opentelemetry")]
+#[derive(Clone, Debug)]
+pub struct OpenTelemetryMetrics {
+    messages: opentelemetry::metrics::Counter<u64>,
+    duration: opentelemetry::metrics::Histogram<f64>,
+    payload_size: opentelemetry::metrics::Histogram<u64>,
+    retries: opentelemetry::metrics::Counter<u64>,
+    token_refreshes: opentelemetry::metrics::Counter<u64>,
+    throttle_duration: opentelemetry::metrics::Histogram<f64>,
+    iid_requests: opentelemetry::metrics::Counter<u64>,
+    iid_duration: opentelemetry::metrics::Histogram<f64>,
+}
+
+#[cfg(feature = "opentelemetry")]
+impl OpenTelemetryMetrics {
+    /// Create the instruments on `meter`.
+    pub fn new(meter: &opentelemetry::metrics::Meter) -> OpenTelemetryMetrics {
+        OpenTelemetryMetrics {
+            messages: meter
+                .u64_counter("fcm.messages")
+                .with_description("Messages sent, by outcome")
+                .build(),
+            duration: meter
+                .f64_histogram("fcm.send.duration")
+                .with_unit("s")
+                .with_description("Time to send a message, by outcome")
+                .build(),
+            payload_size: meter
+                .u64_histogram("fcm.payload.size")
+                .with_unit("By")
+                .with_description("Size of serialized requests")
+                .build(),
+            retries: meter
+                .u64_counter("fcm.retries")
+                .with_description("Messages sent again after a failure")
+                .build(),
+            token_refreshes: meter
+                .u64_counter("fcm.token.refreshes")
+                .with_description("OAuth access tokens fetched")
+                .build(),
+            throttle_duration: meter
+                .f64_histogram("fcm.throttle.duration")
+                .with_unit("s")
+                .with_description("Time messages waited for the client side rate limiter")
+                .build(),
+            iid_requests: meter
+                .u64_counter("fcm.iid.requests")
+                .with_description("Instance id API requests, by operation and outcome")
+                .build(),
+            iid_duration: meter
+                .f64_histogram("fcm.iid.duration")
+                .with_unit("s")
+                .with_description("Time of an instance id API request, by operation and outcome")
+                .build(),
+        }
+    }
+}
+
+#[cfg(feature = "opentelemetry")]
+impl Metrics for OpenTelemetryMetrics {
+    fn message_sent(&self, result: &Result<FcmResponse, FcmError>, latency: Duration) {
+        let attributes = [opentelemetry::KeyValue::new("outcome", outcome_label(result))];
+        self.messages.add(1, &attributes);
+        self.duration.record(latency.as_secs_f64(), &attributes);
+    }
+
+    fn payload_size(&self, bytes: usize) {
+        self.payload_size.record(bytes as u64, &[]);
+    }
+
+    fn message_retried(&self, _attempt: u32) {
+        self.retries.add(1, &[]);
+    }
+
+    fn token_refreshed(&self) {
+        self.token_refreshes.add(1, &[]);
+    }
+
+    fn throttled(&self, wait: Duration) {
+        self.throttle_duration.record(wait.as_secs_f64(), &[]);
+    }
+
+    fn iid_request(&self, operation: &'static str, outcome: &'static str, latency: Duration) {
+        let attributes = [
+            opentelemetry::KeyValue::new("operation", operation),
+            opentelemetry::KeyValue::new("outcome", outcome),
+        ];
+        self.iid_requests.add(1, &attributes);
+        self.iid_duration.record(latency.as_secs_f64(), &attributes);
+    }
+}
@@ -0,0 +1,73 @@
+use reqwest::header::{HeaderName, HeaderValue};
+
+use crate::client::response::{FcmError, FcmResponse};
+use crate::client::transport::HttpRequest;
+use crate::Message;
+
+/// A hook into every request sent by the [`Client`](crate::Client), see
+/// [`ClientBuilder::middleware`](crate::ClientBuilder::middleware).
+///
+/// Middlewares run in the order they were added for `on_message` and `on_request`, and in reverse
+/// order for `on_response`, so the first middleware wraps all others. Every method does nothing by
+/// default.
+///
+/// ```rust
+/// use fcm::{Message, Middleware};
+///
+/// /// Prefix the collapse key of every Android message with the tenant.
+/// struct Tenant(String);
+///
+/// impl Middleware for Tenant {
+///     fn on_message(&self, message: &mut Message) {
+///         if let Some(android) = &mut message.android {
+///             if let Some(collapse_key) = &mut android.collapse_key {
+///                 *collapse_key = format!("{}:{}", self.0, collapse_key);
+///             }
+///         }
+///     }
+/// }
+/// ```
+pub trait Middleware: Send + Sync {
+    /// Inspect or rewrite a message before it is serialized.
+    fn on_message(&self, message: &mut Message) {
+        let _ = message;
+    }
+
+    /// Inspect or modify the HTTP request before it is dispatched, e.g. to add headers. Also called
+    /// for the instance id API requests of topic management and `Client::token_info`.
+    fn on_request(&self, request: &mut HttpRequest) {
+        let _ = request;
+    }
+
+    /// Inspect the outcome of sending `message`, as rewritten by `on_message`. Also called with
+    /// [`FcmError::CircuitOpen`] when the circuit breaker refuses the send.
+    fn on_response(&self, message: &Message, result: &Result<FcmResponse, FcmError>) {
+        let _ = (message, result);
+    }
+}
+
+/// A middleware setting a header on every request, e.g. `X-Goog-User-Project` to attribute quota
+/// to another project.
+#[derive(Clone, Debug)]
+pub struct SetHeader {
+    name: HeaderName,
+    value: HeaderValue,
+}
+
+impl SetHeader {
+    /// Set header `name` to `value`. Fails if either is not a valid header name or value.
+    pub fn new(name: &str, value: &str) -> Result<SetHeader, FcmError> {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|err| FcmError::InvalidMessage(format!("header name {name:?}: {err}")))?;
+        let value =
+            HeaderValue::from_str(value).map_err(|err| FcmError::InvalidMessage(format!("header value: {err}")))?;
+
+        Ok(SetHeader { name, value })
+    }
+}
+
+impl Middleware for SetHeader {
+    fn on_request(&self, request: &mut HttpRequest) {
+        request.headers.insert(self.name.clone(), self.value.clone());
+    }
+}
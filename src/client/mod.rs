@@ -1,177 +1,466 @@
+pub(crate) mod builder;
+pub(crate) mod circuit_breaker;
+pub(crate) mod classify;
+pub(crate) mod concurrency;
+pub(crate) mod idempotency;
+pub(crate) mod metrics;
+pub(crate) mod middleware;
+pub(crate) mod multi_project;
+pub(crate) mod rate_limit;
 pub(crate) mod response;
+pub(crate) mod sender;
+pub(crate) mod service_account;
+pub(crate) mod topics;
+mod trace;
+pub(crate) mod transport;
+
+#[cfg(test)]
+mod tests;
+
+pub use self::builder::ClientBuilder;
+pub use self::circuit_breaker::{CircuitBreakerConfig, CircuitState, CircuitStateCallback};
+pub use self::classify::{ErrorClass, TokenInvalidCallback, TokenInvalidReason};
+pub use self::concurrency::AdaptiveConcurrency;
+pub use self::idempotency::{IdempotencyStore, InMemoryIdempotencyStore};
+#[cfg(feature = "metrics")]
+pub use self::metrics::MetricsRecorder;
+#[cfg(feature = "opentelemetry")]
+pub use self::metrics::OpenTelemetryMetrics;
+pub use self::metrics::{outcome_label, Metrics};
+pub use self::middleware::{Middleware, SetHeader};
+pub use self::multi_project::{MultiProjectClient, MultiProjectClientBuilder};
+pub use self::rate_limit::{Quota, RateLimit, RateLimiterStats};
+pub use self::sender::{RecordingSender, ScriptedSender, Sender};
+pub use self::topics::{TokenInfo, TopicManagementError, TopicManagementResponse};
+pub use self::transport::{
+    HttpRequest, HttpResponse, HttpTransport, ReqwestTransport, TransportError, TransportErrorKind,
+};
+
+use self::circuit_breaker::CircuitBreaker;
+use self::concurrency::ConcurrencyLimiter;
+use self::rate_limit::RateLimiter;
+use self::service_account::{ServiceAccount, TokenError};
+use self::trace::{SendSpan, Stage};
+use crate::client::response::{DecodeError, ErrorCode, ErrorReason, ErrorResponse, FcmError, FcmResponse, RetryAfter};
+use crate::token_store::{TokenStore, TokenStoreError};
+use crate::{Message, Target};
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE, RETRY_AFTER};
+use reqwest::{Method, StatusCode};
+use std::borrow::Cow;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
 
-use crate::client::response::{ErrorReason, FcmError, FcmResponse, RetryAfter};
-use crate::{Message, MessageInternal};
-use gauth::serv_account::ServiceAccount;
-use reqwest::header::RETRY_AFTER;
-use reqwest::{Body, StatusCode};
-use serde::Serialize;
-
-// will be used to wrap the message in a "message" field
-#[derive(Serialize)]
-struct MessageWrapper<'a> {
-    message: &'a MessageInternal<'a>,
+/// An async client for sending the notification payload.
+pub struct Client {
+    transport: Arc<dyn HttpTransport>,
+    service_account: Mutex<ServiceAccount>,
+    project_id: String,
+    fcm_endpoint: String,
+    iid_endpoint: String,
+    rate_limiter: Option<RateLimiter>,
+    concurrency: AdaptiveConcurrency,
+    circuit_breaker: Option<CircuitBreaker>,
+    metrics: Option<Arc<dyn Metrics>>,
+    middleware: Vec<Arc<dyn Middleware>>,
+    on_token_invalid: Option<TokenInvalidCallback>,
+    token_store: Option<Arc<dyn TokenStore>>,
+    idempotency_store: Arc<dyn IdempotencyStore>,
+    idempotency_window: std::time::Duration,
+    last_access_token: std::sync::Mutex<String>,
 }
 
-impl<'m> MessageWrapper<'m> {
-    fn new(message: &'m MessageInternal) -> MessageWrapper<'m> {
-        MessageWrapper { message }
-    }
+/// What a single send is told by its caller and learns on its way down to the transport.
+#[derive(Debug)]
+struct SendState {
+    /// The delivery attempt, counted from 1 by callers that retry the message.
+    attempt: u32,
+
+    /// Duration of the HTTP round trip, once the request was made.
+    round_trip: Option<Duration>,
 }
 
-/// An async client for sending the notification payload.
-pub struct Client {
-    http_client: reqwest::Client,
-    service_account: ServiceAccount,
+impl SendState {
+    fn attempt(attempt: u32) -> SendState {
+        SendState {
+            attempt,
+            round_trip: None,
+        }
+    }
 }
 
 impl Client {
-    /// Get a new instance of Client.
+    /// Get a new instance of Client, configured from the `GOOGLE_APPLICATION_CREDENTIALS` environment variable.
     pub async fn new() -> Result<Client, FcmError> {
-        let http_client = reqwest::ClientBuilder::new()
-            .pool_max_idle_per_host(usize::MAX)
-            .build()
-            .unwrap();
-        let service_key_file = Self::get_service_key_file_name().expect("Failed to get service key file");
-        let mut service_account = ServiceAccount::from_file(
-            &service_key_file,
-            vec!["https://www.googleapis.com/auth/firebase.messaging"],
-        );
-
-        service_account
-            .access_token()
-            .await
-            .map_err(|err| FcmError::AuthToken(format!("{err:?}")))?;
+        ClientBuilder::new().build().await
+    }
 
-        Ok(Client {
-            http_client,
-            service_account,
-        })
+    /// Get a builder to configure a new Client.
+    pub fn builder() -> ClientBuilder {
+        ClientBuilder::new()
     }
 
-    fn get_service_key_file_name() -> Result<String, String> {
-        let key_path = match dotenvy::var("GOOGLE_APPLICATION_CREDENTIALS") {
-            Ok(key_path) => key_path,
-            Err(err) => return Err(err.to_string()),
-        };
+    /// The id of the Firebase project messages are sent to.
+    pub fn project_id(&self) -> &str {
+        &self.project_id
+    }
 
-        Ok(key_path)
+    /// How much sending was slowed down by the rate limiter, if one is configured.
+    pub fn rate_limiter_stats(&self) -> Option<RateLimiterStats> {
+        self.rate_limiter.as_ref().map(|limiter| limiter.stats())
     }
 
-    fn read_service_key_file(&self) -> Result<String, String> {
-        let key_path = Self::get_service_key_file_name()?;
+    /// State of the circuit breaker, if one is configured.
+    pub fn circuit_state(&self) -> Option<CircuitState> {
+        self.circuit_breaker.as_ref().map(|breaker| breaker.state())
+    }
 
-        let private_key_content = match std::fs::read(key_path) {
-            Ok(content) => content,
-            Err(err) => return Err(err.to_string()),
-        };
+    /// The current access token. Failing to reach the token endpoint is reported like any other
+    /// transport failure, refused credentials as [`FcmError::AuthToken`].
+    async fn get_auth_token(&self) -> Result<String, FcmError> {
+        Ok(self.access_token().await?)
+    }
+
+    async fn access_token(&self) -> Result<String, TokenError> {
+        let access_token = self
+            .service_account
+            .lock()
+            .await
+            .access_token(self.transport.as_ref())
+            .await?;
+
+        if let Some(metrics) = &self.metrics {
+            let mut last_access_token = self.last_access_token.lock().unwrap();
+            if *last_access_token != access_token {
+                *last_access_token = access_token.clone();
+                metrics.token_refreshed();
+            }
+        }
 
-        Ok(String::from_utf8(private_key_content).unwrap())
+        Ok(access_token)
     }
 
-    fn read_service_key_file_json(&self) -> Result<serde_json::Value, String> {
-        let file_content = match self.read_service_key_file() {
-            Ok(content) => content,
-            Err(err) => return Err(err),
-        };
+    /// Send a message through the FCM API.
+    pub async fn send(&self, message: &Message) -> Result<FcmResponse, FcmError> {
+        self.post_measured(message, false, &mut SendState::attempt(1)).await
+    }
 
-        let json_content: serde_json::Value = match serde_json::from_str(&file_content) {
-            Ok(json) => json,
-            Err(err) => return Err(err.to_string()),
-        };
+    /// Send a message as delivery `attempt` of a caller that retries it, such as the queue.
+    pub(crate) async fn send_attempt(&self, message: &Message, attempt: u32) -> Result<FcmResponse, FcmError> {
+        self.post_measured(message, false, &mut SendState::attempt(attempt))
+            .await
+    }
 
-        Ok(json_content)
+    /// Validate a message against the FCM API without delivering it to any device.
+    pub async fn validate(&self, message: &Message) -> Result<FcmResponse, FcmError> {
+        self.post_measured(message, true, &mut SendState::attempt(1)).await
     }
 
-    fn get_project_id(&self) -> Result<String, String> {
-        let json_content = match self.read_service_key_file_json() {
-            Ok(json) => json,
-            Err(err) => return Err(err),
-        };
+    /// Send a message at most once per idempotency `key` within the idempotency window, e.g. when
+    /// retrying after an ambiguous timeout.
+    ///
+    /// A key already sent within the window is answered with the recorded message name, without
+    /// sending, and a key that is being sent concurrently fails with [`FcmError::SendInProgress`].
+    /// When it is unknown whether FCM accepted the message, e.g. after a timeout, the key stays
+    /// reserved for two minutes, failing with `SendInProgress`, before it can be sent again.
+    ///
+    /// The key is also set as Android notification tag and `apns-collapse-id` (unless set already),
+    /// so a duplicate that slips through replaces the earlier notification. Keys are at most 64 bytes
+    /// long. See `ClientBuilder::idempotency_store`.
+    pub async fn send_idempotent(&self, key: &str, message: &Message) -> Result<FcmResponse, FcmError> {
+        if key.is_empty() || key.len() > idempotency::MAX_KEY_LEN {
+            return Err(FcmError::InvalidMessage(format!(
+                "idempotency key must be 1 to {} bytes long",
+                idempotency::MAX_KEY_LEN
+            )));
+        }
 
-        let project_id = match json_content["project_id"].as_str() {
-            Some(project_id) => project_id,
-            None => return Err("could not get project_id".to_string()),
+        let now = time::OffsetDateTime::now_utc();
+        let recorded = |message_name| FcmResponse {
+            name: Some(message_name),
+            ..Default::default()
         };
+        if let Some(message_name) = self.idempotency_store.get(key, now).await? {
+            return Ok(recorded(message_name));
+        }
+        if !self
+            .idempotency_store
+            .try_reserve(key, now + idempotency::IN_FLIGHT_LEASE)
+            .await?
+        {
+            return match self.idempotency_store.get(key, now).await? {
+                Some(message_name) => Ok(recorded(message_name)),
+                None => Err(FcmError::SendInProgress(key.to_string())),
+            };
+        }
 
-        Ok(project_id.to_string())
+        let mut message = message.clone();
+        idempotency::collapse_duplicates(&mut message, key);
+        let result = self.send(&message).await;
+
+        // the outcome is known, failing now would either invite the duplicate we try to avoid or
+        // hold back a retry until the reservation expires
+        match &result {
+            Ok(FcmResponse {
+                name: Some(message_name),
+                ..
+            }) => {
+                let _ = self
+                    .idempotency_store
+                    .complete(key, message_name, now + self.idempotency_window)
+                    .await;
+            }
+            // FCM may have accepted the message, keep the key reserved until the lease expires
+            Ok(_) | Err(FcmError::Timeout(_)) | Err(FcmError::Transport(_)) | Err(FcmError::Decode(_)) => {}
+            Err(_) => {
+                let _ = self.idempotency_store.release(key).await;
+            }
+        }
+        result
     }
 
-    async fn get_auth_token(&mut self) -> Result<String, String> {
-        let tkn = match self.access_token().await {
-            Ok(tkn) => tkn,
-            Err(_) => return Err("could not get access token".to_string()),
-        };
+    /// Send `message` to each of `tokens`, returning the results in the order of the tokens.
+    ///
+    /// The target of `message` is replaced by each token in turn, and the messages are sent like
+    /// with [`Client::send_all`].
+    pub async fn send_multicast(&self, message: &Message, tokens: &[String]) -> Vec<Result<FcmResponse, FcmError>> {
+        let messages: Vec<Message> = tokens
+            .iter()
+            .map(|token| Message {
+                target: Target::Token(token.clone()),
+                ..message.clone()
+            })
+            .collect();
+
+        self.send_all(&messages).await
+    }
 
-        Ok(tkn)
+    /// Send `message` to every token of `user_id` that is not stale, returning each token with its
+    /// result. Requires a [`TokenStore`](crate::TokenStore), see `ClientBuilder::token_store`.
+    pub async fn send_to_user(
+        &self,
+        user_id: &str,
+        message: &Message,
+    ) -> Result<Vec<(String, Result<FcmResponse, FcmError>)>, TokenStoreError> {
+        let token_store = self.token_store.as_ref().ok_or(TokenStoreError::NotConfigured)?;
+        let tokens = token_store.active_tokens(user_id).await?;
+        let results = self.send_multicast(message, &tokens).await;
+
+        Ok(tokens.into_iter().zip(results).collect())
     }
 
-    async fn access_token(&mut self) -> Result<String, String> {
-        let access_token = match self.service_account.access_token().await {
-            Ok(access_token) => access_token,
-            Err(err) => return Err(err.to_string()),
+    /// Send every message, returning the results in the same order.
+    ///
+    /// Messages are sent concurrently, adapting the number of in-flight requests to how FCM copes
+    /// with the load, see [`AdaptiveConcurrency`].
+    pub async fn send_all(&self, messages: &[Message]) -> Vec<Result<FcmResponse, FcmError>> {
+        let mut results: Vec<Option<Result<FcmResponse, FcmError>>> = messages.iter().map(|_| None).collect();
+        let mut limiter = ConcurrencyLimiter::new(self.concurrency.clone());
+        let mut in_flight = FuturesUnordered::new();
+        let mut pending = messages.iter().enumerate();
+        let mut exhausted = false;
+
+        loop {
+            while !exhausted && in_flight.len() < limiter.limit() && limiter.paused_until(Instant::now()).is_none() {
+                match pending.next() {
+                    Some((index, message)) => in_flight.push(async move {
+                        let mut state = SendState::attempt(1);
+                        let result = self.post_measured(message, false, &mut state).await;
+                        (index, result, state.round_trip)
+                    }),
+                    None => exhausted = true,
+                }
+            }
+
+            if in_flight.is_empty() {
+                match limiter.paused_until(Instant::now()) {
+                    Some(until) if !exhausted => {
+                        tokio::time::sleep_until(until).await;
+                        continue;
+                    }
+                    _ => break,
+                }
+            }
+
+            if let Some((index, result, round_trip)) = in_flight.next().await {
+                // only what FCM took to answer tells about its load, not the time spent waiting for
+                // the rate limiter or an access token; unsent messages tell nothing
+                if let Some(round_trip) = round_trip {
+                    limiter.on_response(&result, round_trip, Instant::now());
+                }
+                results[index] = Some(result);
+            }
+        }
+
+        results.into_iter().flatten().collect()
+    }
+
+    async fn post(
+        &self,
+        message: &Message,
+        validate_only: bool,
+        span: &SendSpan,
+        state: &mut SendState,
+    ) -> Result<FcmResponse, FcmError> {
+        let circuit_breaker = match &self.circuit_breaker {
+            Some(circuit_breaker) => circuit_breaker,
+            None => return self.request_traced(message, validate_only, span, state).await,
         };
 
-        let token_no_bearer = access_token.split(char::is_whitespace).collect::<Vec<&str>>()[1];
+        let permit = circuit_breaker.acquire()?;
+        let result = self.request_traced(message, validate_only, span, state).await;
+        circuit_breaker.record(permit, &result);
+        result
+    }
 
-        Ok(token_no_bearer.to_string())
+    async fn post_measured(
+        &self,
+        message: &Message,
+        validate_only: bool,
+        state: &mut SendState,
+    ) -> Result<FcmResponse, FcmError> {
+        let start = Instant::now();
+        let result = self.request(message, validate_only, state).await;
+        if let Some(metrics) = &self.metrics {
+            metrics.message_sent(&result, start.elapsed());
+        }
+        result
     }
 
-    pub async fn send(&mut self, message: &Message) -> Result<FcmResponse, FcmError> {
-        let fin = message.finalize();
-        let wrapper = MessageWrapper::new(&fin);
-        let payload = serde_json::to_vec(&wrapper).unwrap();
+    async fn request(
+        &self,
+        message: &Message,
+        validate_only: bool,
+        state: &mut SendState,
+    ) -> Result<FcmResponse, FcmError> {
+        let mut message = Cow::Borrowed(message);
+        for middleware in &self.middleware {
+            middleware.on_message(message.to_mut());
+        }
 
-        let project_id = match self.get_project_id() {
-            Ok(project_id) => project_id,
-            Err(err) => return Err(FcmError::ProjectIdError(err)),
-        };
+        let span = SendSpan::new(&self.project_id, &message, validate_only, state.attempt);
+        // inside the middleware, so that it also sees the sends refused by an open circuit
+        let result = self.post(&message, validate_only, &span, state).await;
+        span.finish(result.as_ref().map(|response| response.name.as_deref()));
 
-        let auth_token = match self.get_auth_token().await {
-            Ok(tkn) => tkn,
-            Err(err) => return Err(FcmError::ProjectIdError(err)),
-        };
+        if let (Target::Token(token), Err(error)) = (&message.target, &result) {
+            if let Some(reason) = error.token_invalid_reason() {
+                if let Some(on_token_invalid) = &self.on_token_invalid {
+                    on_token_invalid(token, reason);
+                }
+                if let Some(token_store) = &self.token_store {
+                    // best effort, the message result matters more than the cleanup
+                    let _ = token_store.remove(token).await;
+                }
+            }
+        }
+
+        for middleware in self.middleware.iter().rev() {
+            middleware.on_response(&message, &result);
+        }
+        result
+    }
+
+    async fn request_traced(
+        &self,
+        message: &Message,
+        validate_only: bool,
+        span: &SendSpan,
+        state: &mut SendState,
+    ) -> Result<FcmResponse, FcmError> {
+        let payload = span
+            .in_stage(Stage::Serialize, || message.to_request_bytes(validate_only))
+            .map_err(|err| FcmError::InvalidMessage(err.to_string()))?;
+        if let Some(metrics) = &self.metrics {
+            metrics.payload_size(payload.len());
+        }
+
+        if let Some(rate_limiter) = &self.rate_limiter {
+            let wait = rate_limiter.acquire(&message.target).await;
+            if let (Some(metrics), true) = (&self.metrics, wait > Duration::ZERO) {
+                metrics.throttled(wait);
+            }
+        }
+
+        let auth_token = span.stage(Stage::AuthToken, self.get_auth_token()).await?;
 
         // https://firebase.google.com/docs/reference/fcm/rest/v1/projects.messages/send
-        let url = format!("https://fcm.googleapis.com/v1/projects/{}/messages:send", project_id);
+        let url = format!("{}/v1/projects/{}/messages:send", self.fcm_endpoint, self.project_id);
 
-        let request = self
-            .http_client
-            .post(&url)
-            .header("Content-Type", "application/json")
-            .bearer_auth(auth_token)
-            .body(Body::from(payload))
-            .build()?;
+        let mut request = HttpRequest {
+            method: Method::POST,
+            url,
+            headers: HeaderMap::new(),
+            body: payload,
+        };
+        request
+            .headers
+            .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        request.headers.insert(AUTHORIZATION, bearer(&auth_token)?);
+        for middleware in &self.middleware {
+            middleware.on_request(&mut request);
+        }
 
-        let response = self.http_client.execute(request).await?;
+        let start = Instant::now();
+        let response = span.stage(Stage::Http, self.transport.execute(request)).await;
+        state.round_trip = Some(start.elapsed());
+        let response = response.map_err(FcmError::from)?;
 
-        let response_status = response.status();
+        span.record_status(response.status.as_u16());
 
         let retry_after = response
-            .headers()
-            .get(RETRY_AFTER)
-            .and_then(|ra| ra.to_str().ok())
+            .header(RETRY_AFTER.as_str())
             .and_then(|ra| ra.parse::<RetryAfter>().ok());
 
-        match response_status {
-            StatusCode::OK => {
-                let intermediate: serde_json::Value = response.json().await.unwrap();
-                dbg!(&intermediate);
-                let fcm_response: FcmResponse = serde_json::value::from_value(intermediate).unwrap();
+        span.in_stage(Stage::Parse, || parse_response(response, retry_after))
+    }
+}
+
+/// The `Authorization` header value for `auth_token`.
+fn bearer(auth_token: &str) -> Result<HeaderValue, FcmError> {
+    let mut value =
+        HeaderValue::from_str(&format!("Bearer {auth_token}")).map_err(|err| FcmError::AuthToken(err.to_string()))?;
+    value.set_sensitive(true);
+    Ok(value)
+}
 
-                match fcm_response.error {
-                    Some(ErrorReason::Unavailable) => Err(FcmError::ServerError(retry_after)),
-                    Some(ErrorReason::InternalServerError) => Err(FcmError::ServerError(retry_after)),
-                    _ => Ok(fcm_response),
+fn parse_response(response: HttpResponse, retry_after: Option<RetryAfter>) -> Result<FcmResponse, FcmError> {
+    match response.status {
+        StatusCode::OK => {
+            let fcm_response: FcmResponse = serde_json::from_slice(&response.body)
+                .map_err(|err| FcmError::Decode(DecodeError::new(err, &response.body)))?;
+
+            match fcm_response.error {
+                Some(ErrorReason::Unavailable) => Err(FcmError::ServerError(retry_after)),
+                Some(ErrorReason::InternalServerError) => Err(FcmError::ServerError(retry_after)),
+                _ => Ok(fcm_response),
+            }
+        }
+        StatusCode::UNAUTHORIZED => Err(FcmError::Unauthorized),
+        StatusCode::BAD_REQUEST => {
+            let body = response.text();
+            match serde_json::from_str::<ErrorResponse>(&body) {
+                Ok(error) if error.violates("message.token") => Err(FcmError::InvalidToken(error.message())),
+                Ok(error) => {
+                    let code = match error.code() {
+                        ErrorCode::UnspecifiedError => ErrorCode::InvalidArgument,
+                        code => code,
+                    };
+                    Err(FcmError::Rejected(code, error.message()))
                 }
+                Err(_) => Err(FcmError::InvalidMessage(format!("Bad Request ({body})"))),
             }
-            StatusCode::UNAUTHORIZED => Err(FcmError::Unauthorized),
-            StatusCode::BAD_REQUEST => {
-                let body = response.text().await.unwrap();
-                Err(FcmError::InvalidMessage(format!("Bad Request ({body}")))
+        }
+        StatusCode::TOO_MANY_REQUESTS => Err(FcmError::QuotaExceeded(retry_after)),
+        status if status.is_server_error() => Err(FcmError::ServerError(retry_after)),
+        _ => {
+            let body = response.text();
+            match serde_json::from_str::<ErrorResponse>(&body) {
+                Ok(error) => Err(FcmError::Rejected(error.code(), error.message())),
+                Err(_) => Err(FcmError::InvalidMessage("Unknown Error".to_string())),
             }
-            status if status.is_server_error() => Err(FcmError::ServerError(retry_after)),
-            _ => Err(FcmError::InvalidMessage("Unknown Error".to_string())),
         }
     }
 }
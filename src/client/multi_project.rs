@@ -0,0 +1,237 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::client::builder::ClientBuilder;
+use crate::client::response::{FcmError, FcmResponse};
+use crate::client::transport::{HttpOptions, HttpTransport, ReqwestTransport};
+use crate::client::Client;
+use crate::Message;
+
+/// Sends to several Firebase projects, e.g. per brand and environment, through one connection pool.
+///
+/// Each project is a [`Client`] with its own credentials, access token and configuration,
+/// registered under a name that messages are routed by.
+///
+/// ```rust,no_run
+/// # async fn run(message: fcm::Message) -> Result<(), fcm::Error> {
+/// use fcm::{Client, MultiProjectClient};
+///
+/// let client = MultiProjectClient::builder()
+///     .project("brand-a", "/keys/brand-a.json")
+///     .project_with("brand-b", Client::builder().service_account_key_path("/keys/brand-b.json"))
+///     .build()
+///     .await?;
+///
+/// client.send("brand-a", &message).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct MultiProjectClient {
+    clients: BTreeMap<String, Client>,
+}
+
+impl fmt::Debug for MultiProjectClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MultiProjectClient")
+            .field("projects", &self.clients.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl MultiProjectClient {
+    /// Get a builder to register the projects.
+    pub fn builder() -> MultiProjectClientBuilder {
+        MultiProjectClientBuilder::default()
+    }
+
+    /// The names of the registered projects, in order.
+    pub fn projects(&self) -> impl Iterator<Item = &str> {
+        self.clients.keys().map(String::as_str)
+    }
+
+    /// The client of project `name`, for everything besides sending, e.g. topic management.
+    pub fn project(&self, name: &str) -> Option<&Client> {
+        self.clients.get(name)
+    }
+
+    fn client(&self, name: &str) -> Result<&Client, FcmError> {
+        self.project(name)
+            .ok_or_else(|| FcmError::UnknownProject(name.to_string()))
+    }
+
+    /// Send a message through project `name`.
+    pub async fn send(&self, name: &str, message: &Message) -> Result<FcmResponse, FcmError> {
+        self.client(name)?.send(message).await
+    }
+
+    /// Validate a message against project `name` without delivering it to any device.
+    pub async fn validate(&self, name: &str, message: &Message) -> Result<FcmResponse, FcmError> {
+        self.client(name)?.validate(message).await
+    }
+
+    /// Send every message through project `name`, returning the results in the same order.
+    pub async fn send_all(
+        &self,
+        name: &str,
+        messages: &[Message],
+    ) -> Result<Vec<Result<FcmResponse, FcmError>>, FcmError> {
+        Ok(self.client(name)?.send_all(messages).await)
+    }
+}
+
+/// A builder to configure a [`MultiProjectClient`].
+#[derive(Default)]
+pub struct MultiProjectClientBuilder {
+    projects: Vec<(String, ClientBuilder)>,
+    transport: Option<Arc<dyn HttpTransport>>,
+    http: HttpOptions,
+}
+
+impl fmt::Debug for MultiProjectClientBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MultiProjectClientBuilder")
+            .field("projects", &self.projects)
+            .field("transport", &self.transport.is_some())
+            .field("http", &self.http)
+            .finish()
+    }
+}
+
+impl MultiProjectClientBuilder {
+    /// Register project `name` with the service account key file at `key_path`.
+    pub fn project(self, name: &str, key_path: &str) -> MultiProjectClientBuilder {
+        self.project_with(name, ClientBuilder::new().service_account_key_path(key_path))
+    }
+
+    /// Register project `name` configured by `builder`. The transport and HTTP options are shared by
+    /// all projects, so `builder` must not set them.
+    pub fn project_with(mut self, name: &str, builder: ClientBuilder) -> MultiProjectClientBuilder {
+        self.projects.push((name.to_string(), builder));
+        self
+    }
+
+    /// Send the requests of all projects through `transport`. Defaults to one [`ReqwestTransport`]
+    /// configured by the HTTP options.
+    pub fn transport<T: HttpTransport + 'static>(mut self, transport: T) -> MultiProjectClientBuilder {
+        self.transport = Some(Arc::new(transport));
+        self
+    }
+
+    /// Configure the connection pool shared by all projects with the HTTP options of `builder`, e.g.
+    /// `Client::builder().proxy(..)`, see [`ClientBuilder`]. Its other settings are ignored, and so
+    /// are the HTTP options when a transport is given.
+    pub fn http_options(mut self, builder: ClientBuilder) -> MultiProjectClientBuilder {
+        self.http = builder.into_http_options();
+        self
+    }
+
+    /// Build a client per project, fetching a first access token for each. Fails if a project
+    /// cannot be built, sets its own transport or HTTP options, or a name is registered twice.
+    pub async fn build(self) -> Result<MultiProjectClient, FcmError> {
+        let transport: Arc<dyn HttpTransport> = match self.transport {
+            Some(transport) => transport,
+            None => Arc::new(ReqwestTransport::new(self.http.build()?)),
+        };
+
+        let mut clients = BTreeMap::new();
+        for (name, builder) in self.projects {
+            if clients.contains_key(&name) {
+                return Err(FcmError::InvalidConfiguration(format!(
+                    "project {name:?} registered twice"
+                )));
+            }
+            if builder.has_transport_options() {
+                return Err(FcmError::InvalidConfiguration(format!(
+                    "project {name:?} sets its own transport or HTTP options, set them on the MultiProjectClientBuilder"
+                )));
+            }
+            let client = builder.shared_transport(transport.clone()).build().await?;
+            clients.insert(name, client);
+        }
+
+        Ok(MultiProjectClient { clients })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{message, MockServer};
+    use crate::{Client, Error, HttpRequest, HttpResponse, HttpTransport, MultiProjectClient, TransportError};
+
+    /// Counts the requests passed on to the default transport.
+    #[derive(Default)]
+    struct CountingTransport {
+        requests: std::sync::atomic::AtomicUsize,
+        inner: crate::ReqwestTransport,
+    }
+
+    #[async_trait::async_trait]
+    impl HttpTransport for std::sync::Arc<CountingTransport> {
+        async fn execute(&self, request: HttpRequest) -> Result<HttpResponse, TransportError> {
+            self.requests.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            self.inner.execute(request).await
+        }
+    }
+
+    #[tokio::test]
+    async fn should_route_messages_to_projects() {
+        let brand_a = MockServer::start();
+        let brand_b = MockServer::start();
+        let transport = std::sync::Arc::new(CountingTransport::default());
+        let project = |server: &MockServer| {
+            Client::builder()
+                .service_account_key_path(&server.key_path().to_string_lossy())
+                .fcm_endpoint(server.url())
+        };
+
+        let client = MultiProjectClient::builder()
+            .project_with("brand-a", project(&brand_a))
+            .project_with("brand-b", project(&brand_b))
+            .transport(transport.clone())
+            .build()
+            .await
+            .unwrap();
+
+        client.send("brand-a", &message("a")).await.unwrap();
+        client.send("brand-b", &message("b1")).await.unwrap();
+        client.validate("brand-b", &message("b2")).await.unwrap();
+
+        assert_eq!(vec!["brand-a", "brand-b"], client.projects().collect::<Vec<_>>());
+        assert_eq!(1, brand_a.messages().len());
+        assert_eq!(2, brand_b.messages().len());
+        assert_eq!(1, brand_a.token_requests());
+        assert_eq!(1, brand_b.token_requests());
+        // a token and the messages per project
+        assert_eq!(5, transport.requests.load(std::sync::atomic::Ordering::SeqCst));
+        assert_eq!(
+            Err(Error::UnknownProject("brand-c".to_string())),
+            client.send("brand-c", &message("c")).await
+        );
+    }
+
+    #[tokio::test]
+    async fn should_share_http_options_between_projects() {
+        let server = MockServer::start();
+        let project = || {
+            Client::builder()
+                .service_account_key_path(&server.key_path().to_string_lossy())
+                .fcm_endpoint("http://fcm.invalid")
+        };
+
+        let client = MultiProjectClient::builder()
+            .project_with("brand-a", project())
+            .http_options(Client::builder().proxy(server.url()))
+            .build()
+            .await
+            .unwrap();
+        client.send("brand-a", &message("a")).await.unwrap();
+        assert_eq!(1, server.messages().len());
+
+        let result = MultiProjectClient::builder()
+            .project_with("brand-a", project().connect_timeout(std::time::Duration::from_secs(5)))
+            .build()
+            .await;
+        assert!(matches!(result, Err(Error::InvalidConfiguration(_))));
+    }
+}
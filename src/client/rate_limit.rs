@@ -0,0 +1,282 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::message::target::Target;
+
+/// Buckets of idle targets are dropped once this many are tracked.
+const MAX_IDLE_BUCKETS: usize = 10_000;
+
+/// A token bucket budget: a sustained rate plus the burst allowed on top of it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quota {
+    /// Number of messages allowed per minute.
+    pub per_minute: u32,
+
+    /// Number of messages that may be sent at once before the rate applies.
+    pub burst: u32,
+}
+
+impl Quota {
+    /// A quota of `per_minute` messages, with a burst of one second worth of messages.
+    pub fn per_minute(per_minute: u32) -> Quota {
+        Quota {
+            per_minute,
+            burst: (per_minute / 60).max(1),
+        }
+    }
+
+    /// Allow `burst` messages to be sent at once.
+    pub fn with_burst(self, burst: u32) -> Quota {
+        Quota {
+            burst: burst.max(1),
+            ..self
+        }
+    }
+}
+
+/// Budgets of the client side rate limiter, see [`ClientBuilder::rate_limit`](crate::ClientBuilder::rate_limit).
+///
+/// The default follows the documented FCM limits: 600,000 messages per minute for the project and
+/// 240 messages per minute to a single device.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RateLimit {
+    /// Budget shared by all messages of the project.
+    pub global: Option<Quota>,
+
+    /// Budget of each registration token.
+    pub per_token: Option<Quota>,
+
+    /// Budget of each topic.
+    pub per_topic: Option<Quota>,
+}
+
+impl Default for RateLimit {
+    fn default() -> RateLimit {
+        RateLimit {
+            global: Some(Quota::per_minute(600_000)),
+            per_token: Some(Quota::per_minute(240)),
+            per_topic: None,
+        }
+    }
+}
+
+/// How much sending was slowed down by the rate limiter.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RateLimiterStats {
+    /// Number of messages that had to wait for their budget.
+    pub throttled_requests: u64,
+
+    /// Total time messages spent waiting for their budget.
+    pub throttled_time: Duration,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    capacity: f64,
+    tokens: f64,
+    refill_per_sec: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn new(quota: Quota, now: Instant) -> Bucket {
+        Bucket {
+            capacity: f64::from(quota.burst),
+            tokens: f64::from(quota.burst),
+            refill_per_sec: f64::from(quota.per_minute.max(1)) / 60.0,
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        self.updated = now;
+    }
+
+    /// Take a token, returning how long to wait until it is actually available. Tokens may be
+    /// borrowed from the future, so concurrent callers are served in order.
+    fn reserve(&mut self, now: Instant) -> Duration {
+        self.refill(now);
+        self.tokens -= 1.0;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.refill_per_sec)
+        }
+    }
+
+    fn is_full(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        self.tokens >= self.capacity
+    }
+}
+
+/// Buckets per token or topic, idle ones are dropped when the map has grown to `sweep_at`.
+#[derive(Debug)]
+struct KeyedBuckets {
+    buckets: HashMap<String, Bucket>,
+    sweep_at: usize,
+}
+
+impl KeyedBuckets {
+    fn new() -> KeyedBuckets {
+        KeyedBuckets {
+            buckets: HashMap::new(),
+            sweep_at: MAX_IDLE_BUCKETS,
+        }
+    }
+
+    fn reserve(&mut self, quota: Quota, key: &str, now: Instant) -> Duration {
+        if self.buckets.len() >= self.sweep_at {
+            self.buckets.retain(|_, bucket| !bucket.is_full(now));
+            // sweep again only after as many buckets are added as survived, so a map full of busy
+            // buckets does not cost a full scan per message
+            self.sweep_at = (2 * self.buckets.len()).max(MAX_IDLE_BUCKETS);
+        }
+
+        self.buckets
+            .entry(key.to_string())
+            .or_insert_with(|| Bucket::new(quota, now))
+            .reserve(now)
+    }
+}
+
+#[derive(Debug)]
+struct Buckets {
+    global: Option<Bucket>,
+    tokens: KeyedBuckets,
+    topics: KeyedBuckets,
+}
+
+/// Token bucket rate limiter with a global budget plus per-token and per-topic budgets.
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    limit: RateLimit,
+    buckets: Mutex<Buckets>,
+    throttled_requests: AtomicU64,
+    throttled_nanos: AtomicU64,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(limit: RateLimit) -> RateLimiter {
+        let now = Instant::now();
+        RateLimiter {
+            buckets: Mutex::new(Buckets {
+                global: limit.global.map(|quota| Bucket::new(quota, now)),
+                tokens: KeyedBuckets::new(),
+                topics: KeyedBuckets::new(),
+            }),
+            limit,
+            throttled_requests: AtomicU64::new(0),
+            throttled_nanos: AtomicU64::new(0),
+        }
+    }
+
+    /// Wait until a message to `target` fits into every budget, returning how long that took.
+    pub(crate) async fn acquire(&self, target: &Target) -> Duration {
+        let wait = {
+            let now = Instant::now();
+            let mut buckets = self.buckets.lock().unwrap();
+            let buckets = &mut *buckets;
+
+            let global = buckets
+                .global
+                .as_mut()
+                .map(|bucket| bucket.reserve(now))
+                .unwrap_or_default();
+            let keyed = match (target, self.limit.per_token, self.limit.per_topic) {
+                (Target::Token(token), Some(quota), _) => buckets.tokens.reserve(quota, token, now),
+                (Target::Topic(topic), _, Some(quota)) => buckets.topics.reserve(quota, topic, now),
+                _ => Duration::ZERO,
+            };
+
+            global.max(keyed)
+        };
+
+        if wait > Duration::ZERO {
+            self.throttled_requests.fetch_add(1, Ordering::Relaxed);
+            self.throttled_nanos
+                .fetch_add(wait.as_nanos() as u64, Ordering::Relaxed);
+            tokio::time::sleep(wait).await;
+        }
+        wait
+    }
+
+    pub(crate) fn stats(&self) -> RateLimiterStats {
+        RateLimiterStats {
+            throttled_requests: self.throttled_requests.load(Ordering::Relaxed),
+            throttled_time: Duration::from_nanos(self.throttled_nanos.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_global_budget_smooths_bursts() {
+        let limiter = RateLimiter::new(RateLimit {
+            global: Some(Quota::per_minute(60).with_burst(2)),
+            per_token: None,
+            per_topic: None,
+        });
+        let target = Target::Token("token".to_string());
+        let start = Instant::now();
+
+        for _ in 0..4 {
+            limiter.acquire(&target).await;
+        }
+
+        // two messages fit into the burst, the other two wait one second each
+        assert_eq!(2, start.elapsed().as_secs());
+        assert_eq!(2, limiter.stats().throttled_requests);
+        assert_eq!(Duration::from_secs(2), limiter.stats().throttled_time);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_per_target_budgets_are_independent() {
+        let limiter = RateLimiter::new(RateLimit {
+            global: None,
+            per_token: Some(Quota::per_minute(1)),
+            per_topic: Some(Quota::per_minute(1)),
+        });
+        let start = Instant::now();
+
+        limiter.acquire(&Target::Token("a".to_string())).await;
+        limiter.acquire(&Target::Token("b".to_string())).await;
+        limiter.acquire(&Target::Topic("news".to_string())).await;
+        limiter.acquire(&Target::Condition("'a' in topics".to_string())).await;
+        assert_eq!(Duration::ZERO, start.elapsed());
+
+        limiter.acquire(&Target::Topic("news".to_string())).await;
+        assert_eq!(60, start.elapsed().as_secs());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_idle_buckets_are_swept_in_amortized_batches() {
+        let quota = Quota::per_minute(1);
+        let mut buckets = KeyedBuckets::new();
+        let now = Instant::now();
+
+        // every bucket is busy, so the first sweep keeps all of them and postpones the next one
+        for i in 0..=MAX_IDLE_BUCKETS {
+            buckets.reserve(quota, &i.to_string(), now);
+        }
+        assert_eq!(MAX_IDLE_BUCKETS + 1, buckets.buckets.len());
+        assert_eq!(2 * MAX_IDLE_BUCKETS, buckets.sweep_at);
+
+        // once the old buckets have refilled, the next sweep drops them
+        let later = now + Duration::from_secs(60);
+        for i in 0..MAX_IDLE_BUCKETS {
+            buckets.reserve(quota, &format!("new-{i}"), later);
+        }
+        assert_eq!(MAX_IDLE_BUCKETS, buckets.buckets.len());
+        assert!(buckets.buckets.keys().all(|key| key.starts_with("new-")));
+    }
+}
@@ -1,5 +1,6 @@
+use crate::client::transport::{TransportError, TransportErrorKind};
 use serde::Deserialize;
-use std::{error::Error, fmt, str::FromStr};
+use std::{error::Error, fmt, str::FromStr, sync::Arc};
 use time::format_description::well_known::Rfc2822;
 pub use time::{Duration, OffsetDateTime};
 
@@ -92,8 +93,107 @@ pub enum ErrorReason {
     InvalidApnsCredential,
 }
 
+/// Error codes of the FCM HTTP v1 API.
+/// Referred from [Firebase documentation](https://firebase.google.com/docs/reference/fcm/rest/v1/ErrorCode)
+#[derive(Deserialize, Debug, PartialEq, Eq, Hash, Copy, Clone)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    /// Request parameters were invalid, for example a malformed registration token or message field.
+    InvalidArgument,
+
+    /// The registration token is no longer valid, for example because the app was uninstalled.
+    Unregistered,
+
+    /// The authenticated sender id is different from the sender id of the registration token.
+    SenderIdMismatch,
+
+    /// Sending limit exceeded for the project, the device or the topic.
+    QuotaExceeded,
+
+    /// The server is overloaded.
+    Unavailable,
+
+    /// An unknown internal error occurred.
+    Internal,
+
+    /// The APNs certificate or web push auth key was invalid or missing.
+    ThirdPartyAuthError,
+
+    /// No more information is available about this error.
+    #[serde(other)]
+    UnspecifiedError,
+}
+
+impl ErrorCode {
+    /// The code as spelled by the FCM API, e.g. `"UNREGISTERED"`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::InvalidArgument => "INVALID_ARGUMENT",
+            ErrorCode::Unregistered => "UNREGISTERED",
+            ErrorCode::SenderIdMismatch => "SENDER_ID_MISMATCH",
+            ErrorCode::QuotaExceeded => "QUOTA_EXCEEDED",
+            ErrorCode::Unavailable => "UNAVAILABLE",
+            ErrorCode::Internal => "INTERNAL",
+            ErrorCode::ThirdPartyAuthError => "THIRD_PARTY_AUTH_ERROR",
+            ErrorCode::UnspecifiedError => "UNSPECIFIED_ERROR",
+        }
+    }
+}
+
+/// The error body returned by the FCM HTTP v1 API.
+#[derive(Deserialize, Debug)]
+pub(crate) struct ErrorResponse {
+    error: ErrorStatus,
+}
+
+#[derive(Deserialize, Debug)]
+struct ErrorStatus {
+    message: String,
+    #[serde(default)]
+    details: Vec<ErrorDetail>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ErrorDetail {
+    #[serde(rename = "errorCode")]
+    error_code: Option<ErrorCode>,
+    #[serde(rename = "fieldViolations", default)]
+    field_violations: Vec<FieldViolation>,
+}
+
+/// A field of the request that failed validation, from a `google.rpc.BadRequest` detail.
 #[derive(Deserialize, Debug)]
+struct FieldViolation {
+    field: String,
+}
+
+impl ErrorResponse {
+    pub(crate) fn code(&self) -> ErrorCode {
+        self.error
+            .details
+            .iter()
+            .find_map(|detail| detail.error_code)
+            .unwrap_or(ErrorCode::UnspecifiedError)
+    }
+
+    /// Whether validation of request `field`, e.g. `message.token`, failed.
+    pub(crate) fn violates(&self, field: &str) -> bool {
+        self.error
+            .details
+            .iter()
+            .flat_map(|detail| &detail.field_violations)
+            .any(|violation| violation.field == field)
+    }
+
+    pub(crate) fn message(self) -> String {
+        self.error.message
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
 pub struct FcmResponse {
+    /// The identifier of the message sent, in the format of `projects/*/messages/{message_id}`.
+    pub name: Option<String>,
     pub message_id: Option<u64>,
     pub error: Option<ErrorReason>,
     pub multicast_id: Option<i64>,
@@ -103,7 +203,7 @@ pub struct FcmResponse {
     pub results: Option<Vec<MessageResult>>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
 pub struct MessageResult {
     pub message_id: Option<String>,
     pub registration_id: Option<String>,
@@ -146,12 +246,136 @@ pub enum FcmError {
     /// Senders that cause problems risk being blacklisted.
     ServerError(Option<RetryAfter>),
 
+    /// Sending limit exceeded for the project, the device or the topic. Retry after the
+    /// [RetryAfter](enum.RetryAfter.html) value if included, using exponential back-off otherwise.
+    QuotaExceeded(Option<RetryAfter>),
+
+    /// The message was rejected by FCM with the given error code, for example `Unregistered` when the
+    /// registration token is no longer valid.
+    Rejected(ErrorCode, String),
+
+    /// FCM rejected the registration token as malformed (`INVALID_ARGUMENT` about `message.token`).
+    InvalidToken(String),
+
     ProjectIdError(String),
 
+    /// No project with the given name is registered with the
+    /// [`MultiProjectClient`](crate::MultiProjectClient).
+    UnknownProject(String),
+
+    /// The client cannot be built with the given settings.
+    InvalidConfiguration(String),
+
     AuthToken(String),
+
+    /// The circuit breaker is open after too many server errors, the message was not sent. See
+    /// [`ClientBuilder::circuit_breaker`](crate::ClientBuilder::circuit_breaker).
+    CircuitOpen,
+
+    /// The idempotency store failed, the message was not sent.
+    IdempotencyStore(String),
+
+    /// Another send with the given idempotency key is still running, the message was not sent.
+    SendInProgress(String),
+
+    /// The request timed out. The message may or may not have been delivered.
+    Timeout(TransportError),
+
+    /// No connection to the server could be established, including failed DNS lookups. The message
+    /// was not delivered.
+    Connect(TransportError),
+
+    /// The TLS handshake or certificate validation failed, usually a problem with the CA
+    /// configuration or an intercepting proxy.
+    Tls(TransportError),
+
+    /// The request failed otherwise without a complete response, e.g. because the connection was
+    /// closed. The message may or may not have been delivered.
+    Transport(TransportError),
+
+    /// FCM answered with a success status but a body that could not be decoded. The message was
+    /// most likely delivered, so it is not retried.
+    Decode(DecodeError),
+}
+
+/// A response body that could not be decoded, see [`FcmError::Decode`].
+///
+/// Errors compare equal by body and message, regardless of their source.
+#[derive(Clone, Debug)]
+pub struct DecodeError {
+    body: String,
+    source: Arc<serde_json::Error>,
+}
+
+impl DecodeError {
+    pub(crate) fn new(source: serde_json::Error, body: &[u8]) -> DecodeError {
+        DecodeError {
+            body: String::from_utf8_lossy(body).into_owned(),
+            source: Arc::new(source),
+        }
+    }
+
+    /// The body that could not be decoded.
+    pub fn body(&self) -> &str {
+        &self.body
+    }
+}
+
+impl PartialEq for DecodeError {
+    fn eq(&self, other: &DecodeError) -> bool {
+        self.body == other.body && self.source.to_string() == other.source.to_string()
+    }
 }
 
-impl Error for FcmError {}
+impl Error for DecodeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(self.source.as_ref())
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid response body: {}", self.source)
+    }
+}
+
+impl FcmError {
+    /// A short label of the error for logs and metrics, the FCM error code for rejected messages.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            FcmError::Unauthorized => "UNAUTHORIZED",
+            FcmError::InvalidMessage(_) => "INVALID_MESSAGE",
+            FcmError::ServerError(_) => "SERVER_ERROR",
+            FcmError::QuotaExceeded(_) => "QUOTA_EXCEEDED",
+            FcmError::Rejected(code, _) => code.as_str(),
+            FcmError::InvalidToken(_) => ErrorCode::InvalidArgument.as_str(),
+            FcmError::ProjectIdError(_) => "PROJECT_ID_ERROR",
+            FcmError::UnknownProject(_) => "UNKNOWN_PROJECT",
+            FcmError::InvalidConfiguration(_) => "INVALID_CONFIGURATION",
+            FcmError::AuthToken(_) => "AUTH_TOKEN",
+            FcmError::CircuitOpen => "CIRCUIT_OPEN",
+            FcmError::IdempotencyStore(_) => "IDEMPOTENCY_STORE",
+            FcmError::SendInProgress(_) => "SEND_IN_PROGRESS",
+            FcmError::Timeout(_) => "TIMEOUT",
+            FcmError::Connect(_) => "CONNECT",
+            FcmError::Tls(_) => "TLS",
+            FcmError::Transport(_) => "TRANSPORT",
+            FcmError::Decode(_) => "DECODE",
+        }
+    }
+}
+
+impl Error for FcmError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            FcmError::Timeout(error) | FcmError::Connect(error) | FcmError::Tls(error) | FcmError::Transport(error) => {
+                Some(error)
+            }
+            FcmError::Decode(error) => Some(error),
+            _ => None,
+        }
+    }
+}
 
 impl fmt::Display for FcmError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -159,15 +383,38 @@ impl fmt::Display for FcmError {
             FcmError::Unauthorized => write!(f, "authorization header missing or with invalid syntax in HTTP request"),
             FcmError::InvalidMessage(ref s) => write!(f, "invalid message {}", s),
             FcmError::ServerError(_) => write!(f, "the server couldn't process the request"),
+            FcmError::QuotaExceeded(_) => write!(f, "sending limit exceeded"),
+            FcmError::Rejected(code, message) => write!(f, "message rejected with {code:?}: {message}"),
+            FcmError::InvalidToken(message) => write!(f, "invalid registration token: {message}"),
             FcmError::ProjectIdError(error) => write!(f, "error getting project_id: {error}"),
+            FcmError::UnknownProject(name) => write!(f, "unknown project {name:?}"),
+            FcmError::InvalidConfiguration(error) => write!(f, "invalid configuration: {error}"),
             FcmError::AuthToken(error) => write!(f, "error getting auth token: {error}"),
+            FcmError::CircuitOpen => write!(f, "circuit breaker is open, FCM is failing"),
+            FcmError::IdempotencyStore(error) => write!(f, "idempotency store failed: {error}"),
+            FcmError::SendInProgress(key) => write!(f, "a message with idempotency key {key:?} is being sent"),
+            FcmError::Timeout(error) | FcmError::Connect(error) | FcmError::Tls(error) | FcmError::Transport(error) => {
+                write!(f, "{error}")
+            }
+            FcmError::Decode(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl From<TransportError> for FcmError {
+    fn from(error: TransportError) -> Self {
+        match error.kind {
+            TransportErrorKind::Timeout => Self::Timeout(error),
+            TransportErrorKind::Dns | TransportErrorKind::Connect => Self::Connect(error),
+            TransportErrorKind::Tls => Self::Tls(error),
+            TransportErrorKind::Other => Self::Transport(error),
         }
     }
 }
 
 impl From<reqwest::Error> for FcmError {
-    fn from(_: reqwest::Error) -> Self {
-        Self::ServerError(None)
+    fn from(error: reqwest::Error) -> Self {
+        TransportError::from(error).into()
     }
 }
 
@@ -227,12 +474,78 @@ mod tests {
             let response_string = serde_json::to_string(&response_data).unwrap();
             let fcm_response: FcmResponse = serde_json::from_str(&response_string).unwrap();
 
-            assert_eq!(Some(error_enum.clone()), fcm_response.results.unwrap()[0].error,);
+            assert_eq!(Some(error_enum), fcm_response.results.unwrap()[0].error,);
 
             assert_eq!(Some(error_enum), fcm_response.error,)
         }
     }
 
+    #[test]
+    fn test_field_violations_from_error_response() {
+        let response: ErrorResponse = serde_json::from_value(json!({
+            "error": {
+                "code": 400,
+                "message": "The registration token is not a valid FCM registration token",
+                "status": "INVALID_ARGUMENT",
+                "details": [
+                    {
+                        "@type": "type.googleapis.com/google.firebase.fcm.v1.FcmError",
+                        "errorCode": "INVALID_ARGUMENT"
+                    },
+                    {
+                        "@type": "type.googleapis.com/google.rpc.BadRequest",
+                        "fieldViolations": [
+                            {
+                                "field": "message.token",
+                                "description": "Invalid registration token"
+                            }
+                        ]
+                    }
+                ]
+            }
+        }))
+        .unwrap();
+
+        assert_eq!(ErrorCode::InvalidArgument, response.code());
+        assert!(response.violates("message.token"));
+        assert!(!response.violates("message.data"));
+    }
+
+    #[test]
+    fn test_error_code_from_error_response() {
+        let response: ErrorResponse = serde_json::from_value(json!({
+            "error": {
+                "code": 404,
+                "message": "Requested entity was not found.",
+                "status": "NOT_FOUND",
+                "details": [
+                    {
+                        "@type": "type.googleapis.com/google.firebase.fcm.v1.FcmError",
+                        "errorCode": "UNREGISTERED"
+                    }
+                ]
+            }
+        }))
+        .unwrap();
+
+        assert_eq!(ErrorCode::Unregistered, response.code());
+        assert_eq!("Requested entity was not found.", response.message());
+    }
+
+    #[test]
+    fn test_unknown_error_code() {
+        let response: ErrorResponse = serde_json::from_value(json!({
+            "error": {
+                "code": 418,
+                "message": "I'm a teapot",
+                "details": [{ "errorCode": "TEAPOT" }]
+            }
+        }))
+        .unwrap();
+
+        assert_eq!(ErrorCode::UnspecifiedError, response.code());
+    }
+
     #[test]
     fn test_retry_after_from_seconds() {
         assert_eq!(RetryAfter::Delay(Duration::seconds(420)), "420".parse().unwrap());
@@ -0,0 +1,253 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+
+use crate::client::response::{FcmError, FcmResponse};
+use crate::client::Client;
+use crate::Message;
+
+/// Anything that can send messages to FCM. Implemented by [`Client`], and by [`RecordingSender`] and
+/// [`ScriptedSender`] to substitute the network in tests.
+#[async_trait]
+pub trait Sender: Send + Sync {
+    /// Send a message.
+    async fn send(&self, message: &Message) -> Result<FcmResponse, FcmError>;
+
+    /// Validate a message without delivering it to any device.
+    async fn validate(&self, message: &Message) -> Result<FcmResponse, FcmError>;
+
+    /// Send a message as delivery `attempt`, counted from 1, of a caller that retries it. The
+    /// client traces the attempt; other senders just send.
+    #[doc(hidden)]
+    async fn send_attempt(&self, message: &Message, attempt: u32) -> Result<FcmResponse, FcmError> {
+        let _ = attempt;
+        self.send(message).await
+    }
+
+    /// Send every message, returning the results in the same order.
+    async fn send_all(&self, messages: &[Message]) -> Vec<Result<FcmResponse, FcmError>> {
+        let mut results = Vec::with_capacity(messages.len());
+        for message in messages {
+            results.push(self.send(message).await);
+        }
+        results
+    }
+}
+
+#[async_trait]
+impl Sender for Client {
+    async fn send(&self, message: &Message) -> Result<FcmResponse, FcmError> {
+        Client::send(self, message).await
+    }
+
+    async fn validate(&self, message: &Message) -> Result<FcmResponse, FcmError> {
+        Client::validate(self, message).await
+    }
+
+    async fn send_attempt(&self, message: &Message, attempt: u32) -> Result<FcmResponse, FcmError> {
+        Client::send_attempt(self, message, attempt).await
+    }
+
+    async fn send_all(&self, messages: &[Message]) -> Vec<Result<FcmResponse, FcmError>> {
+        Client::send_all(self, messages).await
+    }
+}
+
+/// The messages seen by a test sender.
+#[derive(Debug, Default)]
+struct Recorded {
+    sent: Vec<Message>,
+    validated: Vec<Message>,
+}
+
+impl Recorded {
+    fn record(&mut self, message: &Message, validate_only: bool) -> FcmResponse {
+        let messages = if validate_only {
+            &mut self.validated
+        } else {
+            &mut self.sent
+        };
+        messages.push(message.clone());
+
+        FcmResponse {
+            name: Some(format!("projects/recorded/messages/{}", messages.len())),
+            ..Default::default()
+        }
+    }
+}
+
+/// A `Sender` that records every message and accepts all of them.
+#[derive(Debug, Default)]
+pub struct RecordingSender {
+    recorded: Mutex<Recorded>,
+}
+
+impl RecordingSender {
+    /// Get a sender without recorded messages.
+    pub fn new() -> RecordingSender {
+        RecordingSender::default()
+    }
+
+    /// The messages sent so far.
+    pub fn messages(&self) -> Vec<Message> {
+        self.recorded.lock().unwrap().sent.clone()
+    }
+
+    /// The messages validated so far.
+    pub fn validated(&self) -> Vec<Message> {
+        self.recorded.lock().unwrap().validated.clone()
+    }
+}
+
+#[async_trait]
+impl Sender for RecordingSender {
+    async fn send(&self, message: &Message) -> Result<FcmResponse, FcmError> {
+        Ok(self.recorded.lock().unwrap().record(message, false))
+    }
+
+    async fn validate(&self, message: &Message) -> Result<FcmResponse, FcmError> {
+        Ok(self.recorded.lock().unwrap().record(message, true))
+    }
+}
+
+/// A `Sender` that records every message and answers with configured results, in order. Once all
+/// results are used up, messages are accepted.
+///
+/// ```rust
+/// # #[tokio::main]
+/// # async fn main() {
+/// use fcm::{ErrorCode, Message, ScriptedSender, Sender, Target};
+///
+/// let sender = ScriptedSender::new();
+/// sender.push_err(fcm::Error::Rejected(ErrorCode::Unregistered, "gone".to_string()));
+///
+/// let message = Message {
+///     data: None,
+///     notification: None,
+///     target: Target::Token("token".to_string()),
+///     android: None,
+///     webpush: None,
+///     apns: None,
+///     fcm_options: None,
+/// };
+///
+/// assert!(sender.send(&message).await.is_err());
+/// assert!(sender.send(&message).await.is_ok());
+/// assert_eq!(2, sender.messages().len());
+/// # }
+/// ```
+#[derive(Debug, Default)]
+pub struct ScriptedSender {
+    recorded: Mutex<Recorded>,
+    results: Mutex<VecDeque<Result<FcmResponse, FcmError>>>,
+}
+
+impl ScriptedSender {
+    /// Get a sender without scripted results.
+    pub fn new() -> ScriptedSender {
+        ScriptedSender::default()
+    }
+
+    /// Answer a following send or validation with `result`.
+    pub fn push(&self, result: Result<FcmResponse, FcmError>) {
+        self.results.lock().unwrap().push_back(result);
+    }
+
+    /// Answer a following send or validation with `response`.
+    pub fn push_ok(&self, response: FcmResponse) {
+        self.push(Ok(response));
+    }
+
+    /// Answer a following send or validation with `error`.
+    pub fn push_err(&self, error: FcmError) {
+        self.push(Err(error));
+    }
+
+    /// The messages sent so far.
+    pub fn messages(&self) -> Vec<Message> {
+        self.recorded.lock().unwrap().sent.clone()
+    }
+
+    /// The messages validated so far.
+    pub fn validated(&self) -> Vec<Message> {
+        self.recorded.lock().unwrap().validated.clone()
+    }
+
+    fn answer(&self, message: &Message, validate_only: bool) -> Result<FcmResponse, FcmError> {
+        let accepted = self.recorded.lock().unwrap().record(message, validate_only);
+        self.results.lock().unwrap().pop_front().unwrap_or(Ok(accepted))
+    }
+}
+
+#[async_trait]
+impl Sender for ScriptedSender {
+    async fn send(&self, message: &Message) -> Result<FcmResponse, FcmError> {
+        self.answer(message, false)
+    }
+
+    async fn validate(&self, message: &Message) -> Result<FcmResponse, FcmError> {
+        self.answer(message, true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{message, MockServer};
+    use crate::ErrorCode;
+
+    async fn notify(sender: &dyn Sender, tokens: &[&str]) -> Vec<Result<FcmResponse, FcmError>> {
+        let messages: Vec<Message> = tokens.iter().map(|token| message(token)).collect();
+        sender.send_all(&messages).await
+    }
+
+    #[tokio::test]
+    async fn test_recording_sender() {
+        let sender = RecordingSender::new();
+
+        let results = notify(&sender, &["a", "b"]).await;
+        sender.validate(&message("c")).await.unwrap();
+
+        assert_eq!(
+            Some("projects/recorded/messages/2".to_string()),
+            results[1].as_ref().unwrap().name
+        );
+        assert_eq!(vec![message("a"), message("b")], sender.messages());
+        assert_eq!(vec![message("c")], sender.validated());
+    }
+
+    #[tokio::test]
+    async fn test_scripted_sender() {
+        let sender = ScriptedSender::new();
+        sender.push_err(FcmError::Rejected(ErrorCode::Unregistered, "gone".to_string()));
+        sender.push_ok(FcmResponse {
+            name: Some("projects/p/messages/42".to_string()),
+            ..Default::default()
+        });
+
+        let results = notify(&sender, &["a", "b", "c"]).await;
+
+        assert_eq!(
+            Err(FcmError::Rejected(ErrorCode::Unregistered, "gone".to_string())),
+            results[0]
+        );
+        assert_eq!(
+            Some("projects/p/messages/42".to_string()),
+            results[1].as_ref().unwrap().name
+        );
+        assert!(results[2].is_ok());
+        assert_eq!(3, sender.messages().len());
+    }
+
+    #[tokio::test]
+    async fn test_client_as_sender() {
+        let server = MockServer::start();
+        let client = server.client().await.unwrap();
+
+        let results = notify(&client, &["a", "b"]).await;
+
+        assert!(results.iter().all(Result::is_ok));
+        assert_eq!(2, server.messages().len());
+    }
+}
@@ -0,0 +1,579 @@
+use crate::testing::{message, MockResponse, MockServer, PROJECT_ID};
+use crate::{
+    outcome_label, AdaptiveConcurrency, CircuitBreakerConfig, CircuitState, Client, Error, ErrorClass, ErrorCode,
+    FcmResponse, HttpRequest, HttpResponse, HttpTransport, Message, Metrics, Middleware, Quota, RateLimit, SetHeader,
+    TokenInvalidReason, TransportError, TransportErrorKind,
+};
+use serde_json::json;
+
+#[tokio::test]
+async fn should_pause_batches_on_retry_after() {
+    let server = MockServer::start();
+    server.enqueue(MockResponse::quota_exceeded(Some(1)));
+    let client = Client::builder()
+        .service_account_key_path(&server.key_path().to_string_lossy())
+        .fcm_endpoint(server.url())
+        .adaptive_concurrency(AdaptiveConcurrency::fixed(1))
+        .build()
+        .await
+        .unwrap();
+
+    let messages: Vec<Message> = (0..3).map(|i| message(&format!("token{i}"))).collect();
+    let start = std::time::Instant::now();
+    let results = client.send_all(&messages).await;
+
+    assert!(start.elapsed() >= std::time::Duration::from_secs(1));
+    assert!(matches!(results[0], Err(Error::QuotaExceeded(_))));
+    assert!(results[1..].iter().all(Result::is_ok));
+
+    let tokens: Vec<_> = server
+        .messages()
+        .into_iter()
+        .map(|m| m.message["token"].clone())
+        .collect();
+    assert_eq!(vec![json!("token0"), json!("token1"), json!("token2")], tokens);
+}
+
+/// Records the outcome of every send.
+struct RecordOutcomes(std::sync::Arc<std::sync::Mutex<Vec<&'static str>>>);
+
+impl Middleware for RecordOutcomes {
+    fn on_response(&self, _message: &Message, result: &Result<FcmResponse, Error>) {
+        self.0.lock().unwrap().push(outcome_label(result));
+    }
+}
+
+#[tokio::test]
+async fn should_fail_fast_when_circuit_is_open() {
+    let server = MockServer::start();
+    server.enqueue(MockResponse::unavailable());
+    server.enqueue(MockResponse::unavailable());
+    let states = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let recorded = std::sync::Arc::clone(&states);
+    let outcomes = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let client = Client::builder()
+        .service_account_key_path(&server.key_path().to_string_lossy())
+        .fcm_endpoint(server.url())
+        .circuit_breaker(CircuitBreakerConfig {
+            window_size: 2,
+            minimum_requests: 2,
+            open_duration: std::time::Duration::from_millis(100),
+            half_open_probes: 1,
+            ..Default::default()
+        })
+        .on_circuit_state_change(move |state| recorded.lock().unwrap().push(state))
+        .middleware(RecordOutcomes(std::sync::Arc::clone(&outcomes)))
+        .build()
+        .await
+        .unwrap();
+
+    assert!(client.send(&message("token")).await.is_err());
+    assert!(client.send(&message("token")).await.is_err());
+    assert_eq!(Some(CircuitState::Open), client.circuit_state());
+    assert_eq!(
+        Err(Error::CircuitOpen),
+        client.send(&message("token")).await.map(|_| ())
+    );
+    assert_eq!(2, server.messages().len());
+    assert_eq!(
+        vec!["SERVER_ERROR", "SERVER_ERROR", "CIRCUIT_OPEN"],
+        *outcomes.lock().unwrap()
+    );
+
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    assert!(client.send(&message("token")).await.is_ok());
+    assert_eq!(Some(CircuitState::Closed), client.circuit_state());
+    assert_eq!(
+        vec![CircuitState::Open, CircuitState::HalfOpen, CircuitState::Closed],
+        *states.lock().unwrap()
+    );
+}
+
+#[derive(Default)]
+struct CountingMetrics {
+    outcomes: std::sync::Mutex<Vec<&'static str>>,
+    payload_bytes: std::sync::atomic::AtomicUsize,
+    token_refreshes: std::sync::atomic::AtomicUsize,
+    throttled: std::sync::Mutex<Vec<std::time::Duration>>,
+    iid_requests: std::sync::Mutex<Vec<(&'static str, &'static str)>>,
+}
+
+impl Metrics for std::sync::Arc<CountingMetrics> {
+    fn message_sent(&self, result: &Result<FcmResponse, Error>, _latency: std::time::Duration) {
+        self.outcomes.lock().unwrap().push(outcome_label(result));
+    }
+
+    fn payload_size(&self, bytes: usize) {
+        self.payload_bytes
+            .fetch_add(bytes, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    fn token_refreshed(&self) {
+        self.token_refreshes.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    fn throttled(&self, wait: std::time::Duration) {
+        self.throttled.lock().unwrap().push(wait);
+    }
+
+    fn iid_request(&self, operation: &'static str, outcome: &'static str, _latency: std::time::Duration) {
+        self.iid_requests.lock().unwrap().push((operation, outcome));
+    }
+}
+
+#[tokio::test]
+async fn should_report_metrics() {
+    let server = MockServer::start();
+    server.enqueue(MockResponse::unregistered());
+    let metrics = std::sync::Arc::new(CountingMetrics::default());
+    let client = Client::builder()
+        .service_account_key_path(&server.key_path().to_string_lossy())
+        .fcm_endpoint(server.url())
+        .metrics(std::sync::Arc::clone(&metrics))
+        .rate_limit(RateLimit {
+            global: Some(Quota::per_minute(600).with_burst(1)),
+            per_token: None,
+            per_topic: None,
+        })
+        .build()
+        .await
+        .unwrap();
+
+    let _ = client.send(&message("token")).await;
+    let _ = client.send(&message("token")).await;
+
+    assert_eq!(vec!["UNREGISTERED", "success"], *metrics.outcomes.lock().unwrap());
+    assert_eq!(
+        2 * message("token").to_request_bytes(false).unwrap().len(),
+        metrics.payload_bytes.load(std::sync::atomic::Ordering::Relaxed)
+    );
+    assert_eq!(1, metrics.token_refreshes.load(std::sync::atomic::Ordering::Relaxed));
+
+    // the second message waits for the budget, a tenth of a second at 600 per minute
+    let throttled = metrics.throttled.lock().unwrap();
+    assert_eq!(1, throttled.len());
+    assert!(throttled[0] <= std::time::Duration::from_millis(100));
+}
+
+struct AddData(&'static str);
+
+impl Middleware for AddData {
+    fn on_message(&self, message: &mut Message) {
+        message.data.get_or_insert_with(|| json!({}))[self.0] = json!("set");
+    }
+
+    fn on_response(&self, message: &Message, result: &Result<FcmResponse, Error>) {
+        assert!(message.data.as_ref().unwrap().get(self.0).is_some());
+        assert!(result.is_ok());
+    }
+}
+
+#[tokio::test]
+async fn should_run_middleware_in_order() {
+    let server = MockServer::start();
+    let client = Client::builder()
+        .service_account_key_path(&server.key_path().to_string_lossy())
+        .fcm_endpoint(server.url())
+        .middleware(AddData("tenant"))
+        .middleware(SetHeader::new("X-Goog-User-Project", "billing-project").unwrap())
+        .middleware(AddData("region"))
+        .build()
+        .await
+        .unwrap();
+
+    client.send(&message("token")).await.unwrap();
+
+    let received = &server.messages()[0];
+    assert_eq!(
+        json!({ "foo": "bar", "tenant": "set", "region": "set" }),
+        received.message["data"]
+    );
+    assert_eq!(
+        Some(&"billing-project".to_string()),
+        received.headers.get("x-goog-user-project")
+    );
+}
+
+#[tokio::test]
+async fn should_report_invalid_tokens() {
+    let server = MockServer::start();
+    server.enqueue(MockResponse::unregistered());
+    server.enqueue(MockResponse::success());
+    server.enqueue(MockResponse::invalid_token());
+    server.enqueue(MockResponse::sender_id_mismatch());
+    server.enqueue(MockResponse::unavailable());
+    let invalid = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let recorded = std::sync::Arc::clone(&invalid);
+    let client = Client::builder()
+        .service_account_key_path(&server.key_path().to_string_lossy())
+        .fcm_endpoint(server.url())
+        .adaptive_concurrency(AdaptiveConcurrency::fixed(1))
+        .on_token_invalid(move |token, reason| recorded.lock().unwrap().push((token.to_string(), reason)))
+        .build()
+        .await
+        .unwrap();
+
+    let tokens: Vec<String> = (0..5).map(|i| format!("token{i}")).collect();
+    let results = client.send_multicast(&message("ignored"), &tokens).await;
+
+    assert_eq!(5, results.len());
+    assert!(results[1].is_ok());
+    assert_eq!(
+        vec![
+            ("token0".to_string(), TokenInvalidReason::Unregistered),
+            ("token2".to_string(), TokenInvalidReason::InvalidFormat),
+            ("token3".to_string(), TokenInvalidReason::SenderIdMismatch),
+        ],
+        *invalid.lock().unwrap()
+    );
+}
+
+#[tokio::test]
+async fn should_suppress_duplicate_idempotent_sends() {
+    let server = MockServer::start();
+    server.enqueue(MockResponse::unavailable());
+    let client = server.client().await.unwrap();
+    let mut notification = message("token");
+    notification.notification = Some(crate::Notification {
+        title: Some("Reminder".to_string()),
+        ..Default::default()
+    });
+
+    assert!(client.send_idempotent("reminder-42", &notification).await.is_err());
+    let first = client.send_idempotent("reminder-42", &notification).await.unwrap();
+    let second = client.send_idempotent("reminder-42", &notification).await.unwrap();
+    client.send_idempotent("reminder-43", &notification).await.unwrap();
+
+    assert_eq!(first.name, second.name);
+    let messages = server.messages();
+    assert_eq!(3, messages.len());
+    assert_eq!(
+        json!("reminder-42"),
+        messages[1].message["android"]["notification"]["tag"]
+    );
+    assert_eq!(
+        json!("reminder-42"),
+        messages[1].message["apns"]["headers"]["apns-collapse-id"]
+    );
+    assert_eq!(
+        json!("reminder-43"),
+        messages[2].message["android"]["notification"]["tag"]
+    );
+    assert!(matches!(
+        client.send_idempotent(&"x".repeat(65), &notification).await,
+        Err(Error::InvalidMessage(_))
+    ));
+}
+
+#[tokio::test]
+async fn should_send_concurrent_idempotent_sends_once() {
+    let server = MockServer::start();
+    let client = server.client().await.unwrap();
+    let notification = message("token");
+
+    let (first, second) = tokio::join!(
+        client.send_idempotent("reminder-42", &notification),
+        client.send_idempotent("reminder-42", &notification)
+    );
+
+    assert!(first.is_ok());
+    assert_eq!(Err(Error::SendInProgress("reminder-42".to_string())), second);
+    assert_eq!(1, server.messages().len());
+    assert_eq!(
+        first.unwrap().name,
+        client.send_idempotent("reminder-42", &notification).await.unwrap().name
+    );
+    assert_eq!(1, server.messages().len());
+}
+
+#[tokio::test]
+async fn should_manage_topic_subscriptions() {
+    let server = MockServer::start();
+    let client = server.client().await.unwrap();
+    let tokens = vec!["a".to_string(), "b".to_string()];
+
+    let response = client.subscribe("news", &tokens).await.unwrap();
+    assert_eq!(2, response.success_count);
+    assert_eq!(vec!["a".to_string(), "b".to_string()], server.subscribers("news"));
+
+    client.unsubscribe("/topics/news", &tokens[..1]).await.unwrap();
+    assert_eq!(vec!["b".to_string()], server.subscribers("news"));
+
+    let info = client.token_info("b").await.unwrap();
+    assert_eq!(vec!["news".to_string()], info.topics);
+    assert_eq!(Some("ANDROID".to_string()), info.platform);
+
+    let odd_token = "c:d/e?f g".to_string();
+    client
+        .subscribe("news", std::slice::from_ref(&odd_token))
+        .await
+        .unwrap();
+    assert_eq!(
+        vec!["news".to_string()],
+        client.token_info(&odd_token).await.unwrap().topics
+    );
+
+    assert!(matches!(
+        client.token_info("unknown").await,
+        Err(Error::Rejected(ErrorCode::Unregistered, _))
+    ));
+    let misconfigured = Client::builder()
+        .service_account_key_path(&server.key_path().to_string_lossy())
+        .iid_endpoint(&format!("{}/wrong", server.url()))
+        .build()
+        .await
+        .unwrap();
+    assert!(matches!(
+        misconfigured.token_info("b").await,
+        Err(Error::InvalidMessage(_))
+    ));
+
+    assert!(matches!(
+        client.subscribe("news", &[]).await,
+        Err(Error::InvalidMessage(_))
+    ));
+}
+
+/// Records the `X-Goog-User-Project` header of every request before passing it on.
+#[derive(Default)]
+struct UserProjectTransport {
+    user_projects: std::sync::Mutex<Vec<Option<String>>>,
+    inner: crate::ReqwestTransport,
+}
+
+#[async_trait::async_trait]
+impl HttpTransport for std::sync::Arc<UserProjectTransport> {
+    async fn execute(&self, request: HttpRequest) -> Result<HttpResponse, TransportError> {
+        let user_project = request
+            .headers
+            .get("x-goog-user-project")
+            .map(|value| value.to_str().unwrap().to_string());
+        self.user_projects.lock().unwrap().push(user_project);
+        self.inner.execute(request).await
+    }
+}
+
+#[tokio::test]
+async fn should_run_middleware_and_metrics_for_topic_requests() {
+    let server = MockServer::start();
+    let transport = std::sync::Arc::new(UserProjectTransport::default());
+    let metrics = std::sync::Arc::new(CountingMetrics::default());
+    let client = Client::builder()
+        .service_account_key_path(&server.key_path().to_string_lossy())
+        .iid_endpoint(server.url())
+        .transport(transport.clone())
+        .middleware(SetHeader::new("X-Goog-User-Project", "billing-project").unwrap())
+        .metrics(std::sync::Arc::clone(&metrics))
+        .build()
+        .await
+        .unwrap();
+
+    client.subscribe("news", &["a".to_string()]).await.unwrap();
+    client.token_info("a").await.unwrap();
+
+    assert_eq!(
+        vec![
+            None,
+            Some("billing-project".to_string()),
+            Some("billing-project".to_string())
+        ],
+        *transport.user_projects.lock().unwrap()
+    );
+    assert_eq!(
+        vec![("batchAdd", "success"), ("info", "success")],
+        *metrics.iid_requests.lock().unwrap()
+    );
+}
+
+/// Hands out access tokens, fails the first other request with a timeout, accepts the second and
+/// answers garbage to the others, recording their URLs.
+#[derive(Default)]
+struct FlakyTransport {
+    urls: std::sync::Mutex<Vec<String>>,
+}
+
+#[async_trait::async_trait]
+impl HttpTransport for std::sync::Arc<FlakyTransport> {
+    async fn execute(&self, request: HttpRequest) -> Result<HttpResponse, TransportError> {
+        if request.url.ends_with("/token") {
+            return Ok(HttpResponse {
+                status: reqwest::StatusCode::OK,
+                headers: Default::default(),
+                body: br#"{"access_token": "in-memory", "expires_in": 3600}"#.to_vec(),
+            });
+        }
+
+        let mut urls = self.urls.lock().unwrap();
+        urls.push(request.url);
+        let body = match urls.len() {
+            1 => return Err(TransportError::new(TransportErrorKind::Timeout, "operation timed out")),
+            2 => br#"{"name": "projects/in-memory/messages/1"}"#.to_vec(),
+            _ => b"<html>".to_vec(),
+        };
+
+        Ok(HttpResponse {
+            status: reqwest::StatusCode::OK,
+            headers: Default::default(),
+            body,
+        })
+    }
+}
+
+#[tokio::test]
+async fn should_send_through_a_custom_transport() {
+    let server = MockServer::start();
+    let transport = std::sync::Arc::new(FlakyTransport::default());
+    let client = Client::builder()
+        .service_account_key_path(&server.key_path().to_string_lossy())
+        .transport(transport.clone())
+        .build()
+        .await
+        .unwrap();
+
+    let error = client.send(&message("token")).await.unwrap_err();
+    assert_eq!(
+        Error::Timeout(TransportError::new(TransportErrorKind::Timeout, "operation timed out")),
+        error
+    );
+    assert_eq!(ErrorClass::Transient, error.class());
+    assert!(error.is_retryable());
+
+    let response = client.send(&message("token")).await.unwrap();
+    assert_eq!(Some("projects/in-memory/messages/1".to_string()), response.name);
+    assert_eq!(
+        format!("https://fcm.googleapis.com/v1/projects/{PROJECT_ID}/messages:send"),
+        transport.urls.lock().unwrap()[1]
+    );
+    assert!(server.messages().is_empty());
+
+    match client.send(&message("token")).await {
+        Err(Error::Decode(error)) => {
+            assert_eq!("<html>", error.body());
+            assert_eq!(ErrorClass::Unconfirmed, Error::Decode(error.clone()).class());
+            assert!(std::error::Error::source(&Error::Decode(error)).is_some());
+        }
+        result => panic!("expected a decode error, got {:?}", result),
+    }
+}
+
+/// Hands out a first access token that is already due for renewal, then times out on the token
+/// endpoint, counting the other requests.
+#[derive(Default)]
+struct TokenOutage {
+    tokens: std::sync::Mutex<usize>,
+    requests: std::sync::Mutex<usize>,
+}
+
+#[async_trait::async_trait]
+impl HttpTransport for std::sync::Arc<TokenOutage> {
+    async fn execute(&self, request: HttpRequest) -> Result<HttpResponse, TransportError> {
+        if !request.url.ends_with("/token") {
+            *self.requests.lock().unwrap() += 1;
+            return Err(TransportError::new(TransportErrorKind::Other, "unexpected request"));
+        }
+
+        let mut tokens = self.tokens.lock().unwrap();
+        *tokens += 1;
+        if *tokens > 1 {
+            return Err(TransportError::new(
+                TransportErrorKind::Timeout,
+                "token endpoint timed out",
+            ));
+        }
+        Ok(HttpResponse {
+            status: reqwest::StatusCode::OK,
+            headers: Default::default(),
+            body: br#"{"access_token": "short-lived", "expires_in": 10}"#.to_vec(),
+        })
+    }
+}
+
+#[tokio::test]
+async fn should_report_token_endpoint_timeouts_as_retryable() {
+    let server = MockServer::start();
+    let transport = std::sync::Arc::new(TokenOutage::default());
+    let client = Client::builder()
+        .service_account_key_path(&server.key_path().to_string_lossy())
+        .transport(transport.clone())
+        .build()
+        .await
+        .unwrap();
+
+    let timeout = Error::Timeout(TransportError::new(
+        TransportErrorKind::Timeout,
+        "token endpoint timed out",
+    ));
+    let error = client.send(&message("token")).await.unwrap_err();
+    assert_eq!(timeout, error);
+    assert_eq!(ErrorClass::Transient, error.class());
+    assert!(error.is_retryable());
+
+    assert_eq!(Err(timeout), client.token_info("token").await.map(|_| ()));
+    assert_eq!(0, *transport.requests.lock().unwrap());
+}
+
+#[tokio::test]
+async fn should_keep_idempotency_keys_reserved_after_a_timeout() {
+    let server = MockServer::start();
+    let transport = std::sync::Arc::new(FlakyTransport::default());
+    let client = Client::builder()
+        .service_account_key_path(&server.key_path().to_string_lossy())
+        .transport(transport.clone())
+        .build()
+        .await
+        .unwrap();
+
+    assert!(matches!(
+        client.send_idempotent("reminder-42", &message("token")).await,
+        Err(Error::Timeout(_))
+    ));
+    assert_eq!(
+        Err(Error::SendInProgress("reminder-42".to_string())),
+        client.send_idempotent("reminder-42", &message("token")).await
+    );
+    assert_eq!(1, transport.urls.lock().unwrap().len());
+}
+
+#[tokio::test]
+async fn should_send_through_a_proxy() {
+    let server = MockServer::start();
+    // only reachable through the proxy
+    let mut key: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(server.key_path()).unwrap()).unwrap();
+    key["token_uri"] = json!("http://oauth.invalid/token");
+    let key_path = server.key_path().with_extension("proxied.json");
+    std::fs::write(&key_path, key.to_string()).unwrap();
+
+    let builder = Client::builder()
+        .service_account_key_path(&key_path.to_string_lossy())
+        .fcm_endpoint("http://fcm.invalid")
+        .proxy(server.url())
+        .proxy_credentials("egress", "secret")
+        .connect_timeout(std::time::Duration::from_secs(5));
+    assert!(!format!("{builder:?}").contains("secret"));
+    let client = builder.build().await.unwrap();
+
+    client.send(&message("token")).await.unwrap();
+
+    let received = &server.messages()[0];
+    assert_eq!(PROJECT_ID, received.project_id);
+    assert_eq!(
+        Some(&"Basic ZWdyZXNzOnNlY3JldA==".to_string()),
+        received.headers.get("proxy-authorization")
+    );
+    assert_eq!(1, server.token_requests());
+    let _ = std::fs::remove_file(&key_path);
+}
+
+#[cfg(any(feature = "native-tls", feature = "rustls", feature = "vendored-tls"))]
+#[tokio::test]
+async fn should_reject_invalid_root_certificates() {
+    let server = MockServer::start();
+    let result = Client::builder()
+        .service_account_key_path(&server.key_path().to_string_lossy())
+        .add_root_certificate_pem(b"-----BEGIN CERTIFICATE-----\nnot base64\n-----END CERTIFICATE-----\n")
+        .build()
+        .await;
+
+    assert!(result.is_err());
+}
@@ -0,0 +1,243 @@
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE, RETRY_AFTER};
+use reqwest::{Method, StatusCode};
+use serde_json::{json, Value};
+
+use tokio::time::Instant;
+
+use crate::client::metrics::outcome_label;
+use crate::client::response::{DecodeError, ErrorCode, FcmError, RetryAfter};
+use crate::client::trace::{SendSpan, Stage};
+use crate::client::transport::HttpRequest;
+use crate::client::{bearer, Client};
+
+/// Most registration tokens accepted by one topic management request.
+const MAX_TOKENS_PER_REQUEST: usize = 1000;
+
+/// A registration token the instance id API could not (un)subscribe.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TopicManagementError {
+    /// Position of the token in the request.
+    pub index: usize,
+
+    /// The registration token.
+    pub token: String,
+
+    /// The reason given by the API, e.g. `NOT_FOUND` or `INVALID_ARGUMENT`.
+    pub reason: String,
+}
+
+/// The outcome of subscribing tokens to, or unsubscribing them from, a topic.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TopicManagementResponse {
+    /// Number of tokens that were (un)subscribed.
+    pub success_count: usize,
+
+    /// The tokens that failed.
+    pub errors: Vec<TopicManagementError>,
+}
+
+/// What the instance id API knows about a registration token.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TokenInfo {
+    /// Package name or bundle id of the app the token belongs to.
+    pub application: Option<String>,
+
+    /// Version of the app.
+    pub application_version: Option<String>,
+
+    /// Project number or sender id authorized to send to the token.
+    pub authorized_entity: Option<String>,
+
+    /// `ANDROID`, `IOS`, `CHROME` etc.
+    pub platform: Option<String>,
+
+    /// Topics the token is subscribed to.
+    pub topics: Vec<String>,
+}
+
+/// `segment` percent-encoded for a URL path, e.g. a registration token.
+fn path_segment(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b':' => char::from(byte).to_string(),
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+/// Whether a 404 body of the instance id API says that it does not know the token, as opposed to
+/// e.g. a wrong endpoint.
+fn is_unknown_token(body: &[u8]) -> bool {
+    serde_json::from_slice::<Value>(body)
+        .ok()
+        .and_then(|body| {
+            body["error"]
+                .as_str()
+                .map(|error| error.starts_with("No information found"))
+        })
+        .unwrap_or(false)
+}
+
+fn topic_path(topic: &str) -> String {
+    if topic.starts_with("/topics/") {
+        topic.to_string()
+    } else {
+        format!("/topics/{topic}")
+    }
+}
+
+impl Client {
+    /// Subscribe up to 1000 registration tokens to `topic`.
+    pub async fn subscribe(&self, topic: &str, tokens: &[String]) -> Result<TopicManagementResponse, FcmError> {
+        self.manage_topic("batchAdd", topic, tokens).await
+    }
+
+    /// Unsubscribe up to 1000 registration tokens from `topic`.
+    pub async fn unsubscribe(&self, topic: &str, tokens: &[String]) -> Result<TopicManagementResponse, FcmError> {
+        self.manage_topic("batchRemove", topic, tokens).await
+    }
+
+    /// Look up the app, platform and topic subscriptions of a registration token. Fails with
+    /// `Rejected(Unregistered, _)` if the token is unknown.
+    pub async fn token_info(&self, token: &str) -> Result<TokenInfo, FcmError> {
+        let url = format!("{}/iid/info/{}?details=true", self.iid_endpoint, path_segment(token));
+        let body = self.iid_request("info", Method::GET, &url, None).await?;
+
+        let text = |field: &str| body[field].as_str().map(str::to_string);
+        Ok(TokenInfo {
+            application: text("application"),
+            application_version: text("applicationVersion"),
+            authorized_entity: text("authorizedEntity"),
+            platform: text("platform"),
+            topics: body["rel"]["topics"]
+                .as_object()
+                .map(|topics| topics.keys().cloned().collect())
+                .unwrap_or_default(),
+        })
+    }
+
+    async fn manage_topic(
+        &self,
+        operation: &'static str,
+        topic: &str,
+        tokens: &[String],
+    ) -> Result<TopicManagementResponse, FcmError> {
+        if tokens.is_empty() || tokens.len() > MAX_TOKENS_PER_REQUEST {
+            return Err(FcmError::InvalidMessage(format!(
+                "between 1 and {MAX_TOKENS_PER_REQUEST} registration tokens are required"
+            )));
+        }
+
+        // https://developers.google.com/instance-id/reference/server#manage_relationship_maps_for_multiple_app_instances
+        let url = format!("{}/iid/v1:{}", self.iid_endpoint, operation);
+        let request = json!({ "to": topic_path(topic), "registration_tokens": tokens });
+        let body = self.iid_request(operation, Method::POST, &url, Some(request)).await?;
+
+        let mut response = TopicManagementResponse::default();
+        let results = body["results"].as_array().cloned().unwrap_or_default();
+        for (index, result) in results.iter().enumerate() {
+            match result["error"].as_str() {
+                Some(reason) => response.errors.push(TopicManagementError {
+                    index,
+                    token: tokens.get(index).cloned().unwrap_or_default(),
+                    reason: reason.to_string(),
+                }),
+                None => response.success_count += 1,
+            }
+        }
+        Ok(response)
+    }
+
+    /// Send a request to the instance id API through the middleware, circuit breaker, tracing and
+    /// metrics, like a message.
+    async fn iid_request(
+        &self,
+        operation: &'static str,
+        method: Method,
+        url: &str,
+        body: Option<Value>,
+    ) -> Result<Value, FcmError> {
+        let start = Instant::now();
+        let result = self.iid_post(operation, method, url, body).await;
+        if let Some(metrics) = &self.metrics {
+            metrics.iid_request(operation, outcome_label(&result), start.elapsed());
+        }
+        result
+    }
+
+    async fn iid_post(
+        &self,
+        operation: &'static str,
+        method: Method,
+        url: &str,
+        body: Option<Value>,
+    ) -> Result<Value, FcmError> {
+        let permit = match &self.circuit_breaker {
+            Some(circuit_breaker) => Some(circuit_breaker.acquire()?),
+            None => None,
+        };
+
+        let span = SendSpan::topic_request(&self.project_id, operation);
+        let result = self.iid_traced(method, url, body, &span).await;
+        span.finish(result.as_ref().map(|_| None));
+
+        if let (Some(circuit_breaker), Some(permit)) = (&self.circuit_breaker, permit) {
+            circuit_breaker.record(permit, &result);
+        }
+        result
+    }
+
+    async fn iid_traced(
+        &self,
+        method: Method,
+        url: &str,
+        body: Option<Value>,
+        span: &SendSpan,
+    ) -> Result<Value, FcmError> {
+        let auth_token = span.stage(Stage::AuthToken, self.get_auth_token()).await?;
+
+        let mut request = HttpRequest {
+            method,
+            url: url.to_string(),
+            headers: HeaderMap::new(),
+            body: Vec::new(),
+        };
+        request.headers.insert(AUTHORIZATION, bearer(&auth_token)?);
+        request
+            .headers
+            .insert("access_token_auth", HeaderValue::from_static("true"));
+        if let Some(body) = body {
+            request
+                .headers
+                .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+            request.body = serde_json::to_vec(&body).map_err(|err| FcmError::InvalidMessage(err.to_string()))?;
+        }
+        for middleware in &self.middleware {
+            middleware.on_request(&mut request);
+        }
+
+        let response = span
+            .stage(Stage::Http, self.transport.execute(request))
+            .await
+            .map_err(FcmError::from)?;
+
+        span.record_status(response.status.as_u16());
+
+        let retry_after = response
+            .header(RETRY_AFTER.as_str())
+            .and_then(|ra| ra.parse::<RetryAfter>().ok());
+
+        span.in_stage(Stage::Parse, || match response.status {
+            StatusCode::OK => serde_json::from_slice(&response.body)
+                .map_err(|err| FcmError::Decode(DecodeError::new(err, &response.body))),
+            StatusCode::UNAUTHORIZED => Err(FcmError::Unauthorized),
+            StatusCode::NOT_FOUND if is_unknown_token(&response.body) => {
+                Err(FcmError::Rejected(ErrorCode::Unregistered, response.text()))
+            }
+            StatusCode::TOO_MANY_REQUESTS => Err(FcmError::QuotaExceeded(retry_after)),
+            status if status.is_server_error() => Err(FcmError::ServerError(retry_after)),
+            _ => Err(FcmError::InvalidMessage(response.text())),
+        })
+    }
+}
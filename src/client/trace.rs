@@ -0,0 +1,164 @@
+//! `tracing` instrumentation of a send, compiled to no-ops without the `tracing` feature.
+
+use std::future::Future;
+
+use crate::client::response::FcmError;
+use crate::Message;
+
+/// A stage of sending a message, each traced as a child span of the send.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Stage {
+    Serialize,
+    AuthToken,
+    Http,
+    Parse,
+}
+
+/// The span of one attempt at sending a message, or of a request to the instance id API.
+pub(crate) struct SendSpan {
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+    #[cfg(feature = "tracing")]
+    start: tokio::time::Instant,
+}
+
+#[cfg(feature = "tracing")]
+impl SendSpan {
+    pub(crate) fn new(project_id: &str, message: &Message, validate_only: bool, attempt: u32) -> SendSpan {
+        let (target_kind, target) = describe_target(&message.target);
+        let span = tracing::info_span!(
+            "fcm.send",
+            project_id,
+            target_kind,
+            target = %target,
+            validate_only,
+            attempt,
+            http.status = tracing::field::Empty,
+            message_name = tracing::field::Empty,
+            error_code = tracing::field::Empty,
+            latency_ms = tracing::field::Empty,
+        );
+
+        SendSpan {
+            span,
+            start: tokio::time::Instant::now(),
+        }
+    }
+
+    /// The span of the instance id API `operation`, e.g. `batchAdd`.
+    pub(crate) fn topic_request(project_id: &str, operation: &'static str) -> SendSpan {
+        let span = tracing::info_span!(
+            "fcm.iid",
+            project_id,
+            operation,
+            http.status = tracing::field::Empty,
+            error_code = tracing::field::Empty,
+            latency_ms = tracing::field::Empty,
+        );
+
+        SendSpan {
+            span,
+            start: tokio::time::Instant::now(),
+        }
+    }
+
+    fn stage_span(&self, stage: Stage) -> tracing::Span {
+        match stage {
+            Stage::Serialize => tracing::debug_span!(parent: &self.span, "fcm.serialize"),
+            Stage::AuthToken => tracing::debug_span!(parent: &self.span, "fcm.auth_token"),
+            Stage::Http => tracing::debug_span!(parent: &self.span, "fcm.http"),
+            Stage::Parse => tracing::debug_span!(parent: &self.span, "fcm.parse"),
+        }
+    }
+
+    /// Run a synchronous stage inside its span.
+    pub(crate) fn in_stage<T>(&self, stage: Stage, f: impl FnOnce() -> T) -> T {
+        self.stage_span(stage).in_scope(f)
+    }
+
+    /// Run an asynchronous stage inside its span.
+    pub(crate) async fn stage<F: Future>(&self, stage: Stage, future: F) -> F::Output {
+        tracing::Instrument::instrument(future, self.stage_span(stage)).await
+    }
+
+    pub(crate) fn record_status(&self, status: u16) {
+        self.span.record("http.status", status);
+    }
+
+    /// Record the outcome, with the name of the sent message if any, and emit an event for it.
+    pub(crate) fn finish(&self, result: Result<Option<&str>, &FcmError>) {
+        let latency_ms = self.start.elapsed().as_millis() as u64;
+        self.span.record("latency_ms", latency_ms);
+
+        let _entered = self.span.enter();
+        match result {
+            Ok(message_name) => {
+                if let Some(name) = message_name {
+                    self.span.record("message_name", name);
+                }
+                tracing::debug!(latency_ms, "request succeeded");
+            }
+            Err(error) => {
+                self.span.record("error_code", error.kind());
+                tracing::warn!(latency_ms, %error, "request failed");
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+impl SendSpan {
+    pub(crate) fn new(_project_id: &str, _message: &Message, _validate_only: bool, _attempt: u32) -> SendSpan {
+        SendSpan {}
+    }
+
+    pub(crate) fn topic_request(_project_id: &str, _operation: &'static str) -> SendSpan {
+        SendSpan {}
+    }
+
+    pub(crate) fn in_stage<T>(&self, _stage: Stage, f: impl FnOnce() -> T) -> T {
+        f()
+    }
+
+    pub(crate) async fn stage<F: Future>(&self, _stage: Stage, future: F) -> F::Output {
+        future.await
+    }
+
+    pub(crate) fn record_status(&self, _status: u16) {}
+
+    pub(crate) fn finish(&self, _result: Result<Option<&str>, &FcmError>) {}
+}
+
+/// The kind of a target, and the target with registration tokens shortened to a prefix.
+#[cfg(feature = "tracing")]
+fn describe_target(target: &crate::Target) -> (&'static str, String) {
+    match target {
+        crate::Target::Token(token) => ("token", redact_token(token)),
+        crate::Target::Topic(topic) => ("topic", topic.clone()),
+        crate::Target::Condition(condition) => ("condition", condition.clone()),
+    }
+}
+
+/// Keep only enough of a registration token to tell tokens apart in logs.
+#[cfg(feature = "tracing")]
+fn redact_token(token: &str) -> String {
+    let prefix: String = token.chars().take(6).collect();
+    if token.chars().count() > 6 {
+        format!("{prefix}…")
+    } else {
+        "…".to_string()
+    }
+}
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact_token() {
+        assert_eq!("abcdef…", redact_token("abcdefghijklmnop"));
+        assert_eq!("…", redact_token("short"));
+        assert_eq!("…", redact_token("ééééé"));
+        assert_eq!("éééééé…", redact_token("ééééééé"));
+    }
+}
@@ -0,0 +1,410 @@
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use reqwest::header::HeaderMap;
+use reqwest::{Method, StatusCode};
+
+/// An HTTP request to FCM or the instance id API, with the body already serialized.
+#[derive(Clone, Debug)]
+pub struct HttpRequest {
+    /// The request method.
+    pub method: Method,
+
+    /// The absolute URL.
+    pub url: String,
+
+    /// The request headers, including `Authorization`.
+    pub headers: HeaderMap,
+
+    /// The request body, empty for `GET` requests.
+    pub body: Vec<u8>,
+}
+
+/// The HTTP response to an [`HttpRequest`].
+#[derive(Clone, Debug)]
+pub struct HttpResponse {
+    /// The response status.
+    pub status: StatusCode,
+
+    /// The response headers.
+    pub headers: HeaderMap,
+
+    /// The complete response body.
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    /// The value of header `name`, if present and valid UTF-8.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).and_then(|value| value.to_str().ok())
+    }
+
+    /// The body as text, replacing invalid UTF-8.
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+}
+
+/// What went wrong while exchanging a request with the server.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TransportErrorKind {
+    /// The request or the connection attempt timed out.
+    Timeout,
+
+    /// The host name could not be resolved.
+    Dns,
+
+    /// The connection could not be established.
+    Connect,
+
+    /// The TLS handshake or certificate validation failed.
+    Tls,
+
+    /// Any other failure, e.g. the connection closed while reading the response.
+    Other,
+}
+
+/// A request that never got a complete HTTP response. Reported as [`FcmError::Timeout`],
+/// [`FcmError::Connect`], [`FcmError::Tls`] or [`FcmError::Transport`] depending on its kind.
+///
+/// Errors compare equal by kind and message, regardless of their source.
+///
+/// [`FcmError::Timeout`]: crate::Error::Timeout
+/// [`FcmError::Connect`]: crate::Error::Connect
+/// [`FcmError::Tls`]: crate::Error::Tls
+/// [`FcmError::Transport`]: crate::Error::Transport
+#[derive(Clone, Debug)]
+pub struct TransportError {
+    /// The kind of failure.
+    pub kind: TransportErrorKind,
+
+    /// Description of the failure by the transport.
+    pub message: String,
+
+    source: Option<Arc<dyn Error + Send + Sync>>,
+}
+
+impl TransportError {
+    /// Get an error of `kind` with `message`.
+    pub fn new(kind: TransportErrorKind, message: impl Into<String>) -> TransportError {
+        TransportError {
+            kind,
+            message: message.into(),
+            source: None,
+        }
+    }
+
+    /// Keep `source` as the underlying error, returned by `Error::source`.
+    pub fn with_source<E: Error + Send + Sync + 'static>(mut self, source: E) -> TransportError {
+        self.source = Some(Arc::new(source));
+        self
+    }
+}
+
+impl PartialEq for TransportError {
+    fn eq(&self, other: &TransportError) -> bool {
+        self.kind == other.kind && self.message == other.message
+    }
+}
+
+impl Error for TransportError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.source.as_deref().map(|source| source as &(dyn Error + 'static))
+    }
+}
+
+impl fmt::Display for TransportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            TransportErrorKind::Timeout => "timed out",
+            TransportErrorKind::Dns => "DNS lookup failed",
+            TransportErrorKind::Connect => "connection failed",
+            TransportErrorKind::Tls => "TLS failed",
+            TransportErrorKind::Other => "request failed",
+        };
+        write!(f, "{kind}: {}", self.message)
+    }
+}
+
+/// Sends the HTTP requests of the [`Client`](crate::Client), see
+/// [`ClientBuilder::transport`](crate::ClientBuilder::transport). Defaults to [`ReqwestTransport`].
+///
+/// Implementations only move bytes: any response with a status, successful or not, is returned as
+/// `Ok` and interpreted by the client. The client also fetches its OAuth access tokens through the
+/// transport, with form encoded `POST` requests to the `token_uri` of the service account key.
+///
+/// ```rust
+/// use async_trait::async_trait;
+/// use fcm::{HttpRequest, HttpResponse, HttpTransport, TransportError};
+///
+/// /// Accept every message without talking to FCM.
+/// struct AcceptAll;
+///
+/// #[async_trait]
+/// impl HttpTransport for AcceptAll {
+///     async fn execute(&self, _request: HttpRequest) -> Result<HttpResponse, TransportError> {
+///         Ok(HttpResponse {
+///             status: reqwest::StatusCode::OK,
+///             headers: Default::default(),
+///             body: br#"{"name": "projects/test/messages/1"}"#.to_vec(),
+///         })
+///     }
+/// }
+/// ```
+#[async_trait]
+pub trait HttpTransport: Send + Sync {
+    /// Send `request` and read the complete response.
+    async fn execute(&self, request: HttpRequest) -> Result<HttpResponse, TransportError>;
+}
+
+/// The default transport, sending through a `reqwest::Client`.
+#[derive(Clone, Debug)]
+pub struct ReqwestTransport {
+    client: reqwest::Client,
+}
+
+impl ReqwestTransport {
+    /// Send through `client`, e.g. to share its connection pool with the rest of the application.
+    pub fn new(client: reqwest::Client) -> ReqwestTransport {
+        ReqwestTransport { client }
+    }
+}
+
+impl Default for ReqwestTransport {
+    fn default() -> ReqwestTransport {
+        let client = HttpOptions::default().build().unwrap();
+        ReqwestTransport::new(client)
+    }
+}
+
+/// A TLS client certificate with its private key.
+#[derive(Clone)]
+pub(crate) enum ClientIdentity {
+    /// PEM with the certificate chain and the key, for rustls.
+    #[cfg(feature = "rustls")]
+    Pem(Vec<u8>),
+
+    /// PEM certificate chain and PKCS #8 PEM key, for native-tls.
+    #[cfg(any(feature = "native-tls", feature = "vendored-tls"))]
+    Pkcs8Pem { certificate: Vec<u8>, key: Vec<u8> },
+
+    /// PKCS #12 archive, for native-tls.
+    #[cfg(any(feature = "native-tls", feature = "vendored-tls"))]
+    Pkcs12Der { der: Vec<u8>, password: String },
+}
+
+impl ClientIdentity {
+    /// Authenticate with the identity, using the TLS backend it is meant for.
+    #[cfg_attr(
+        not(any(feature = "native-tls", feature = "rustls", feature = "vendored-tls")),
+        allow(unused_variables)
+    )]
+    fn apply(&self, builder: reqwest::ClientBuilder) -> Result<reqwest::ClientBuilder, reqwest::Error> {
+        match *self {
+            #[cfg(feature = "rustls")]
+            ClientIdentity::Pem(ref pem) => Ok(builder.use_rustls_tls().identity(reqwest::Identity::from_pem(pem)?)),
+            #[cfg(any(feature = "native-tls", feature = "vendored-tls"))]
+            ClientIdentity::Pkcs8Pem {
+                ref certificate,
+                ref key,
+            } => Ok(builder
+                .use_native_tls()
+                .identity(reqwest::Identity::from_pkcs8_pem(certificate, key)?)),
+            #[cfg(any(feature = "native-tls", feature = "vendored-tls"))]
+            ClientIdentity::Pkcs12Der { ref der, ref password } => Ok(builder
+                .use_native_tls()
+                .identity(reqwest::Identity::from_pkcs12_der(der, password)?)),
+        }
+    }
+}
+
+/// Settings of the `reqwest::Client` built for the default transport, see the HTTP options of
+/// [`ClientBuilder`](crate::ClientBuilder).
+#[derive(Clone, Default)]
+pub(crate) struct HttpOptions {
+    pub(crate) proxy: Option<String>,
+    pub(crate) proxy_credentials: Option<(String, String)>,
+    pub(crate) root_certificates: Vec<Vec<u8>>,
+    pub(crate) identity: Option<ClientIdentity>,
+    pub(crate) connect_timeout: Option<Duration>,
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) http2_prior_knowledge: bool,
+    pub(crate) tcp_keepalive: Option<Duration>,
+    pub(crate) pool_idle_timeout: Option<Duration>,
+    pub(crate) pool_max_idle_per_host: Option<usize>,
+    pub(crate) http2_keep_alive_interval: Option<Duration>,
+}
+
+impl HttpOptions {
+    /// Whether all options are left at their defaults.
+    pub(crate) fn is_empty(&self) -> bool {
+        let HttpOptions {
+            proxy,
+            proxy_credentials,
+            root_certificates,
+            identity,
+            connect_timeout,
+            timeout,
+            http2_prior_knowledge,
+            tcp_keepalive,
+            pool_idle_timeout,
+            pool_max_idle_per_host,
+            http2_keep_alive_interval,
+        } = self;
+        proxy.is_none()
+            && proxy_credentials.is_none()
+            && root_certificates.is_empty()
+            && identity.is_none()
+            && connect_timeout.is_none()
+            && timeout.is_none()
+            && !http2_prior_knowledge
+            && tcp_keepalive.is_none()
+            && pool_idle_timeout.is_none()
+            && pool_max_idle_per_host.is_none()
+            && http2_keep_alive_interval.is_none()
+    }
+
+    /// Build the client, failing if the proxy URL, a certificate or the identity is invalid.
+    pub(crate) fn build(&self) -> Result<reqwest::Client, reqwest::Error> {
+        let mut builder =
+            reqwest::ClientBuilder::new().pool_max_idle_per_host(self.pool_max_idle_per_host.unwrap_or(usize::MAX));
+
+        if let Some(url) = &self.proxy {
+            let mut proxy = reqwest::Proxy::all(url)?;
+            if let Some((username, password)) = &self.proxy_credentials {
+                proxy = proxy.basic_auth(username, password);
+            }
+            builder = builder.proxy(proxy);
+        }
+
+        #[cfg(any(feature = "native-tls", feature = "rustls", feature = "vendored-tls"))]
+        for pem in &self.root_certificates {
+            for certificate in reqwest::Certificate::from_pem_bundle(pem)? {
+                builder = builder.add_root_certificate(certificate);
+            }
+        }
+
+        if let Some(identity) = &self.identity {
+            builder = identity.apply(builder)?;
+        }
+
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if self.http2_prior_knowledge {
+            builder = builder.http2_prior_knowledge();
+        }
+        if let Some(interval) = self.tcp_keepalive {
+            builder = builder.tcp_keepalive(interval);
+        }
+        if let Some(timeout) = self.pool_idle_timeout {
+            builder = builder.pool_idle_timeout(timeout);
+        }
+        if let Some(interval) = self.http2_keep_alive_interval {
+            builder = builder
+                .http2_keep_alive_interval(interval)
+                .http2_keep_alive_while_idle(true);
+        }
+
+        builder.build()
+    }
+}
+
+impl fmt::Debug for HttpOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HttpOptions")
+            .field("proxy", &self.proxy)
+            .field(
+                "proxy_credentials",
+                &self.proxy_credentials.as_ref().map(|(username, _)| username),
+            )
+            .field("root_certificates", &self.root_certificates.len())
+            .field("identity", &self.identity.is_some())
+            .field("connect_timeout", &self.connect_timeout)
+            .field("timeout", &self.timeout)
+            .field("http2_prior_knowledge", &self.http2_prior_knowledge)
+            .field("tcp_keepalive", &self.tcp_keepalive)
+            .field("pool_idle_timeout", &self.pool_idle_timeout)
+            .field("pool_max_idle_per_host", &self.pool_max_idle_per_host)
+            .field("http2_keep_alive_interval", &self.http2_keep_alive_interval)
+            .finish()
+    }
+}
+
+#[async_trait]
+impl HttpTransport for ReqwestTransport {
+    async fn execute(&self, request: HttpRequest) -> Result<HttpResponse, TransportError> {
+        let response = self
+            .client
+            .request(request.method, &request.url)
+            .headers(request.headers)
+            .body(request.body)
+            .send()
+            .await?;
+
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = response.bytes().await?.to_vec();
+
+        Ok(HttpResponse { status, headers, body })
+    }
+}
+
+impl From<reqwest::Error> for TransportError {
+    fn from(error: reqwest::Error) -> TransportError {
+        // reqwest only tells timeouts and connection failures apart, the source chain says more
+        let mut causes = Vec::new();
+        let mut source = error.source();
+        while let Some(cause) = source {
+            causes.push(cause.to_string());
+            source = cause.source();
+        }
+        let message = causes.last().cloned().unwrap_or_else(|| error.to_string());
+        let caused_by = |needles: &[&str]| {
+            causes
+                .iter()
+                .map(|cause| cause.to_lowercase())
+                .any(|cause| needles.iter().any(|needle| cause.contains(needle)))
+        };
+
+        let kind = if error.is_timeout() {
+            TransportErrorKind::Timeout
+        } else if caused_by(&["dns error", "failed to lookup address"]) {
+            TransportErrorKind::Dns
+        } else if caused_by(&["certificate", "tls", "ssl", "handshake"]) {
+            TransportErrorKind::Tls
+        } else if error.is_connect() {
+            TransportErrorKind::Connect
+        } else {
+            TransportErrorKind::Other
+        };
+
+        TransportError::new(kind, message).with_source(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_connection_refused() {
+        // nothing listens on the discard port
+        let request = HttpRequest {
+            method: Method::GET,
+            url: "http://127.0.0.1:9/".to_string(),
+            headers: HeaderMap::new(),
+            body: Vec::new(),
+        };
+
+        let error = ReqwestTransport::default().execute(request).await.unwrap_err();
+        assert_eq!(TransportErrorKind::Connect, error.kind);
+        assert!(error.source().unwrap().is::<reqwest::Error>());
+    }
+}
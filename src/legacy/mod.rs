@@ -0,0 +1,387 @@
+#[cfg(test)]
+mod tests;
+
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use time::OffsetDateTime;
+
+use crate::android::android_config::AndroidConfig;
+use crate::android::android_message_priority::AndroidMessagePriority;
+use crate::android::android_notification::AndroidNotification;
+use crate::apns::apns_config::ApnsConfig;
+use crate::message::target::Target;
+use crate::message::Message;
+use crate::notification::Notification;
+
+/// Priority of a legacy message.
+/// https://firebase.google.com/docs/cloud-messaging/http-server-ref#downstream-http-messages-json
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LegacyPriority {
+    Normal,
+    High,
+}
+
+/// The notification payload of a legacy message.
+/// https://firebase.google.com/docs/cloud-messaging/http-server-ref#notification-payload-support
+#[derive(Deserialize, Debug, Default)]
+pub struct LegacyNotification {
+    /// The notification's title.
+    pub title: Option<String>,
+
+    /// The notification's body text.
+    pub body: Option<String>,
+
+    /// The notification's subtitle (iOS only).
+    pub subtitle: Option<String>,
+
+    /// The notification's channel id (Android only).
+    pub android_channel_id: Option<String>,
+
+    /// The notification's icon (Android only).
+    pub icon: Option<String>,
+
+    /// The sound to play when the device receives the notification.
+    pub sound: Option<String>,
+
+    /// The value of the badge on the home screen app icon (iOS only).
+    pub badge: Option<String>,
+
+    /// Identifier used to replace existing notifications in the notification drawer (Android only).
+    pub tag: Option<String>,
+
+    /// The notification's icon color, expressed in #rrggbb format (Android only).
+    pub color: Option<String>,
+
+    /// The action associated with a user click on the notification.
+    pub click_action: Option<String>,
+
+    /// The key to the body string in the app's string resources.
+    pub body_loc_key: Option<String>,
+
+    /// Variable string values to be used in place of the format specifiers in body_loc_key.
+    pub body_loc_args: Option<Vec<String>>,
+
+    /// The key to the title string in the app's string resources.
+    pub title_loc_key: Option<String>,
+
+    /// Variable string values to be used in place of the format specifiers in title_loc_key.
+    pub title_loc_args: Option<Vec<String>>,
+
+    /// Contains the URL of an image that is going to be displayed in a notification.
+    pub image: Option<String>,
+
+    /// Fields of the notification payload that have no equivalent in the v1 API.
+    #[serde(flatten)]
+    pub unmapped: Map<String, Value>,
+}
+
+/// A message in the format of the legacy FCM HTTP API, which can be converted into v1 `Message`s.
+///
+/// Fields without a v1 equivalent (for example `dry_run`) are collected in `unmapped` and make the
+/// conversion fail with [`LegacyConversionError::UnmappedFields`]. Clear `unmapped` on the message
+/// and its notification to drop them instead.
+///
+/// ```rust
+/// use std::convert::TryFrom;
+/// use fcm::{LegacyMessage, Message};
+///
+/// let legacy: LegacyMessage = serde_json::from_str(r#"{
+///     "registration_ids": ["token1", "token2"],
+///     "priority": "high",
+///     "notification": { "title": "Hello", "click_action": "OPEN_INBOX" }
+/// }"#).unwrap();
+///
+/// let messages = Vec::<Message>::try_from(legacy).unwrap();
+/// assert_eq!(messages.len(), 2);
+/// ```
+/// https://firebase.google.com/docs/cloud-messaging/http-server-ref#downstream-http-messages-json
+#[derive(Deserialize, Debug, Default)]
+pub struct LegacyMessage {
+    /// A registration token, notification key or topic (prefixed with `/topics/`).
+    pub to: Option<String>,
+
+    /// A list of registration tokens, each of them results in its own `Message`.
+    pub registration_ids: Option<Vec<String>>,
+
+    /// A logical expression of conditions that determine the message target.
+    pub condition: Option<String>,
+
+    /// An identifier of a group of messages that can be collapsed.
+    pub collapse_key: Option<String>,
+
+    /// The priority of the message.
+    pub priority: Option<LegacyPriority>,
+
+    /// Wakes an inactive iOS app with a background update.
+    pub content_available: Option<bool>,
+
+    /// Allows a notification service extension to modify the notification on iOS.
+    pub mutable_content: Option<bool>,
+
+    /// How long (in seconds) the message should be kept in FCM storage if the device is offline.
+    pub time_to_live: Option<u32>,
+
+    /// Package name of the application where the registration tokens must match.
+    pub restricted_package_name: Option<String>,
+
+    /// Arbitrary key/value payload. Non-string values are converted to their JSON representation,
+    /// as the v1 API only accepts strings.
+    pub data: Option<Map<String, Value>>,
+
+    /// Predefined, user-visible key/value pairs of the notification payload.
+    pub notification: Option<LegacyNotification>,
+
+    /// Fields of the message that have no equivalent in the v1 API.
+    #[serde(flatten)]
+    pub unmapped: Map<String, Value>,
+}
+
+/// Reasons why a `LegacyMessage` could not be converted into v1 `Message`s.
+#[derive(Debug, PartialEq)]
+pub enum LegacyConversionError {
+    /// None of `to`, `registration_ids` or `condition` is set, or `registration_ids` is empty.
+    MissingTarget,
+
+    /// More than one of `to`, `registration_ids` or `condition` is set.
+    AmbiguousTarget,
+
+    /// The listed fields have no equivalent in the v1 API.
+    UnmappedFields(Vec<String>),
+}
+
+impl Error for LegacyConversionError {}
+
+impl fmt::Display for LegacyConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LegacyConversionError::MissingTarget => write!(f, "legacy message has no target"),
+            LegacyConversionError::AmbiguousTarget => write!(f, "legacy message has more than one target"),
+            LegacyConversionError::UnmappedFields(fields) => {
+                write!(f, "legacy fields without v1 mapping: {}", fields.join(", "))
+            }
+        }
+    }
+}
+
+impl LegacyMessage {
+    /// Convert into v1 `Message`s as of `now`. APNs expects an absolute `apns-expiration`, so a
+    /// `time_to_live` is counted from `now`; convert right before sending.
+    pub fn into_messages(self, now: OffsetDateTime) -> Result<Vec<Message>, LegacyConversionError> {
+        let unmapped = self.unmapped_fields();
+        if !unmapped.is_empty() {
+            return Err(LegacyConversionError::UnmappedFields(unmapped));
+        }
+
+        let targets = self.targets()?;
+        Ok(targets.into_iter().map(|target| self.to_message(target, now)).collect())
+    }
+
+    fn targets(&self) -> Result<Vec<Target>, LegacyConversionError> {
+        match (&self.to, &self.registration_ids, &self.condition) {
+            (Some(to), None, None) => match to.strip_prefix("/topics/") {
+                Some(topic) => Ok(vec![Target::Topic(topic.to_string())]),
+                None => Ok(vec![Target::Token(to.clone())]),
+            },
+            (None, Some(ids), None) if ids.is_empty() => Err(LegacyConversionError::MissingTarget),
+            (None, Some(ids), None) => Ok(ids.iter().cloned().map(Target::Token).collect()),
+            (None, None, Some(condition)) => Ok(vec![Target::Condition(condition.clone())]),
+            (None, None, None) => Err(LegacyConversionError::MissingTarget),
+            _ => Err(LegacyConversionError::AmbiguousTarget),
+        }
+    }
+
+    fn unmapped_fields(&self) -> Vec<String> {
+        let mut fields: Vec<String> = self.unmapped.keys().cloned().collect();
+        if let Some(notification) = &self.notification {
+            fields.extend(notification.unmapped.keys().map(|key| format!("notification.{key}")));
+        }
+        fields
+    }
+
+    fn to_data(&self) -> Option<Value> {
+        let data = self.data.as_ref()?;
+        let data = data
+            .iter()
+            .map(|(key, value)| {
+                let value = match value {
+                    Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                };
+                (key.clone(), Value::String(value))
+            })
+            .collect();
+
+        Some(Value::Object(data))
+    }
+
+    fn to_notification(&self) -> Option<Notification> {
+        let n = self.notification.as_ref()?;
+        if n.title.is_none() && n.body.is_none() && n.image.is_none() {
+            return None;
+        }
+
+        Some(Notification {
+            title: n.title.clone(),
+            body: n.body.clone(),
+            image: n.image.clone(),
+            ..Default::default()
+        })
+    }
+
+    fn to_android_notification(&self) -> Option<AndroidNotification> {
+        let n = self.notification.as_ref()?;
+        let notification = AndroidNotification {
+            icon: n.icon.clone(),
+            color: n.color.clone(),
+            sound: n.sound.clone(),
+            tag: n.tag.clone(),
+            click_action: n.click_action.clone(),
+            body_loc_key: n.body_loc_key.clone(),
+            body_loc_args: n.body_loc_args.clone(),
+            title_loc_key: n.title_loc_key.clone(),
+            title_loc_args: n.title_loc_args.clone(),
+            channel_id: n.android_channel_id.clone(),
+            ..Default::default()
+        };
+
+        let is_empty = notification.icon.is_none()
+            && notification.color.is_none()
+            && notification.sound.is_none()
+            && notification.tag.is_none()
+            && notification.click_action.is_none()
+            && notification.body_loc_key.is_none()
+            && notification.body_loc_args.is_none()
+            && notification.title_loc_key.is_none()
+            && notification.title_loc_args.is_none()
+            && notification.channel_id.is_none();
+
+        if is_empty {
+            None
+        } else {
+            Some(notification)
+        }
+    }
+
+    fn to_android(&self) -> Option<AndroidConfig> {
+        let config = AndroidConfig {
+            collapse_key: self.collapse_key.clone(),
+            priority: self.priority.map(|priority| match priority {
+                LegacyPriority::Normal => AndroidMessagePriority::Normal,
+                LegacyPriority::High => AndroidMessagePriority::High,
+            }),
+            ttl: self.time_to_live.map(|ttl| format!("{ttl}s")),
+            restricted_package_name: self.restricted_package_name.clone(),
+            notification: self.to_android_notification(),
+            ..Default::default()
+        };
+
+        let is_empty = config.collapse_key.is_none()
+            && config.priority.is_none()
+            && config.ttl.is_none()
+            && config.restricted_package_name.is_none()
+            && config.notification.is_none();
+
+        if is_empty {
+            None
+        } else {
+            Some(config)
+        }
+    }
+
+    fn to_apns(&self, now: OffsetDateTime) -> Option<ApnsConfig> {
+        let mut headers = Map::new();
+        if let Some(collapse_key) = &self.collapse_key {
+            headers.insert("apns-collapse-id".to_string(), json!(collapse_key));
+        }
+        if let Some(priority) = self.priority {
+            let priority = match priority {
+                LegacyPriority::Normal => "5",
+                LegacyPriority::High => "10",
+            };
+            headers.insert("apns-priority".to_string(), json!(priority));
+        }
+        if let Some(ttl) = self.time_to_live {
+            // APNs expects an absolute UNIX timestamp, where 0 means "deliver once or never"
+            let expiration = match ttl {
+                0 => 0,
+                ttl => now.unix_timestamp() + i64::from(ttl),
+            };
+            headers.insert("apns-expiration".to_string(), json!(expiration.to_string()));
+        }
+
+        let mut aps = Map::new();
+        let mut alert = Map::new();
+        if let Some(n) = &self.notification {
+            if let Some(subtitle) = &n.subtitle {
+                alert.insert("subtitle".to_string(), json!(subtitle));
+            }
+            if let Some(key) = &n.title_loc_key {
+                alert.insert("title-loc-key".to_string(), json!(key));
+            }
+            if let Some(args) = &n.title_loc_args {
+                alert.insert("title-loc-args".to_string(), json!(args));
+            }
+            if let Some(key) = &n.body_loc_key {
+                alert.insert("loc-key".to_string(), json!(key));
+            }
+            if let Some(args) = &n.body_loc_args {
+                alert.insert("loc-args".to_string(), json!(args));
+            }
+            if let Some(badge) = &n.badge {
+                let badge = badge.parse::<u32>().map(Value::from).unwrap_or_else(|_| json!(badge));
+                aps.insert("badge".to_string(), badge);
+            }
+            if let Some(sound) = &n.sound {
+                aps.insert("sound".to_string(), json!(sound));
+            }
+            if let Some(click_action) = &n.click_action {
+                aps.insert("category".to_string(), json!(click_action));
+            }
+        }
+        if !alert.is_empty() {
+            aps.insert("alert".to_string(), Value::Object(alert));
+        }
+        if self.content_available == Some(true) {
+            aps.insert("content-available".to_string(), json!(1));
+        }
+        if self.mutable_content == Some(true) {
+            aps.insert("mutable-content".to_string(), json!(1));
+        }
+
+        if headers.is_empty() && aps.is_empty() {
+            return None;
+        }
+
+        Some(ApnsConfig {
+            headers: (!headers.is_empty()).then(|| Value::Object(headers)),
+            payload: (!aps.is_empty()).then(|| json!({ "aps": aps })),
+            fcm_options: None,
+        })
+    }
+
+    fn to_message(&self, target: Target, now: OffsetDateTime) -> Message {
+        Message {
+            data: self.to_data(),
+            notification: self.to_notification(),
+            target,
+            android: self.to_android(),
+            webpush: None,
+            apns: self.to_apns(now),
+            fcm_options: None,
+        }
+    }
+}
+
+/// Converts as of the current time, see [`LegacyMessage::into_messages`].
+impl TryFrom<LegacyMessage> for Vec<Message> {
+    type Error = LegacyConversionError;
+
+    fn try_from(legacy: LegacyMessage) -> Result<Self, Self::Error> {
+        legacy.into_messages(OffsetDateTime::now_utc())
+    }
+}
@@ -0,0 +1,155 @@
+use std::convert::TryFrom;
+
+use crate::{LegacyConversionError, LegacyMessage, Message, Target};
+use serde_json::json;
+use time::OffsetDateTime;
+
+fn convert(legacy: serde_json::Value) -> Result<Vec<Message>, LegacyConversionError> {
+    let legacy: LegacyMessage = serde_json::from_value(legacy).unwrap();
+    Vec::<Message>::try_from(legacy)
+}
+
+#[test]
+fn should_convert_topic_in_to_field() {
+    let messages = convert(json!({ "to": "/topics/news" })).unwrap();
+
+    assert_eq!(1, messages.len());
+    assert_eq!(Target::Topic("news".to_string()), messages[0].target);
+}
+
+#[test]
+fn should_create_one_message_per_registration_id() {
+    let messages = convert(json!({
+        "registration_ids": ["token1", "token2"],
+        "data": { "foo": "bar" },
+    }))
+    .unwrap();
+
+    let targets: Vec<_> = messages.iter().map(|m| m.target.clone()).collect();
+    assert_eq!(
+        vec![Target::Token("token1".to_string()), Target::Token("token2".to_string())],
+        targets
+    );
+    assert_eq!(Some(json!({ "foo": "bar" })), messages[1].data);
+}
+
+#[test]
+fn should_map_legacy_fields_to_platform_configs() {
+    let messages = convert(json!({
+        "to": "token",
+        "collapse_key": "updates",
+        "priority": "high",
+        "content_available": true,
+        "restricted_package_name": "com.example",
+        "data": { "count": 3 },
+        "notification": {
+            "title": "Hello",
+            "body": "World",
+            "sound": "default",
+            "badge": "2",
+            "click_action": "OPEN_INBOX",
+            "body_loc_key": "greeting",
+            "body_loc_args": ["Jane"],
+        },
+    }))
+    .unwrap();
+
+    let expected_payload = json!({
+        "message": {
+            "data": {
+                "count": "3",
+            },
+            "notification": {
+                "title": "Hello",
+                "body": "World",
+            },
+            "android": {
+                "collapse_key": "updates",
+                "priority": "HIGH",
+                "restricted_package_name": "com.example",
+                "notification": {
+                    "sound": "default",
+                    "click_action": "OPEN_INBOX",
+                    "body_loc_key": "greeting",
+                    "body_loc_args": ["Jane"],
+                },
+            },
+            "apns": {
+                "headers": {
+                    "apns-collapse-id": "updates",
+                    "apns-priority": "10",
+                },
+                "payload": {
+                    "aps": {
+                        "badge": 2,
+                        "sound": "default",
+                        "category": "OPEN_INBOX",
+                        "alert": {
+                            "loc-key": "greeting",
+                            "loc-args": ["Jane"],
+                        },
+                        "content-available": 1,
+                    },
+                },
+            },
+            "token": "token",
+        }
+    });
+
+    assert_eq!(expected_payload, messages[0].to_request_json(false).unwrap());
+}
+
+#[test]
+fn should_map_time_to_live() {
+    let messages = convert(json!({ "to": "token", "time_to_live": 0 })).unwrap();
+
+    let android = messages[0].android.as_ref().unwrap();
+    assert_eq!(Some("0s".to_string()), android.ttl);
+
+    let apns = messages[0].apns.as_ref().unwrap();
+    assert_eq!(Some(json!({ "apns-expiration": "0" })), apns.headers);
+}
+
+#[test]
+fn should_count_apns_expiration_from_now() {
+    let legacy: LegacyMessage = serde_json::from_value(json!({ "to": "token", "time_to_live": 3600 })).unwrap();
+    let now = OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+
+    let messages = legacy.into_messages(now).unwrap();
+
+    let apns = messages[0].apns.as_ref().unwrap();
+    assert_eq!(Some(json!({ "apns-expiration": "1700003600" })), apns.headers);
+}
+
+#[test]
+fn should_report_unmapped_fields() {
+    let result = convert(json!({
+        "to": "token",
+        "dry_run": true,
+        "notification": { "title": "Hello", "body_loc_key_unknown": "x" },
+    }));
+
+    assert_eq!(
+        Err(LegacyConversionError::UnmappedFields(vec![
+            "dry_run".to_string(),
+            "notification.body_loc_key_unknown".to_string(),
+        ])),
+        result.map(|_| ())
+    );
+}
+
+#[test]
+fn should_require_exactly_one_target() {
+    assert_eq!(
+        Err(LegacyConversionError::MissingTarget),
+        convert(json!({})).map(|_| ())
+    );
+    assert_eq!(
+        Err(LegacyConversionError::MissingTarget),
+        convert(json!({ "registration_ids": [] })).map(|_| ())
+    );
+    assert_eq!(
+        Err(LegacyConversionError::AmbiguousTarget),
+        convert(json!({ "to": "token", "condition": "'a' in topics" })).map(|_| ())
+    );
+}
@@ -14,7 +14,7 @@
 //! async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
 //!     use serde_json::json;
 //!     use fcm::{Target, FcmOptions, Notification, Message};
-//!     let client = fcm::Client::new();
+//!     let client = fcm::Client::new().await?;
 //!
 //!     let data = json!({
 //!         "message": "Howdy!"
@@ -24,8 +24,8 @@
 //!         data: Some(data),
 //!         notification: Some(Notification {
 //!             title: Some("Hello".to_string()),
-//!             body: Some(format!("it's {}", chrono::Utc::now())),
-//!             image: None,
+//!             body: Some(format!("it's {}", time::OffsetDateTime::now_utc())),
+//!             ..Default::default()
 //!         }),
 //!         target: Target::Token("token".to_string()),
 //!         android: None,
@@ -36,7 +36,7 @@
 //!         }),
 //!     };
 //!
-//!     let response = client.send(builder).await?;
+//!     let response = client.send(&builder).await?;
 //!     println!("Sent: {:?}", response);
 //!
 //!     Ok(())
@@ -45,6 +45,7 @@
 
 mod message;
 pub use crate::message::fcm_options::*;
+pub use crate::message::platform::*;
 pub use crate::message::target::*;
 pub use crate::message::*;
 
@@ -69,6 +70,37 @@ mod web;
 pub use crate::web::webpush_config::*;
 pub use crate::web::webpush_fcm_options::*;
 
+mod template;
+pub use crate::template::catalog::*;
+pub use crate::template::*;
+
+mod legacy;
+pub use crate::legacy::*;
+
+mod token_store;
+pub use crate::token_store::memory::*;
+#[cfg(feature = "sqlite")]
+pub use crate::token_store::sqlite::*;
+pub use crate::token_store::*;
+
 mod client;
-pub use crate::client::response::{FcmError as Error, RetryAfter};
+pub use crate::client::response::{DecodeError, ErrorCode, FcmError as Error, FcmResponse, RetryAfter};
 pub use crate::client::*;
+
+#[cfg(feature = "blocking")]
+pub mod blocking;
+
+#[cfg(feature = "bulk")]
+pub mod bulk;
+
+#[cfg(any(feature = "queue", feature = "scheduler"))]
+mod retry;
+
+#[cfg(feature = "queue")]
+pub mod queue;
+
+#[cfg(feature = "scheduler")]
+pub mod scheduler;
+
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
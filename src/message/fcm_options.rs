@@ -1,4 +1,4 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Debug)]
 /// https://firebase.google.com/docs/reference/fcm/rest/v1/projects.messages?authuser=0#fcmoptions
@@ -7,7 +7,7 @@ pub(crate) struct FcmOptionsInternal<'m> {
     analytics_label: &'m str,
 }
 
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
 /// https://firebase.google.com/docs/reference/fcm/rest/v1/projects.messages?authuser=0#fcmoptions
 pub struct FcmOptions {
     /// Label associated with the message's analytics data.
@@ -15,7 +15,7 @@ pub struct FcmOptions {
 }
 
 impl FcmOptions {
-    pub(crate) fn finalize(&self) -> FcmOptionsInternal {
+    pub(crate) fn finalize(&self) -> FcmOptionsInternal<'_> {
         FcmOptionsInternal {
             analytics_label: &self.analytics_label,
         }
@@ -1,12 +1,13 @@
 pub mod fcm_options;
+pub mod platform;
 pub mod target;
 
 #[cfg(test)]
 mod tests;
 
 use serde::ser::SerializeMap;
-use serde::Serialize;
 use serde::Serializer;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
 use crate::android::android_config::AndroidConfig;
@@ -22,6 +23,22 @@ use self::fcm_options::FcmOptions;
 use self::fcm_options::FcmOptionsInternal;
 use self::target::Target;
 
+// will be used to wrap the message in a "message" field
+#[derive(Serialize)]
+struct MessageWrapper<'a> {
+    /// Flag for testing the request without actually delivering the message.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    validate_only: bool,
+
+    message: &'a MessageInternal<'a>,
+}
+
+impl<'m> MessageWrapper<'m> {
+    fn new(message: &'m MessageInternal, validate_only: bool) -> MessageWrapper<'m> {
+        MessageWrapper { validate_only, message }
+    }
+}
+
 fn output_target<S>(target: &Target, s: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
@@ -69,7 +86,7 @@ pub(crate) struct MessageInternal<'m> {
 
 /// A `Message` instance is the main object to send to the FCM API.
 /// https://firebase.google.com/docs/reference/fcm/rest/v1/projects.messages?authuser=0#resource:-message
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Message {
     /// Arbitrary key/value payload, which must be UTF-8 encoded.
     pub data: Option<Value>,
@@ -88,16 +105,44 @@ pub struct Message {
 }
 
 impl Message {
-    /// Complete the build and get a `MessageInternal` instance
-    pub(crate) fn finalize(&self) -> MessageInternal {
-        MessageInternal {
+    /// Complete the build and get a `MessageInternal` instance. Fails if the localized notification
+    /// cannot be expanded into the APNs payload.
+    pub(crate) fn finalize(&self) -> serde_json::Result<MessageInternal<'_>> {
+        let mut android = self.android.as_ref().map(|a| a.finalize());
+        let mut apns = self.apns.as_ref().map(|a| a.finalize());
+
+        // expand the platform independent localization into the Android and APNs specific fields
+        let title_loc = self.notification.as_ref().and_then(|n| n.localized_title.as_ref());
+        let body_loc = self.notification.as_ref().and_then(|n| n.localized_body.as_ref());
+        if title_loc.is_some() || body_loc.is_some() {
+            android
+                .get_or_insert_with(Default::default)
+                .apply_localized(title_loc, body_loc);
+            apns.get_or_insert_with(Default::default)
+                .apply_localized(title_loc, body_loc)?;
+        }
+
+        Ok(MessageInternal {
             data: self.data.as_ref(),
             notification: self.notification.as_ref().map(|n| n.finalize()),
-            android: self.android.as_ref().map(|a| a.finalize()),
+            android,
             webpush: self.webpush.as_ref().map(|w| w.finalize()),
-            apns: self.apns.as_ref().map(|a| a.finalize()),
+            apns,
             fcm_options: self.fcm_options.as_ref().map(|f| f.finalize()),
             target: &self.target,
-        }
+        })
+    }
+
+    /// Get the exact JSON body that `Client::send` (or `Client::validate`, when `validate_only` is
+    /// set) posts to the FCM API for this message, including the `message` wrapper.
+    pub fn to_request_json(&self, validate_only: bool) -> serde_json::Result<Value> {
+        let fin = self.finalize()?;
+        serde_json::to_value(MessageWrapper::new(&fin, validate_only))
+    }
+
+    /// Same as [`to_request_json`](Message::to_request_json), serialized to the bytes sent on the wire.
+    pub fn to_request_bytes(&self, validate_only: bool) -> serde_json::Result<Vec<u8>> {
+        let fin = self.finalize()?;
+        serde_json::to_vec(&MessageWrapper::new(&fin, validate_only))
     }
 }
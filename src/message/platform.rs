@@ -0,0 +1,155 @@
+use std::convert::TryFrom;
+
+use serde_json::Value;
+
+use super::Message;
+use crate::notification::Localized;
+
+/// A device platform a `Message` can be delivered to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Platform {
+    Android,
+    Ios,
+    Web,
+}
+
+/// What a device of a given `Platform` displays for a `Message`, after the platform specific
+/// overrides have been merged into the common `Notification`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EffectiveNotification {
+    /// The notification's title.
+    pub title: Option<String>,
+
+    /// The notification's body text.
+    pub body: Option<String>,
+
+    /// The URL of the image displayed in the notification.
+    pub image: Option<String>,
+
+    /// The sound played when the device receives the notification.
+    pub sound: Option<String>,
+
+    /// The badge count (Android notification count or iOS app icon badge).
+    pub badge: Option<u32>,
+
+    /// The action, category or link associated with a user click on the notification.
+    pub click_action: Option<String>,
+
+    /// The title localized on the device, which replaces `title` where the app has the string.
+    pub localized_title: Option<Localized>,
+
+    /// The body text localized on the device, which replaces `body` where the app has the string.
+    pub localized_body: Option<Localized>,
+}
+
+fn string_at(value: Option<&Value>, key: &str) -> Option<String> {
+    value?.get(key)?.as_str().map(str::to_string)
+}
+
+fn localized(key: Option<&String>, args: Option<&Vec<String>>) -> Option<Localized> {
+    Some(Localized {
+        key: key?.clone(),
+        args: args.cloned().unwrap_or_default(),
+    })
+}
+
+fn localized_at(value: Option<&Value>, key_field: &str, args_field: &str) -> Option<Localized> {
+    let args = value
+        .and_then(|value| value.get(args_field))
+        .and_then(Value::as_array)
+        .map(|args| args.iter().filter_map(Value::as_str).map(str::to_string).collect());
+
+    Some(Localized {
+        key: string_at(value, key_field)?,
+        args: args.unwrap_or_default(),
+    })
+}
+
+impl Message {
+    /// Resolve what a device of the given `platform` will display for this message.
+    ///
+    /// Platform specific values (`AndroidNotification`, the `aps` dictionary of the APNs payload and the
+    /// Webpush notification) take precedence over the common `Notification`. Its localized title and
+    /// body only apply to Android and iOS, as they are not expanded for Webpush.
+    pub fn effective_for(&self, platform: Platform) -> EffectiveNotification {
+        let common = self.notification.as_ref();
+        let title = common.and_then(|n| n.title.clone());
+        let body = common.and_then(|n| n.body.clone());
+        let image = common.and_then(|n| n.image.clone());
+        let localized_title = common.and_then(|n| n.localized_title.clone());
+        let localized_body = common.and_then(|n| n.localized_body.clone());
+
+        match platform {
+            Platform::Android => {
+                let n = self.android.as_ref().and_then(|a| a.notification.as_ref());
+                let sound = n.and_then(|n| match (&n.sound, n.default_sound) {
+                    (Some(sound), _) => Some(sound.clone()),
+                    (None, Some(true)) => Some("default".to_string()),
+                    (None, _) => None,
+                });
+
+                EffectiveNotification {
+                    title: n.and_then(|n| n.title.clone()).or(title),
+                    body: n.and_then(|n| n.body.clone()).or(body),
+                    image: n.and_then(|n| n.image.clone()).or(image),
+                    sound,
+                    badge: n.and_then(|n| n.notification_count).and_then(|c| u32::try_from(c).ok()),
+                    click_action: n.and_then(|n| n.click_action.clone()),
+                    localized_title: n
+                        .and_then(|n| localized(n.title_loc_key.as_ref(), n.title_loc_args.as_ref()))
+                        .or(localized_title),
+                    localized_body: n
+                        .and_then(|n| localized(n.body_loc_key.as_ref(), n.body_loc_args.as_ref()))
+                        .or(localized_body),
+                }
+            }
+            Platform::Ios => {
+                let apns = self.apns.as_ref();
+                let aps = apns.and_then(|a| a.payload.as_ref()).and_then(|p| p.get("aps"));
+                let alert = aps.and_then(|aps| aps.get("alert"));
+                let (alert_title, alert_body) = match alert {
+                    Some(Value::String(body)) => (None, Some(body.clone())),
+                    alert => (string_at(alert, "title"), string_at(alert, "body")),
+                };
+                let sound = match aps.and_then(|aps| aps.get("sound")) {
+                    Some(Value::String(sound)) => Some(sound.clone()),
+                    sound => string_at(sound, "name"),
+                };
+                let fcm_image = apns.and_then(|a| a.fcm_options.as_ref()).and_then(|o| o.image.clone());
+
+                EffectiveNotification {
+                    title: alert_title.or(title),
+                    body: alert_body.or(body),
+                    image: fcm_image.or(image),
+                    sound,
+                    badge: aps
+                        .and_then(|aps| aps.get("badge"))
+                        .and_then(Value::as_u64)
+                        .and_then(|b| u32::try_from(b).ok()),
+                    click_action: string_at(aps, "category"),
+                    localized_title: localized_at(alert, "title-loc-key", "title-loc-args").or(localized_title),
+                    localized_body: localized_at(alert, "loc-key", "loc-args").or(localized_body),
+                }
+            }
+            Platform::Web => {
+                let webpush = self.webpush.as_ref();
+                let n = webpush.and_then(|w| w.notification.as_ref());
+                let link = webpush
+                    .and_then(|w| w.fcm_options.as_ref())
+                    .map(|o| o.link.clone())
+                    .filter(|link| !link.is_empty());
+
+                EffectiveNotification {
+                    title: string_at(n, "title").or(title),
+                    body: string_at(n, "body").or(body),
+                    image: string_at(n, "image").or(image),
+                    sound: None,
+                    badge: None,
+                    click_action: link,
+                    localized_title: None,
+                    localized_body: None,
+                }
+            }
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 /// Target to send a message to.
 ///
@@ -9,7 +9,7 @@ use serde::Serialize;
 /// Target::Topic("my-topic-name".to_string());
 /// Target::Condition("my-condition".to_string());
 /// ```
-#[derive(Clone, Serialize, Debug, PartialEq)]
+#[derive(Clone, Serialize, Debug, PartialEq, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum Target {
     Token(String),
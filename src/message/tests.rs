@@ -1,4 +1,8 @@
-use crate::{message::Target, notification::Notification, Message};
+use crate::{
+    message::Target, notification::Notification, AndroidConfig, AndroidMessagePriority, AndroidNotification,
+    ApnsConfig, ApnsFcmOptions, EffectiveNotification, Localized, Message, Platform, Visibility, WebpushConfig,
+    WebpushFcmOptions,
+};
 use serde_json::json;
 
 #[test]
@@ -13,7 +17,7 @@ fn should_create_new_message() {
         apns: None,
         fcm_options: None,
     };
-    let msg = msg.finalize();
+    let msg = msg.finalize().unwrap();
 
     assert_eq!(*msg.target, target);
 }
@@ -30,7 +34,7 @@ fn should_leave_nones_out_of_the_json() {
         apns: None,
         fcm_options: None,
     };
-    let msg = msg.finalize();
+    let msg = msg.finalize().unwrap();
     let payload = serde_json::to_string(&msg).unwrap();
 
     let expected_payload = json!({
@@ -47,7 +51,7 @@ fn should_add_custom_data_to_the_payload() {
     let data = json!({ "foo": "bar", "bar": false });
 
     let builder = Message {
-        target: target,
+        target,
         data: Some(data),
         notification: None,
         android: None,
@@ -56,7 +60,7 @@ fn should_add_custom_data_to_the_payload() {
         fcm_options: None,
     };
 
-    let msg = builder.finalize();
+    let msg = builder.finalize().unwrap();
     let payload = serde_json::to_string(&msg).unwrap();
 
     let expected_payload = json!({
@@ -78,6 +82,7 @@ fn should_be_able_to_render_a_full_token_message_to_json() {
         title: None,
         body: None,
         image: None,
+        ..Default::default()
     };
     let builder = Message {
         target: target.clone(),
@@ -89,7 +94,7 @@ fn should_be_able_to_render_a_full_token_message_to_json() {
         fcm_options: None,
     };
 
-    let payload = serde_json::to_string(&builder.finalize()).unwrap();
+    let payload = serde_json::to_string(&builder.finalize().unwrap()).unwrap();
 
     let expected_payload = json!({
         "notification": {},
@@ -107,6 +112,7 @@ fn should_be_able_to_render_a_full_topic_message_to_json() {
         title: None,
         body: None,
         image: None,
+        ..Default::default()
     };
     let builder = Message {
         target: target.clone(),
@@ -118,7 +124,7 @@ fn should_be_able_to_render_a_full_topic_message_to_json() {
         fcm_options: None,
     };
 
-    let payload = serde_json::to_string(&builder.finalize()).unwrap();
+    let payload = serde_json::to_string(&builder.finalize().unwrap()).unwrap();
 
     let expected_payload = json!({
         "notification": {},
@@ -136,6 +142,7 @@ fn should_be_able_to_render_a_full_condition_message_to_json() {
         title: None,
         body: None,
         image: None,
+        ..Default::default()
     };
     let builder = Message {
         target: target.clone(),
@@ -147,7 +154,7 @@ fn should_be_able_to_render_a_full_condition_message_to_json() {
         fcm_options: None,
     };
 
-    let payload = serde_json::to_string(&builder.finalize()).unwrap();
+    let payload = serde_json::to_string(&builder.finalize().unwrap()).unwrap();
 
     let expected_payload = json!({
         "notification": {},
@@ -166,6 +173,7 @@ fn should_set_notifications() {
         title: None,
         body: None,
         image: None,
+        ..Default::default()
     };
 
     let builder = Message {
@@ -177,7 +185,339 @@ fn should_set_notifications() {
         apns: None,
         fcm_options: None,
     };
-    let msg = builder.finalize();
+    let msg = builder.finalize().unwrap();
 
-    assert_eq!(msg.notification.is_none(), false);
+    assert!(msg.notification.is_some());
+}
+
+#[test]
+fn should_wrap_the_request_body_in_a_message_field() {
+    let builder = Message {
+        target: Target::Topic("my_topic".to_string()),
+        data: Some(json!({ "foo": "bar" })),
+        notification: None,
+        android: None,
+        webpush: None,
+        apns: None,
+        fcm_options: None,
+    };
+
+    let expected_payload = json!({
+        "message": {
+            "data": {
+                "foo": "bar",
+            },
+            "topic": "my_topic",
+        }
+    });
+
+    assert_eq!(expected_payload, builder.to_request_json(false).unwrap());
+    assert_eq!(
+        expected_payload.to_string().into_bytes(),
+        builder.to_request_bytes(false).unwrap()
+    );
+}
+
+#[test]
+fn should_set_validate_only_in_the_request_body() {
+    let builder = Message {
+        target: Target::Token("token".to_string()),
+        data: None,
+        notification: None,
+        android: None,
+        webpush: None,
+        apns: None,
+        fcm_options: None,
+    };
+
+    let expected_payload = json!({
+        "validate_only": true,
+        "message": {
+            "token": "token",
+        }
+    });
+
+    assert_eq!(expected_payload, builder.to_request_json(true).unwrap());
+    assert_eq!(
+        expected_payload.to_string().into_bytes(),
+        builder.to_request_bytes(true).unwrap()
+    );
+}
+
+fn message_with_overrides() -> Message {
+    Message {
+        target: Target::Token("token".to_string()),
+        data: None,
+        notification: Some(Notification {
+            title: Some("Common title".to_string()),
+            body: Some("Common body".to_string()),
+            image: Some("https://my.image.com/common.jpg".to_string()),
+            ..Default::default()
+        }),
+        android: Some(AndroidConfig {
+            notification: Some(AndroidNotification {
+                title: Some("Android title".to_string()),
+                default_sound: Some(true),
+                notification_count: Some(3),
+                click_action: Some("OPEN_ACTIVITY".to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }),
+        webpush: Some(WebpushConfig {
+            notification: Some(json!({ "body": "Web body" })),
+            fcm_options: Some(WebpushFcmOptions {
+                link: "https://example.com".to_string(),
+                analytics_label: String::new(),
+            }),
+            ..Default::default()
+        }),
+        apns: Some(ApnsConfig {
+            payload: Some(json!({
+                "aps": {
+                    "alert": "iOS body",
+                    "sound": { "name": "ping.aiff", "critical": 1 },
+                    "badge": 7,
+                    "category": "MESSAGE",
+                }
+            })),
+            fcm_options: Some(ApnsFcmOptions {
+                image: Some("https://my.image.com/ios.jpg".to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }),
+        fcm_options: None,
+    }
+}
+
+#[test]
+fn should_merge_android_overrides() {
+    let expected = EffectiveNotification {
+        title: Some("Android title".to_string()),
+        body: Some("Common body".to_string()),
+        image: Some("https://my.image.com/common.jpg".to_string()),
+        sound: Some("default".to_string()),
+        badge: Some(3),
+        click_action: Some("OPEN_ACTIVITY".to_string()),
+        ..Default::default()
+    };
+
+    assert_eq!(expected, message_with_overrides().effective_for(Platform::Android));
+}
+
+#[test]
+fn should_merge_apns_overrides() {
+    let expected = EffectiveNotification {
+        title: Some("Common title".to_string()),
+        body: Some("iOS body".to_string()),
+        image: Some("https://my.image.com/ios.jpg".to_string()),
+        sound: Some("ping.aiff".to_string()),
+        badge: Some(7),
+        click_action: Some("MESSAGE".to_string()),
+        ..Default::default()
+    };
+
+    assert_eq!(expected, message_with_overrides().effective_for(Platform::Ios));
+}
+
+#[test]
+fn should_merge_webpush_overrides() {
+    let expected = EffectiveNotification {
+        title: Some("Common title".to_string()),
+        body: Some("Web body".to_string()),
+        image: Some("https://my.image.com/common.jpg".to_string()),
+        sound: None,
+        badge: None,
+        click_action: Some("https://example.com".to_string()),
+        ..Default::default()
+    };
+
+    assert_eq!(expected, message_with_overrides().effective_for(Platform::Web));
+}
+
+#[test]
+fn should_resolve_nothing_for_data_messages() {
+    let builder = Message {
+        target: Target::Token("token".to_string()),
+        data: Some(json!({ "foo": "bar" })),
+        notification: None,
+        android: None,
+        webpush: None,
+        apns: None,
+        fcm_options: None,
+    };
+
+    assert_eq!(EffectiveNotification::default(), builder.effective_for(Platform::Ios));
+}
+
+#[test]
+fn should_expand_localized_notification_for_android_and_apns() {
+    let builder = Message {
+        target: Target::Token("token".to_string()),
+        data: None,
+        notification: Some(Notification {
+            localized_title: Some(Localized::new("greeting_title")),
+            localized_body: Some(Localized {
+                key: "greeting_body".to_string(),
+                args: vec!["Jane".to_string()],
+            }),
+            ..Default::default()
+        }),
+        android: None,
+        webpush: None,
+        apns: None,
+        fcm_options: None,
+    };
+
+    let expected_payload = json!({
+        "message": {
+            "notification": {},
+            "android": {
+                "notification": {
+                    "body_loc_key": "greeting_body",
+                    "body_loc_args": ["Jane"],
+                    "title_loc_key": "greeting_title",
+                },
+            },
+            "apns": {
+                "payload": {
+                    "aps": {
+                        "alert": {
+                            "title-loc-key": "greeting_title",
+                            "loc-key": "greeting_body",
+                            "loc-args": ["Jane"],
+                        },
+                    },
+                },
+            },
+            "token": "token",
+        }
+    });
+
+    assert_eq!(expected_payload, builder.to_request_json(false).unwrap());
+
+    let expected = EffectiveNotification {
+        localized_title: Some(Localized::new("greeting_title")),
+        localized_body: Some(Localized {
+            key: "greeting_body".to_string(),
+            args: vec!["Jane".to_string()],
+        }),
+        ..Default::default()
+    };
+    assert_eq!(expected, builder.effective_for(Platform::Android));
+    assert_eq!(expected, builder.effective_for(Platform::Ios));
+    assert_eq!(EffectiveNotification::default(), builder.effective_for(Platform::Web));
+}
+
+#[test]
+fn should_keep_explicit_platform_localization() {
+    let builder = Message {
+        target: Target::Token("token".to_string()),
+        data: None,
+        notification: Some(Notification {
+            localized_body: Some(Localized::new("greeting_body")),
+            ..Default::default()
+        }),
+        android: Some(AndroidConfig {
+            notification: Some(AndroidNotification {
+                body_loc_key: Some("android_body".to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }),
+        webpush: None,
+        apns: Some(ApnsConfig {
+            payload: Some(json!({ "aps": { "alert": "Fallback body", "badge": 1 }, "custom": true })),
+            ..Default::default()
+        }),
+        fcm_options: None,
+    };
+
+    let expected_payload = json!({
+        "message": {
+            "notification": {},
+            "android": {
+                "notification": {
+                    "body_loc_key": "android_body",
+                },
+            },
+            "apns": {
+                "payload": {
+                    "aps": {
+                        "alert": {
+                            "body": "Fallback body",
+                            "loc-key": "greeting_body",
+                        },
+                        "badge": 1,
+                    },
+                    "custom": true,
+                },
+            },
+            "token": "token",
+        }
+    });
+
+    assert_eq!(expected_payload, builder.to_request_json(false).unwrap());
+    assert_eq!(
+        Some(Localized::new("android_body")),
+        builder.effective_for(Platform::Android).localized_body
+    );
+    assert_eq!(
+        Some(Localized::new("greeting_body")),
+        builder.effective_for(Platform::Ios).localized_body
+    );
+}
+
+#[test]
+fn should_fail_to_localize_a_non_object_apns_payload() {
+    let builder = Message {
+        target: Target::Token("token".to_string()),
+        data: None,
+        notification: Some(Notification {
+            localized_body: Some(Localized::new("greeting_body")),
+            ..Default::default()
+        }),
+        android: None,
+        webpush: None,
+        apns: Some(ApnsConfig {
+            payload: Some(json!({ "aps": "not an object" })),
+            ..Default::default()
+        }),
+        fcm_options: None,
+    };
+
+    assert!(builder.to_request_json(false).is_err());
+    assert!(builder.to_request_bytes(false).is_err());
+}
+
+#[test]
+fn should_round_trip_through_serde() {
+    let message = Message {
+        data: Some(json!({ "foo": "bar" })),
+        notification: Some(Notification {
+            title: Some("Title".to_string()),
+            localized_body: Some(Localized::new("greeting_body")),
+            ..Default::default()
+        }),
+        target: Target::Condition("'news' in topics".to_string()),
+        android: Some(AndroidConfig {
+            priority: Some(AndroidMessagePriority::High),
+            notification: Some(AndroidNotification {
+                visibility: Some(Visibility::Private),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }),
+        webpush: None,
+        apns: Some(ApnsConfig {
+            payload: Some(json!({ "aps": { "badge": 1 } })),
+            ..Default::default()
+        }),
+        fcm_options: None,
+    };
+
+    let stored = serde_json::to_string(&message).unwrap();
+
+    assert_eq!(message, serde_json::from_str::<Message>(&stored).unwrap());
 }
@@ -1,7 +1,7 @@
 #[cfg(test)]
 mod tests;
 
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 /// This struct represents a FCM notification. Use the
 /// corresponding `Notification` to get an instance. You can then use
@@ -22,7 +22,15 @@ pub(crate) struct NotificationInternal<'m> {
     image: Option<&'m str>,
 }
 
-#[derive(Clone, Debug, Default, serde::Serialize)]
+/// The notification shown on all platforms.
+///
+/// ```rust
+/// use fcm::{Localized, Notification};
+///
+/// let mut notification = Notification::new("Hello", "Howdy!");
+/// notification.localized_title = Some(Localized::new("greeting_title"));
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
 pub struct Notification {
     /// The notification's title.
     pub title: Option<String>,
@@ -32,11 +40,48 @@ pub struct Notification {
 
     /// Contains the URL of an image that is going to be downloaded on the device and displayed in a notification.
     pub image: Option<String>,
+
+    /// Localizes the title on the device. Expanded into `title_loc_key`/`title_loc_args` of the Android
+    /// notification and `title-loc-key`/`title-loc-args` of the APNs alert, unless those are set explicitly.
+    pub localized_title: Option<Localized>,
+
+    /// Localizes the body text on the device. Expanded into `body_loc_key`/`body_loc_args` of the Android
+    /// notification and `loc-key`/`loc-args` of the APNs alert, unless those are set explicitly.
+    pub localized_body: Option<Localized>,
+}
+
+/// A string from the app's resources, localized to the user's current localization on the device.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Localized {
+    /// The key of the string in the app's string resources.
+    pub key: String,
+
+    /// Variable string values to be used in place of the format specifiers of the string.
+    pub args: Vec<String>,
+}
+
+impl Localized {
+    /// Get a localized string without format arguments.
+    pub fn new(key: &str) -> Localized {
+        Localized {
+            key: key.to_string(),
+            args: Vec::new(),
+        }
+    }
 }
 
 impl Notification {
+    /// Get a notification with a `title` and `body` text.
+    pub fn new(title: &str, body: &str) -> Notification {
+        Notification {
+            title: Some(title.to_string()),
+            body: Some(body.to_string()),
+            ..Default::default()
+        }
+    }
+
     /// Complete the build and get a `Notification` instance
-    pub(crate) fn finalize(&self) -> NotificationInternal {
+    pub(crate) fn finalize(&self) -> NotificationInternal<'_> {
         NotificationInternal {
             title: self.title.as_deref(),
             body: self.body.as_deref(),
@@ -7,6 +7,7 @@ fn should_be_able_to_render_a_full_notification_to_json() {
         title: Some("foo".to_string()),
         body: Some("bar".to_string()),
         image: Some("https://my.image.com/test.jpg".to_string()),
+        ..Default::default()
     };
 
     let payload = serde_json::to_string(&not.finalize()).unwrap();
@@ -0,0 +1,389 @@
+//! A durable outbound queue with at-least-once delivery, available with the `queue` feature.
+//!
+//! Messages are persisted in a SQLite database before they are sent, so a campaign interrupted by a
+//! crash or restart resumes where it stopped. A message is marked as delivered as soon as FCM
+//! acknowledges it and is never sent again after that; a crash between the acknowledgement and the
+//! bookkeeping may send it once more. Each result is stored as soon as its send completes, so an
+//! interrupted `drain` only resends the messages that were in flight, once their claim expires.
+//!
+//! ```rust,no_run
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! use fcm::queue::{Queue, RetryPolicy};
+//! use fcm::{Message, Target};
+//!
+//! let client = fcm::Client::new().await?;
+//! let queue = Queue::open("outbox.sqlite")?.retry_policy(RetryPolicy::default());
+//!
+//! let id = queue.enqueue(&Message {
+//!     data: None,
+//!     notification: None,
+//!     target: Target::Topic("news".to_string()),
+//!     android: None,
+//!     webpush: None,
+//!     apns: None,
+//!     fcm_options: None,
+//! })?;
+//!
+//! let report = queue.drain(&client).await?;
+//! println!("{} delivered, {} failed, status of {}: {:?}", report.delivered, report.failed, id, queue.status(id)?);
+//! # Ok(())
+//! # }
+//! ```
+
+#[cfg(test)]
+mod tests;
+
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use futures_util::stream::{self, StreamExt};
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::client::metrics::Metrics;
+use crate::client::response::{FcmError, FcmResponse};
+use crate::client::sender::Sender;
+use crate::message::Message;
+pub use crate::retry::RetryPolicy;
+
+/// Number of due messages claimed from the database at once.
+const BATCH_SIZE: usize = 100;
+
+/// How long a claimed message is reserved for the drain that claimed it. Messages still claimed
+/// after that, because their drain stopped before storing the result, are sent again.
+const LEASE: Duration = Duration::from_secs(300);
+
+/// Id of a queued message.
+pub type JobId = i64;
+
+/// Errors of the queue's storage.
+#[derive(Debug, PartialEq)]
+pub enum QueueError {
+    /// The database failed.
+    Storage(String),
+
+    /// A message could not be stored or read back.
+    Serialization(String),
+}
+
+impl Error for QueueError {}
+
+impl fmt::Display for QueueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueueError::Storage(error) => write!(f, "queue storage failed: {error}"),
+            QueueError::Serialization(error) => write!(f, "queued message is invalid: {error}"),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for QueueError {
+    fn from(error: rusqlite::Error) -> QueueError {
+        QueueError::Storage(error.to_string())
+    }
+}
+
+impl From<serde_json::Error> for QueueError {
+    fn from(error: serde_json::Error) -> QueueError {
+        QueueError::Serialization(error.to_string())
+    }
+}
+
+/// Where a queued message stands.
+#[derive(Clone, Debug, PartialEq)]
+pub enum JobStatus {
+    /// Waiting to be sent or being sent, after `attempts` failed attempts.
+    Pending { attempts: u32 },
+
+    /// Acknowledged by FCM with the given message name.
+    Delivered { message_name: Option<String> },
+
+    /// Given up after `attempts` attempts, the last one failing with `error`.
+    Failed {
+        attempts: u32,
+        error_kind: String,
+        error: String,
+    },
+}
+
+/// What a call to [`Queue::drain`] did.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DrainReport {
+    /// Messages acknowledged by FCM.
+    pub delivered: usize,
+
+    /// Messages given up on.
+    pub failed: usize,
+
+    /// Failed attempts that were scheduled for a retry.
+    pub retried: usize,
+}
+
+/// A durable queue of messages, drained through any [`Sender`] such as the [`Client`](crate::Client).
+pub struct Queue {
+    connection: Mutex<Connection>,
+    retry_policy: RetryPolicy,
+    concurrency: usize,
+    metrics: Option<Arc<dyn Metrics>>,
+}
+
+impl fmt::Debug for Queue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Queue")
+            .field("retry_policy", &self.retry_policy)
+            .field("concurrency", &self.concurrency)
+            .field("metrics", &self.metrics.is_some())
+            .finish()
+    }
+}
+
+/// A pending message loaded for sending.
+struct QueuedJob {
+    id: JobId,
+    attempts: u32,
+    message: Message,
+}
+
+fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+impl Queue {
+    /// Open or create the queue stored at `path`. Messages left over from an earlier process are
+    /// sent by the next `drain`.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Queue, QueueError> {
+        Queue::with_connection(Connection::open(path)?)
+    }
+
+    /// Use a queue that only lives in memory.
+    pub fn open_in_memory() -> Result<Queue, QueueError> {
+        Queue::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(connection: Connection) -> Result<Queue, QueueError> {
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS fcm_queue (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                message TEXT NOT NULL,
+                status TEXT NOT NULL DEFAULT 'pending',
+                attempts INTEGER NOT NULL DEFAULT 0,
+                next_attempt_at INTEGER NOT NULL,
+                claimed_at INTEGER,
+                message_name TEXT,
+                error_kind TEXT,
+                error TEXT
+            );
+            CREATE INDEX IF NOT EXISTS fcm_queue_due ON fcm_queue (status, next_attempt_at);",
+        )?;
+        // messages left claimed by a process that stopped while sending them
+        connection.execute(
+            "UPDATE fcm_queue SET status = 'pending', claimed_at = NULL WHERE status = 'sending' AND claimed_at <= ?1",
+            params![now_millis() - LEASE.as_millis() as i64],
+        )?;
+
+        Ok(Queue {
+            connection: Mutex::new(connection),
+            retry_policy: RetryPolicy::default(),
+            concurrency: 10,
+            metrics: None,
+        })
+    }
+
+    /// Retry transient failures according to `retry_policy`.
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Queue {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Send at most `concurrency` messages at a time.
+    pub fn concurrency(mut self, concurrency: usize) -> Queue {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Report retries to `metrics`.
+    pub fn metrics(mut self, metrics: Arc<dyn Metrics>) -> Queue {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Persist `message` to be sent by the next `drain`.
+    pub fn enqueue(&self, message: &Message) -> Result<JobId, QueueError> {
+        let message = serde_json::to_string(message)?;
+        let connection = self.connection.lock().unwrap();
+        connection.execute(
+            "INSERT INTO fcm_queue (message, next_attempt_at) VALUES (?1, ?2)",
+            params![message, now_millis()],
+        )?;
+        Ok(connection.last_insert_rowid())
+    }
+
+    /// Where the message `id` stands, `None` if there is no such message.
+    pub fn status(&self, id: JobId) -> Result<Option<JobStatus>, QueueError> {
+        let connection = self.connection.lock().unwrap();
+        let status = connection
+            .query_row(
+                "SELECT status, attempts, message_name, error_kind, error FROM fcm_queue WHERE id = ?1",
+                params![id],
+                |row| {
+                    let status: String = row.get(0)?;
+                    let attempts: u32 = row.get(1)?;
+                    Ok(match status.as_str() {
+                        "delivered" => JobStatus::Delivered {
+                            message_name: row.get(2)?,
+                        },
+                        "failed" => JobStatus::Failed {
+                            attempts,
+                            error_kind: row.get::<_, Option<String>>(3)?.unwrap_or_default(),
+                            error: row.get::<_, Option<String>>(4)?.unwrap_or_default(),
+                        },
+                        _ => JobStatus::Pending { attempts },
+                    })
+                },
+            )
+            .optional()?;
+        Ok(status)
+    }
+
+    /// Number of messages still waiting to be sent, or being sent.
+    pub fn pending(&self) -> Result<usize, QueueError> {
+        let connection = self.connection.lock().unwrap();
+        let pending: i64 = connection.query_row(
+            "SELECT COUNT(*) FROM fcm_queue WHERE status IN ('pending', 'sending')",
+            [],
+            |row| row.get(0),
+        )?;
+        Ok(pending as usize)
+    }
+
+    /// Claim a batch of due messages, including those whose claim expired, so that concurrent drains
+    /// on the same database do not send them too. Also returns the time the next message not yet
+    /// due becomes due. Messages that can no longer be read back are marked as failed instead of
+    /// stopping the drain.
+    fn due(&self, report: &mut DrainReport) -> Result<(Vec<QueuedJob>, Option<i64>), QueueError> {
+        let now = now_millis();
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(
+            "UPDATE fcm_queue SET status = 'sending', claimed_at = ?1
+             WHERE id IN (
+                SELECT id FROM fcm_queue
+                WHERE (status = 'pending' AND next_attempt_at <= ?1) OR (status = 'sending' AND claimed_at <= ?2)
+                ORDER BY next_attempt_at, id LIMIT ?3
+             )
+             RETURNING id, next_attempt_at, attempts, message",
+        )?;
+        let mut rows = statement
+            .query_map(params![now, now - LEASE.as_millis() as i64, BATCH_SIZE as i64], |row| {
+                Ok((
+                    row.get::<_, JobId>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, u32>(2)?,
+                    row.get::<_, String>(3)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        rows.sort_by_key(|&(id, next_attempt_at, _, _)| (next_attempt_at, id));
+
+        let mut due = Vec::new();
+        for (id, _, attempts, message) in rows {
+            match serde_json::from_str(&message) {
+                Ok(message) => due.push(QueuedJob { id, attempts, message }),
+                Err(error) => {
+                    connection.execute(
+                        "UPDATE fcm_queue SET status = 'failed', error_kind = 'SERIALIZATION', error = ?2 WHERE id = ?1",
+                        params![id, QueueError::from(error).to_string()],
+                    )?;
+                    report.failed += 1;
+                }
+            }
+        }
+
+        let next: Option<i64> = connection.query_row(
+            "SELECT MIN(next_attempt_at) FROM fcm_queue WHERE status = 'pending' AND next_attempt_at > ?1",
+            params![now],
+            |row| row.get(0),
+        )?;
+        Ok((due, next))
+    }
+
+    fn record(
+        &self,
+        id: JobId,
+        attempts: u32,
+        result: &Result<FcmResponse, FcmError>,
+        report: &mut DrainReport,
+    ) -> Result<(), QueueError> {
+        let connection = self.connection.lock().unwrap();
+        match result {
+            Ok(response) => {
+                connection.execute(
+                    "UPDATE fcm_queue SET status = 'delivered', attempts = ?2, message_name = ?3 WHERE id = ?1",
+                    params![id, attempts, response.name],
+                )?;
+                report.delivered += 1;
+            }
+            Err(error) if error.is_retryable() && attempts < self.retry_policy.max_attempts => {
+                let next_attempt_at = now_millis() + self.retry_policy.backoff(attempts, error).as_millis() as i64;
+                connection.execute(
+                    "UPDATE fcm_queue SET status = 'pending', attempts = ?2, next_attempt_at = ?3, error_kind = ?4, error = ?5
+                     WHERE id = ?1",
+                    params![id, attempts, next_attempt_at, error.kind(), error.to_string()],
+                )?;
+                report.retried += 1;
+                if let Some(metrics) = &self.metrics {
+                    metrics.message_retried(attempts + 1);
+                }
+            }
+            Err(error) => {
+                connection.execute(
+                    "UPDATE fcm_queue SET status = 'failed', attempts = ?2, error_kind = ?3, error = ?4 WHERE id = ?1",
+                    params![id, attempts, error.kind(), error.to_string()],
+                )?;
+                report.failed += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Send pending messages through `sender` until every message is delivered or failed, waiting
+    /// for the backoff of retried messages.
+    pub async fn drain(&self, sender: &dyn Sender) -> Result<DrainReport, QueueError> {
+        let mut report = DrainReport::default();
+
+        loop {
+            let failed = report.failed;
+            let (due, next) = self.due(&mut report)?;
+
+            if due.is_empty() && report.failed > failed {
+                // A whole batch of unreadable messages, look further.
+                continue;
+            }
+            if due.is_empty() {
+                match next {
+                    Some(next) => {
+                        let wait = (next - now_millis()).max(0) as u64;
+                        tokio::time::sleep(Duration::from_millis(wait)).await;
+                        continue;
+                    }
+                    None => return Ok(report),
+                }
+            }
+
+            let mut results = stream::iter(due)
+                .map(|job| async move {
+                    let result = sender.send_attempt(&job.message, job.attempts + 1).await;
+                    (job, result)
+                })
+                .buffer_unordered(self.concurrency);
+
+            while let Some((job, result)) = results.next().await {
+                self.record(job.id, job.attempts + 1, &result, &mut report)?;
+            }
+        }
+    }
+}
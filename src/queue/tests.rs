@@ -0,0 +1,199 @@
+use std::time::Duration;
+
+use crate::queue::{DrainReport, JobStatus, Queue, RetryPolicy};
+use crate::testing::message;
+use crate::{Error, ErrorCode, FcmResponse, Message, RecordingSender, ScriptedSender, Sender, Target};
+
+fn no_backoff() -> RetryPolicy {
+    RetryPolicy {
+        max_attempts: 3,
+        initial_backoff: Duration::ZERO,
+        max_backoff: Duration::ZERO,
+    }
+}
+
+#[tokio::test]
+async fn test_drain_retries_transient_failures() {
+    let queue = Queue::open_in_memory().unwrap().retry_policy(no_backoff());
+    let first = queue.enqueue(&message("a")).unwrap();
+    let second = queue.enqueue(&message("b")).unwrap();
+    let third = queue.enqueue(&message("c")).unwrap();
+
+    let sender = ScriptedSender::new();
+    sender.push_err(Error::ServerError(None));
+    sender.push_err(Error::Rejected(ErrorCode::Unregistered, "gone".to_string()));
+    sender.push_ok(FcmResponse {
+        name: Some("projects/p/messages/3".to_string()),
+        ..Default::default()
+    });
+
+    let report = queue.drain(&sender).await.unwrap();
+
+    assert_eq!(
+        DrainReport {
+            delivered: 2,
+            failed: 1,
+            retried: 1,
+        },
+        report
+    );
+    assert_eq!(
+        Some(JobStatus::Delivered {
+            message_name: Some("projects/recorded/messages/4".to_string()),
+        }),
+        queue.status(first).unwrap()
+    );
+    assert_eq!(
+        Some(JobStatus::Failed {
+            attempts: 1,
+            error_kind: "UNREGISTERED".to_string(),
+            error: "message rejected with Unregistered: gone".to_string(),
+        }),
+        queue.status(second).unwrap()
+    );
+    assert_eq!(
+        Some(JobStatus::Delivered {
+            message_name: Some("projects/p/messages/3".to_string()),
+        }),
+        queue.status(third).unwrap()
+    );
+    assert_eq!(message("a"), sender.messages()[3]);
+    assert_eq!(0, queue.pending().unwrap());
+}
+
+#[tokio::test]
+async fn test_drain_gives_up_after_max_attempts() {
+    let queue = Queue::open_in_memory().unwrap().retry_policy(no_backoff());
+    let id = queue.enqueue(&message("a")).unwrap();
+
+    let sender = ScriptedSender::new();
+    for _ in 0..3 {
+        sender.push_err(Error::QuotaExceeded(None));
+    }
+
+    let report = queue.drain(&sender).await.unwrap();
+
+    assert_eq!(2, report.retried);
+    assert_eq!(1, report.failed);
+    assert!(matches!(
+        queue.status(id).unwrap(),
+        Some(JobStatus::Failed { attempts: 3, .. })
+    ));
+}
+
+#[tokio::test]
+async fn test_resume_without_resending_delivered_messages() {
+    let path = std::env::temp_dir().join(format!("fcm-queue-test-{}.sqlite", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    {
+        let queue = Queue::open(&path).unwrap();
+        queue.enqueue(&message("a")).unwrap();
+        queue.drain(&RecordingSender::new()).await.unwrap();
+        queue.enqueue(&message("b")).unwrap();
+    }
+
+    let queue = Queue::open(&path).unwrap();
+    assert_eq!(1, queue.pending().unwrap());
+
+    let sender = RecordingSender::new();
+    queue.drain(&sender).await.unwrap();
+    assert_eq!(vec![message("b")], sender.messages());
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn test_drain_fails_unreadable_messages_and_continues() {
+    let queue = Queue::open_in_memory().unwrap();
+    let poison = queue.enqueue(&message("a")).unwrap();
+    let id = queue.enqueue(&message("b")).unwrap();
+    queue
+        .connection
+        .lock()
+        .unwrap()
+        .execute("UPDATE fcm_queue SET message = '{' WHERE id = ?1", [poison])
+        .unwrap();
+
+    let sender = RecordingSender::new();
+    let report = queue.drain(&sender).await.unwrap();
+
+    assert_eq!(1, report.failed);
+    assert_eq!(1, report.delivered);
+    assert!(matches!(
+        queue.status(poison).unwrap(),
+        Some(JobStatus::Failed { attempts: 0, ref error_kind, .. }) if error_kind == "SERIALIZATION"
+    ));
+    assert!(matches!(queue.status(id).unwrap(), Some(JobStatus::Delivered { .. })));
+    assert_eq!(vec![message("b")], sender.messages());
+}
+
+/// Delivers the message to token "a" and never answers for any other.
+struct StallingSender;
+
+#[async_trait::async_trait]
+impl Sender for StallingSender {
+    async fn send(&self, message: &Message) -> Result<FcmResponse, Error> {
+        if message.target != Target::Token("a".to_string()) {
+            std::future::pending::<()>().await;
+        }
+        Ok(FcmResponse {
+            name: Some("projects/p/messages/a".to_string()),
+            ..Default::default()
+        })
+    }
+
+    async fn validate(&self, message: &Message) -> Result<FcmResponse, Error> {
+        self.send(message).await
+    }
+}
+
+#[tokio::test]
+async fn test_drain_persists_each_result_as_it_completes() {
+    let queue = Queue::open_in_memory().unwrap();
+    let delivered = queue.enqueue(&message("a")).unwrap();
+    let stalled = queue.enqueue(&message("b")).unwrap();
+
+    let drain = tokio::time::timeout(Duration::from_millis(50), queue.drain(&StallingSender)).await;
+
+    assert!(drain.is_err());
+    assert_eq!(
+        Some(JobStatus::Delivered {
+            message_name: Some("projects/p/messages/a".to_string()),
+        }),
+        queue.status(delivered).unwrap()
+    );
+    assert_eq!(Some(JobStatus::Pending { attempts: 0 }), queue.status(stalled).unwrap());
+}
+
+#[tokio::test]
+async fn test_drain_skips_claimed_messages_until_their_claim_expires() {
+    let path = std::env::temp_dir().join(format!("fcm-queue-lease-test-{}.sqlite", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let queue = Queue::open(&path).unwrap();
+    queue.enqueue(&message("b")).unwrap();
+
+    let drain = tokio::time::timeout(Duration::from_millis(50), queue.drain(&StallingSender)).await;
+    assert!(drain.is_err());
+
+    let sender = RecordingSender::new();
+    assert_eq!(DrainReport::default(), queue.drain(&sender).await.unwrap());
+    assert!(sender.messages().is_empty());
+    assert_eq!(1, queue.pending().unwrap());
+
+    // the stalled drain stopped long ago
+    queue
+        .connection
+        .lock()
+        .unwrap()
+        .execute("UPDATE fcm_queue SET claimed_at = 0", [])
+        .unwrap();
+    drop(queue);
+
+    let queue = Queue::open(&path).unwrap();
+    queue.drain(&sender).await.unwrap();
+    assert_eq!(vec![message("b")], sender.messages());
+    assert_eq!(0, queue.pending().unwrap());
+
+    let _ = std::fs::remove_file(&path);
+}
@@ -0,0 +1,45 @@
+//! Retries of transient send failures, shared by the queue and the scheduler.
+
+use std::time::Duration;
+
+use crate::client::response::FcmError;
+
+/// How often and when transient failures are retried. Other failures are final right away.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetryPolicy {
+    /// Attempts per message, including the first one.
+    pub max_attempts: u32,
+
+    /// Wait before the first retry, doubled for each further one.
+    pub initial_backoff: Duration,
+
+    /// Upper bound of the wait between attempts, unless FCM asks for more with `Retry-After`.
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 5,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(300),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// The wait after `attempts` failed attempts ending in `error`.
+    pub(crate) fn backoff(&self, attempts: u32, error: &FcmError) -> Duration {
+        let exponential = self
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempts.saturating_sub(1)))
+            .min(self.max_backoff);
+        let retry_after = match error {
+            FcmError::QuotaExceeded(Some(retry_after)) | FcmError::ServerError(Some(retry_after)) => {
+                retry_after.wait_time()
+            }
+            _ => Duration::ZERO,
+        };
+        exponential.max(retry_after)
+    }
+}
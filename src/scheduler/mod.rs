@@ -0,0 +1,473 @@
+//! Delayed delivery of messages, available with the `scheduler` feature.
+//!
+//! FCM delivers messages right away, so the [`Scheduler`] keeps messages in a SQLite database until
+//! their send time, optionally moved into a recipient's [`DeliveryWindow`] so nobody is woken up
+//! at night. Jobs survive restarts and can be cancelled until they are sent. Transient failures are
+//! retried according to a [`RetryPolicy`]. A job whose run stopped between sending it and storing
+//! the result is sent again once its claim expires, so delivery is at least once.
+//!
+//! ```rust,no_run
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! use fcm::scheduler::{DeliveryWindow, Scheduler};
+//! use fcm::{Message, Target};
+//! use time::macros::{offset, time};
+//!
+//! let client = fcm::Client::new().await?;
+//! let scheduler = Scheduler::open("schedule.sqlite")?;
+//!
+//! let message = Message {
+//!     data: None,
+//!     notification: None,
+//!     target: Target::Token("token".to_string()),
+//!     android: None,
+//!     webpush: None,
+//!     apns: None,
+//!     fcm_options: None,
+//! };
+//! let quiet_hours = DeliveryWindow::quiet_hours(offset!(+2), time!(22:00), time!(8:00));
+//! let id = scheduler.schedule_in_window(&message, time::OffsetDateTime::now_utc(), &quiet_hours)?;
+//!
+//! scheduler.run(&client, std::time::Duration::from_secs(1)).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+#[cfg(test)]
+mod tests;
+
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use futures_util::stream::{self, StreamExt};
+use rusqlite::{params, Connection, OptionalExtension};
+use time::{Duration, OffsetDateTime, Time, UtcOffset};
+
+use crate::client::metrics::Metrics;
+use crate::client::response::{FcmError, FcmResponse};
+use crate::client::sender::Sender;
+use crate::message::Message;
+pub use crate::retry::RetryPolicy;
+
+/// Number of due jobs claimed from the database at once.
+const BATCH_SIZE: usize = 100;
+
+/// How long a claimed job is reserved for the run that claimed it. Jobs still claimed after that,
+/// because their run stopped before storing the result, are sent again.
+const LEASE: std::time::Duration = std::time::Duration::from_secs(300);
+
+/// Id of a scheduled message.
+pub type JobId = i64;
+
+/// Errors of the scheduler's storage.
+#[derive(Debug, PartialEq)]
+pub enum SchedulerError {
+    /// The database failed.
+    Storage(String),
+
+    /// A message could not be stored or read back.
+    Serialization(String),
+}
+
+impl Error for SchedulerError {}
+
+impl fmt::Display for SchedulerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchedulerError::Storage(error) => write!(f, "scheduler storage failed: {error}"),
+            SchedulerError::Serialization(error) => write!(f, "scheduled message is invalid: {error}"),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for SchedulerError {
+    fn from(error: rusqlite::Error) -> SchedulerError {
+        SchedulerError::Storage(error.to_string())
+    }
+}
+
+impl From<serde_json::Error> for SchedulerError {
+    fn from(error: serde_json::Error) -> SchedulerError {
+        SchedulerError::Serialization(error.to_string())
+    }
+}
+
+/// The local hours in which a recipient may receive messages.
+///
+/// The window starts at `start` and ends before `end` in the recipient's `offset`. It may wrap
+/// around midnight, and covers the whole day when `start` equals `end`.
+///
+/// The offset is fixed, it does not follow daylight saving time. When the recipient's offset
+/// changes between now and the send time, build the window with the offset in effect at the send
+/// time, e.g. looked up in the recipient's time zone; otherwise the window is shifted by the change.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DeliveryWindow {
+    /// The recipient's offset from UTC.
+    pub offset: UtcOffset,
+
+    /// Local time from which messages are delivered.
+    pub start: Time,
+
+    /// Local time from which messages are held back.
+    pub end: Time,
+}
+
+impl DeliveryWindow {
+    /// Deliver between `start` and `end`, local time.
+    pub fn new(offset: UtcOffset, start: Time, end: Time) -> DeliveryWindow {
+        DeliveryWindow { offset, start, end }
+    }
+
+    /// Deliver at any time except between `quiet_start` and `quiet_end`, local time.
+    pub fn quiet_hours(offset: UtcOffset, quiet_start: Time, quiet_end: Time) -> DeliveryWindow {
+        DeliveryWindow {
+            offset,
+            start: quiet_end,
+            end: quiet_start,
+        }
+    }
+
+    /// Whether a message may be delivered at `at`.
+    pub fn contains(&self, at: OffsetDateTime) -> bool {
+        let local = at.to_offset(self.offset).time();
+        match self.start.cmp(&self.end) {
+            std::cmp::Ordering::Equal => true,
+            std::cmp::Ordering::Less => local >= self.start && local < self.end,
+            std::cmp::Ordering::Greater => local >= self.start || local < self.end,
+        }
+    }
+
+    /// The earliest time from `at` on at which a message may be delivered.
+    pub fn next_allowed(&self, at: OffsetDateTime) -> OffsetDateTime {
+        if self.contains(at) {
+            return at;
+        }
+
+        let local = at.to_offset(self.offset);
+        let start_today = local.replace_time(self.start);
+        let next = if start_today > local {
+            start_today
+        } else {
+            start_today + Duration::days(1)
+        };
+        next.to_offset(UtcOffset::UTC)
+    }
+}
+
+/// Where a scheduled message stands.
+#[derive(Clone, Debug, PartialEq)]
+pub enum JobStatus {
+    /// Waiting for its send time.
+    Scheduled { send_at: OffsetDateTime },
+
+    /// Claimed by a run that is sending it. If that run stops before it can store the result, the
+    /// job is sent again once its claim expires.
+    Sending,
+
+    /// Sent, with the message name given by FCM.
+    Sent { message_name: Option<String> },
+
+    /// Sending failed with `error`.
+    Failed { error_kind: String, error: String },
+
+    /// Cancelled before it was sent.
+    Cancelled,
+}
+
+/// Messages waiting for their send time, persisted in SQLite.
+pub struct Scheduler {
+    connection: Mutex<Connection>,
+    retry_policy: RetryPolicy,
+    concurrency: usize,
+    metrics: Option<Arc<dyn Metrics>>,
+}
+
+impl fmt::Debug for Scheduler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Scheduler")
+            .field("retry_policy", &self.retry_policy)
+            .field("concurrency", &self.concurrency)
+            .field("metrics", &self.metrics.is_some())
+            .finish()
+    }
+}
+
+/// A due job claimed for sending.
+#[derive(Debug, PartialEq)]
+struct ScheduledJob {
+    id: JobId,
+    attempts: u32,
+    message: Message,
+}
+
+fn timestamp(at: OffsetDateTime) -> i64 {
+    (at.unix_timestamp_nanos() / 1_000_000) as i64
+}
+
+fn from_timestamp(millis: i64) -> OffsetDateTime {
+    OffsetDateTime::from_unix_timestamp_nanos(i128::from(millis) * 1_000_000).unwrap_or(OffsetDateTime::UNIX_EPOCH)
+}
+
+impl Scheduler {
+    /// Open or create the schedule stored at `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Scheduler, SchedulerError> {
+        Scheduler::with_connection(Connection::open(path)?)
+    }
+
+    /// Use a schedule that only lives in memory.
+    pub fn open_in_memory() -> Result<Scheduler, SchedulerError> {
+        Scheduler::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(connection: Connection) -> Result<Scheduler, SchedulerError> {
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS fcm_schedule (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                message TEXT NOT NULL,
+                send_at INTEGER NOT NULL,
+                status TEXT NOT NULL DEFAULT 'scheduled',
+                attempts INTEGER NOT NULL DEFAULT 0,
+                claimed_at INTEGER,
+                message_name TEXT,
+                error_kind TEXT,
+                error TEXT
+            );
+            CREATE INDEX IF NOT EXISTS fcm_schedule_due ON fcm_schedule (status, send_at);",
+        )?;
+        // jobs left claimed by a process that stopped while sending them
+        connection.execute(
+            "UPDATE fcm_schedule SET status = 'scheduled', claimed_at = NULL WHERE status = 'sending' AND claimed_at <= ?1",
+            params![timestamp(OffsetDateTime::now_utc() - LEASE)],
+        )?;
+
+        Ok(Scheduler {
+            connection: Mutex::new(connection),
+            retry_policy: RetryPolicy::default(),
+            concurrency: 10,
+            metrics: None,
+        })
+    }
+
+    /// Retry transient failures according to `retry_policy`.
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Scheduler {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Send at most `concurrency` messages at a time.
+    pub fn concurrency(mut self, concurrency: usize) -> Scheduler {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Report retries to `metrics`.
+    pub fn metrics(mut self, metrics: Arc<dyn Metrics>) -> Scheduler {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Send `message` at `send_at`.
+    pub fn schedule(&self, message: &Message, send_at: OffsetDateTime) -> Result<JobId, SchedulerError> {
+        let message = serde_json::to_string(message)?;
+        let connection = self.connection.lock().unwrap();
+        connection.execute(
+            "INSERT INTO fcm_schedule (message, send_at) VALUES (?1, ?2)",
+            params![message, timestamp(send_at)],
+        )?;
+        Ok(connection.last_insert_rowid())
+    }
+
+    /// Send `message` at `send_at`, or at the next start of `window` if `send_at` falls outside of it.
+    pub fn schedule_in_window(
+        &self,
+        message: &Message,
+        send_at: OffsetDateTime,
+        window: &DeliveryWindow,
+    ) -> Result<JobId, SchedulerError> {
+        self.schedule(message, window.next_allowed(send_at))
+    }
+
+    /// Cancel the job `id`, returning whether it was still waiting to be sent.
+    pub fn cancel(&self, id: JobId) -> Result<bool, SchedulerError> {
+        let cancelled = self.connection.lock().unwrap().execute(
+            "UPDATE fcm_schedule SET status = 'cancelled' WHERE id = ?1 AND status = 'scheduled'",
+            params![id],
+        )?;
+        Ok(cancelled > 0)
+    }
+
+    /// Where the job `id` stands, `None` if there is no such job.
+    pub fn status(&self, id: JobId) -> Result<Option<JobStatus>, SchedulerError> {
+        let connection = self.connection.lock().unwrap();
+        let status = connection
+            .query_row(
+                "SELECT status, send_at, message_name, error_kind, error FROM fcm_schedule WHERE id = ?1",
+                params![id],
+                |row| {
+                    let status: String = row.get(0)?;
+                    Ok(match status.as_str() {
+                        "sent" => JobStatus::Sent {
+                            message_name: row.get(2)?,
+                        },
+                        "failed" => JobStatus::Failed {
+                            error_kind: row.get::<_, Option<String>>(3)?.unwrap_or_default(),
+                            error: row.get::<_, Option<String>>(4)?.unwrap_or_default(),
+                        },
+                        "cancelled" => JobStatus::Cancelled,
+                        "sending" => JobStatus::Sending,
+                        _ => JobStatus::Scheduled {
+                            send_at: from_timestamp(row.get(1)?),
+                        },
+                    })
+                },
+            )
+            .optional()?;
+        Ok(status)
+    }
+
+    /// The send time of the earliest job still waiting.
+    pub fn next_send_at(&self) -> Result<Option<OffsetDateTime>, SchedulerError> {
+        let next: Option<i64> = self.connection.lock().unwrap().query_row(
+            "SELECT MIN(send_at) FROM fcm_schedule WHERE status = 'scheduled'",
+            [],
+            |row| row.get(0),
+        )?;
+        Ok(next.map(from_timestamp))
+    }
+
+    /// Claim a batch of jobs whose send time has come, including those whose claim expired, so that
+    /// neither another run nor `cancel` can take them. Also returns the number of claimed jobs, as
+    /// jobs that can no longer be read back are marked as failed instead.
+    fn due(&self, now: OffsetDateTime) -> Result<(Vec<ScheduledJob>, usize), SchedulerError> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(
+            "UPDATE fcm_schedule SET status = 'sending', claimed_at = ?1
+             WHERE id IN (
+                SELECT id FROM fcm_schedule
+                WHERE (status = 'scheduled' AND send_at <= ?1) OR (status = 'sending' AND claimed_at <= ?2)
+                ORDER BY send_at, id LIMIT ?3
+             )
+             RETURNING id, send_at, attempts, message",
+        )?;
+        let mut rows = statement
+            .query_map(
+                params![timestamp(now), timestamp(now - LEASE), BATCH_SIZE as i64],
+                |row| {
+                    Ok((
+                        row.get::<_, JobId>(0)?,
+                        row.get::<_, i64>(1)?,
+                        row.get::<_, u32>(2)?,
+                        row.get::<_, String>(3)?,
+                    ))
+                },
+            )?
+            .collect::<Result<Vec<_>, _>>()?;
+        rows.sort_by_key(|&(id, send_at, _, _)| (send_at, id));
+
+        let claimed = rows.len();
+        let mut due = Vec::new();
+        for (id, _, attempts, message) in rows {
+            match serde_json::from_str(&message) {
+                Ok(message) => due.push(ScheduledJob { id, attempts, message }),
+                Err(error) => {
+                    connection.execute(
+                        "UPDATE fcm_schedule SET status = 'failed', error_kind = 'SERIALIZATION', error = ?2 WHERE id = ?1",
+                        params![id, SchedulerError::from(error).to_string()],
+                    )?;
+                }
+            }
+        }
+        Ok((due, claimed))
+    }
+
+    /// Store the result of the `attempts`th attempt at sending the job `id`, rescheduling transient
+    /// failures until the retry policy gives up.
+    fn record(&self, id: JobId, attempts: u32, result: &Result<FcmResponse, FcmError>) -> Result<(), SchedulerError> {
+        let connection = self.connection.lock().unwrap();
+        match result {
+            Ok(response) => connection.execute(
+                "UPDATE fcm_schedule SET status = 'sent', attempts = ?2, message_name = ?3
+                 WHERE id = ?1 AND status = 'sending'",
+                params![id, attempts, response.name],
+            )?,
+            Err(error) if error.is_retryable() && attempts < self.retry_policy.max_attempts => {
+                let send_at = OffsetDateTime::now_utc() + self.retry_policy.backoff(attempts, error);
+                let rescheduled = connection.execute(
+                    "UPDATE fcm_schedule SET status = 'scheduled', send_at = ?2, attempts = ?3, error_kind = ?4, error = ?5
+                     WHERE id = ?1 AND status = 'sending'",
+                    params![id, timestamp(send_at), attempts, error.kind(), error.to_string()],
+                )?;
+                if let Some(metrics) = &self.metrics {
+                    metrics.message_retried(attempts + 1);
+                }
+                rescheduled
+            }
+            Err(error) => connection.execute(
+                "UPDATE fcm_schedule SET status = 'failed', attempts = ?2, error_kind = ?3, error = ?4
+                 WHERE id = ?1 AND status = 'sending'",
+                params![id, attempts, error.kind(), error.to_string()],
+            )?,
+        };
+        Ok(())
+    }
+
+    /// Send every job whose send time has come through `sender`, returning the job ids with their
+    /// results in the order the sends completed. Jobs that fail transiently are rescheduled
+    /// according to the retry policy and are part of a later run.
+    ///
+    /// Each job is claimed before it is sent, so concurrent runs on the same database do not send it
+    /// twice. Results are stored as they complete; when storing one fails, the others are still
+    /// stored and the first storage error is returned once the claimed jobs are done.
+    pub async fn run_due(
+        &self,
+        sender: &dyn Sender,
+    ) -> Result<Vec<(JobId, Result<FcmResponse, FcmError>)>, SchedulerError> {
+        let mut sent = Vec::new();
+        let mut storage_error = None;
+
+        while storage_error.is_none() {
+            let (due, claimed) = self.due(OffsetDateTime::now_utc())?;
+            if claimed == 0 {
+                break;
+            }
+
+            let mut results = stream::iter(due)
+                .map(|job| async move {
+                    let result = sender.send_attempt(&job.message, job.attempts + 1).await;
+                    (job, result)
+                })
+                .buffer_unordered(self.concurrency);
+
+            while let Some((job, result)) = results.next().await {
+                // the job stays claimed and is sent again once its claim expires
+                if let Err(error) = self.record(job.id, job.attempts + 1, &result) {
+                    storage_error.get_or_insert(error);
+                }
+                sent.push((job.id, result));
+            }
+        }
+
+        match storage_error {
+            Some(error) => Err(error),
+            None => Ok(sent),
+        }
+    }
+
+    /// Send jobs through `sender` as their send time comes, checking for new jobs at least every
+    /// `poll_interval`. Only returns when the storage fails.
+    pub async fn run(&self, sender: &dyn Sender, poll_interval: std::time::Duration) -> Result<(), SchedulerError> {
+        loop {
+            self.run_due(sender).await?;
+
+            let wait = match self.next_send_at()? {
+                Some(next) => std::time::Duration::try_from(next - OffsetDateTime::now_utc())
+                    .unwrap_or_default()
+                    .min(poll_interval),
+                None => poll_interval,
+            };
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
@@ -0,0 +1,271 @@
+use time::macros::{datetime, offset, time};
+use time::{Duration, OffsetDateTime};
+
+use crate::scheduler::{DeliveryWindow, JobStatus, RetryPolicy, ScheduledJob, Scheduler, SchedulerError};
+use crate::testing::message;
+use crate::{Error, ErrorCode, Metrics, RecordingSender, ScriptedSender};
+
+#[test]
+fn test_delivery_window() {
+    let office_hours = DeliveryWindow::new(offset!(+2), time!(9:00), time!(17:00));
+
+    assert!(office_hours.contains(datetime!(2024-05-01 10:00 UTC)));
+    assert!(!office_hours.contains(datetime!(2024-05-01 15:00 UTC)));
+    assert_eq!(
+        datetime!(2024-05-01 7:00 UTC),
+        office_hours.next_allowed(datetime!(2024-05-01 5:00 UTC))
+    );
+    assert_eq!(
+        datetime!(2024-05-02 7:00 UTC),
+        office_hours.next_allowed(datetime!(2024-05-01 15:00 UTC))
+    );
+}
+
+#[test]
+fn test_quiet_hours_wrap_around_midnight() {
+    let quiet_hours = DeliveryWindow::quiet_hours(offset!(-5), time!(22:00), time!(7:00));
+
+    assert!(quiet_hours.contains(datetime!(2024-05-01 20:00 UTC)));
+    assert!(!quiet_hours.contains(datetime!(2024-05-02 4:00 UTC)));
+    assert_eq!(
+        datetime!(2024-05-02 12:00 UTC),
+        quiet_hours.next_allowed(datetime!(2024-05-02 4:00 UTC))
+    );
+}
+
+#[tokio::test]
+async fn test_run_due_sends_only_due_jobs() {
+    let scheduler = Scheduler::open_in_memory().unwrap();
+    let now = OffsetDateTime::now_utc();
+    let due = scheduler.schedule(&message("due"), now - Duration::minutes(1)).unwrap();
+    let later = scheduler.schedule(&message("later"), now + Duration::hours(1)).unwrap();
+    let cancelled = scheduler
+        .schedule(&message("cancelled"), now - Duration::minutes(1))
+        .unwrap();
+
+    assert!(scheduler.cancel(cancelled).unwrap());
+    assert!(!scheduler.cancel(cancelled).unwrap());
+
+    let sender = RecordingSender::new();
+    let sent = scheduler.run_due(&sender).await.unwrap();
+
+    assert_eq!(1, sent.len());
+    assert_eq!(due, sent[0].0);
+    assert_eq!(vec![message("due")], sender.messages());
+    assert!(matches!(scheduler.status(due).unwrap(), Some(JobStatus::Sent { .. })));
+    assert!(matches!(
+        scheduler.status(later).unwrap(),
+        Some(JobStatus::Scheduled { .. })
+    ));
+    assert_eq!(Some(JobStatus::Cancelled), scheduler.status(cancelled).unwrap());
+    assert!(scheduler.next_send_at().unwrap().unwrap() > now);
+}
+
+#[tokio::test]
+async fn test_schedule_in_window_defers_to_window_start() {
+    let scheduler = Scheduler::open_in_memory().unwrap();
+    let window = DeliveryWindow::new(offset!(UTC), time!(9:00), time!(17:00));
+
+    let id = scheduler
+        .schedule_in_window(&message("token"), datetime!(2024-05-01 20:00 UTC), &window)
+        .unwrap();
+
+    assert_eq!(
+        Some(JobStatus::Scheduled {
+            send_at: datetime!(2024-05-02 9:00 UTC),
+        }),
+        scheduler.status(id).unwrap()
+    );
+}
+
+#[test]
+fn test_delivery_window_offset_is_fixed_across_dst() {
+    // Berlin moves from +1 to +2 on 2024-03-31, so 9:00 local is 7:00 UTC from then on.
+    let before_change = DeliveryWindow::new(offset!(+1), time!(9:00), time!(17:00));
+    let after_change = DeliveryWindow::new(offset!(+2), time!(9:00), time!(17:00));
+    let evening = datetime!(2024-03-30 20:00 UTC);
+
+    assert_eq!(datetime!(2024-03-31 8:00 UTC), before_change.next_allowed(evening));
+    assert_eq!(datetime!(2024-03-31 7:00 UTC), after_change.next_allowed(evening));
+}
+
+#[tokio::test]
+async fn test_claimed_jobs_are_neither_cancelled_nor_sent_again() {
+    let scheduler = Scheduler::open_in_memory().unwrap();
+    let id = scheduler
+        .schedule(&message("due"), OffsetDateTime::now_utc() - Duration::minutes(1))
+        .unwrap();
+
+    let (claimed, _) = scheduler.due(OffsetDateTime::now_utc()).unwrap();
+
+    assert_eq!(
+        vec![ScheduledJob {
+            id,
+            attempts: 0,
+            message: message("due"),
+        }],
+        claimed
+    );
+    assert_eq!(Some(JobStatus::Sending), scheduler.status(id).unwrap());
+    assert!(!scheduler.cancel(id).unwrap());
+
+    let sender = RecordingSender::new();
+    assert!(scheduler.run_due(&sender).await.unwrap().is_empty());
+    assert!(sender.messages().is_empty());
+}
+
+#[tokio::test]
+async fn test_run_due_fails_unreadable_jobs_and_continues() {
+    let scheduler = Scheduler::open_in_memory().unwrap();
+    let now = OffsetDateTime::now_utc();
+    let poison = scheduler
+        .schedule(&message("poison"), now - Duration::minutes(2))
+        .unwrap();
+    let due = scheduler.schedule(&message("due"), now - Duration::minutes(1)).unwrap();
+    scheduler
+        .connection
+        .lock()
+        .unwrap()
+        .execute("UPDATE fcm_schedule SET message = '{' WHERE id = ?1", [poison])
+        .unwrap();
+
+    let sender = RecordingSender::new();
+    let sent = scheduler.run_due(&sender).await.unwrap();
+
+    assert_eq!(1, sent.len());
+    assert_eq!(due, sent[0].0);
+    assert!(matches!(
+        scheduler.status(poison).unwrap(),
+        Some(JobStatus::Failed { ref error_kind, .. }) if error_kind == "SERIALIZATION"
+    ));
+    assert!(matches!(scheduler.status(due).unwrap(), Some(JobStatus::Sent { .. })));
+}
+
+/// Records the attempts reported as retries.
+#[derive(Default)]
+struct RetriedAttempts(std::sync::Mutex<Vec<u32>>);
+
+impl Metrics for RetriedAttempts {
+    fn message_retried(&self, attempt: u32) {
+        self.0.lock().unwrap().push(attempt);
+    }
+}
+
+#[tokio::test]
+async fn test_run_due_reschedules_transient_failures() {
+    let metrics = std::sync::Arc::new(RetriedAttempts::default());
+    let scheduler = Scheduler::open_in_memory()
+        .unwrap()
+        .retry_policy(RetryPolicy {
+            max_attempts: 2,
+            initial_backoff: std::time::Duration::from_secs(60),
+            max_backoff: std::time::Duration::from_secs(60),
+        })
+        .metrics(metrics.clone());
+    let now = OffsetDateTime::now_utc();
+    let retried = scheduler
+        .schedule(&message("retried"), now - Duration::minutes(1))
+        .unwrap();
+
+    let sender = ScriptedSender::new();
+    sender.push_err(Error::ServerError(None));
+    sender.push_err(Error::ServerError(None));
+    let sent = scheduler.run_due(&sender).await.unwrap();
+
+    assert_eq!(1, sent.len());
+    let send_at = match scheduler.status(retried).unwrap() {
+        Some(JobStatus::Scheduled { send_at }) => send_at,
+        status => panic!("expected a rescheduled job, got {:?}", status),
+    };
+    assert!(send_at >= now + Duration::seconds(59));
+    assert_eq!(vec![2], *metrics.0.lock().unwrap());
+
+    // the retry is due
+    scheduler
+        .connection
+        .lock()
+        .unwrap()
+        .execute("UPDATE fcm_schedule SET send_at = 0", [])
+        .unwrap();
+    scheduler.run_due(&sender).await.unwrap();
+    assert!(matches!(
+        scheduler.status(retried).unwrap(),
+        Some(JobStatus::Failed { ref error_kind, .. }) if error_kind == "SERVER_ERROR"
+    ));
+    assert_eq!(2, sender.messages().len());
+
+    let rejected = scheduler
+        .schedule(&message("rejected"), now - Duration::minutes(1))
+        .unwrap();
+    sender.push_err(Error::Rejected(ErrorCode::Unregistered, "gone".to_string()));
+    scheduler.run_due(&sender).await.unwrap();
+    assert!(matches!(
+        scheduler.status(rejected).unwrap(),
+        Some(JobStatus::Failed { .. })
+    ));
+}
+
+#[tokio::test]
+async fn test_jobs_of_a_stopped_run_are_sent_again_once_their_claim_expires() {
+    let path = std::env::temp_dir().join(format!("fcm-scheduler-lease-test-{}.sqlite", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let scheduler = Scheduler::open(&path).unwrap();
+    let id = scheduler
+        .schedule(&message("due"), OffsetDateTime::now_utc() - Duration::minutes(1))
+        .unwrap();
+    scheduler.due(OffsetDateTime::now_utc()).unwrap();
+
+    // the run that claimed the job stopped long ago
+    scheduler
+        .connection
+        .lock()
+        .unwrap()
+        .execute("UPDATE fcm_schedule SET claimed_at = 0", [])
+        .unwrap();
+    drop(scheduler);
+
+    let scheduler = Scheduler::open(&path).unwrap();
+    assert!(matches!(
+        scheduler.status(id).unwrap(),
+        Some(JobStatus::Scheduled { .. })
+    ));
+    let sender = RecordingSender::new();
+    scheduler.run_due(&sender).await.unwrap();
+    assert_eq!(vec![message("due")], sender.messages());
+    assert!(matches!(scheduler.status(id).unwrap(), Some(JobStatus::Sent { .. })));
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn test_run_due_stores_the_other_results_when_storing_one_fails() {
+    let scheduler = Scheduler::open_in_memory().unwrap().concurrency(1);
+    let now = OffsetDateTime::now_utc();
+    let broken = scheduler
+        .schedule(&message("broken"), now - Duration::minutes(2))
+        .unwrap();
+    let stored = scheduler
+        .schedule(&message("stored"), now - Duration::minutes(1))
+        .unwrap();
+    scheduler
+        .connection
+        .lock()
+        .unwrap()
+        .execute_batch(&format!(
+            "CREATE TRIGGER fail_broken BEFORE UPDATE OF status ON fcm_schedule
+             WHEN NEW.id = {broken} AND NEW.status = 'sent'
+             BEGIN SELECT RAISE(ABORT, 'disk I/O error'); END;"
+        ))
+        .unwrap();
+
+    let sender = RecordingSender::new();
+    let result = scheduler.run_due(&sender).await;
+
+    assert!(matches!(result, Err(SchedulerError::Storage(_))));
+    assert_eq!(vec![message("broken"), message("stored")], sender.messages());
+    assert_eq!(Some(JobStatus::Sending), scheduler.status(broken).unwrap());
+    assert!(matches!(
+        scheduler.status(stored).unwrap(),
+        Some(JobStatus::Sent { .. })
+    ));
+}
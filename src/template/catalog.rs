@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::{fs, io};
+
+/// Server-side translations of template strings, keyed by locale and message key.
+///
+/// Catalogs can be filled programmatically or loaded from simple key/value files:
+///
+/// ```text
+/// # comments and blank lines are ignored
+/// welcome.title = Welcome, {name}!
+/// welcome.body = You have {count} new messages.
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Catalog {
+    locales: HashMap<String, HashMap<String, String>>,
+}
+
+impl Catalog {
+    /// Get an empty catalog.
+    pub fn new() -> Catalog {
+        Catalog::default()
+    }
+
+    /// Add or replace the translation of `key` for `locale`.
+    pub fn insert(&mut self, locale: &str, key: &str, value: &str) {
+        self.locales
+            .entry(locale.to_string())
+            .or_default()
+            .insert(key.to_string(), value.to_string());
+    }
+
+    /// Add all `key = value` lines of `content` as translations for `locale`.
+    pub fn load_str(&mut self, locale: &str, content: &str) -> io::Result<()> {
+        for (number, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            match line.split_once('=') {
+                Some((key, value)) => self.insert(locale, key.trim(), value.trim()),
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("line {} is not a `key = value` pair", number + 1),
+                    ))
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Add the translations of the key/value file at `path` for `locale`.
+    pub fn load_file<P: AsRef<Path>>(&mut self, locale: &str, path: P) -> io::Result<()> {
+        let content = fs::read_to_string(path)?;
+        self.load_str(locale, &content)
+    }
+
+    /// Load every `<locale>.properties` file of the directory at `path`.
+    pub fn load_dir<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        for entry in fs::read_dir(path)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("properties") {
+                continue;
+            }
+            if let Some(locale) = path.file_stem().and_then(|stem| stem.to_str()) {
+                let locale = locale.to_string();
+                self.load_file(&locale, &path)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Look up the translation of `key` for `locale`, falling back from a regional locale
+    /// (e.g. `pt-BR` or `pt_BR`) to its language (`pt`).
+    pub fn get(&self, locale: &str, key: &str) -> Option<&str> {
+        let lookup = |locale: &str| self.locales.get(locale).and_then(|strings| strings.get(key));
+
+        lookup(locale)
+            .or_else(|| {
+                let language = locale.split(['-', '_']).next()?;
+                lookup(language)
+            })
+            .map(String::as_str)
+    }
+}
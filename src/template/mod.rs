@@ -0,0 +1,241 @@
+pub mod catalog;
+
+#[cfg(test)]
+mod tests;
+
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+
+use serde_json::Value;
+
+use crate::message::target::Target;
+use crate::message::Message;
+use crate::notification::{Localized, Notification};
+
+use self::catalog::Catalog;
+
+/// Reasons why a `MessageTemplate` could not be rendered.
+#[derive(Debug, PartialEq)]
+pub enum TemplateError {
+    /// The template refers to a placeholder that is missing from the context.
+    MissingVariable(String),
+
+    /// The template contains an unterminated `{` or a stray `}`.
+    InvalidPlaceholder(String),
+}
+
+impl Error for TemplateError {}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateError::MissingVariable(name) => write!(f, "missing template variable {name}"),
+            TemplateError::InvalidPlaceholder(template) => write!(f, "invalid placeholder in {template:?}"),
+        }
+    }
+}
+
+/// Replace the `{name}` placeholders of `template` with the values of `context`. Literal braces are
+/// written as `{{` and `}}`.
+pub fn interpolate(template: &str, context: &HashMap<String, String>) -> Result<String, TemplateError> {
+    let invalid = || TemplateError::InvalidPlaceholder(template.to_string());
+    let mut output = String::with_capacity(template.len());
+    let mut chars = template.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                output.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                output.push('}');
+            }
+            '{' => {
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some('{') | None => return Err(invalid()),
+                        Some(c) => name.push(c),
+                    }
+                }
+                let name = name.trim();
+                match context.get(name) {
+                    Some(value) => output.push_str(value),
+                    None => return Err(TemplateError::MissingVariable(name.to_string())),
+                }
+            }
+            '}' => return Err(invalid()),
+            c => output.push(c),
+        }
+    }
+
+    Ok(output)
+}
+
+/// A notification whose texts are looked up in a `Catalog` and rendered per locale, or given as
+/// literal templates with `{name}` placeholders.
+///
+/// When the catalog has no translation of the title or body for the requested locale, the rendered
+/// message falls back to client-side localization: the key is sent as a `Localized` title or body
+/// (`title_loc_key`/`body_loc_key` on Android, `title-loc-key`/`loc-key` on iOS) with the values of the
+/// `*_args` context entries as format arguments, so the app's own string resources are used.
+///
+/// ```rust
+/// use std::collections::HashMap;
+/// use fcm::{Catalog, MessageTemplate, Target};
+///
+/// let mut catalog = Catalog::new();
+/// catalog.insert("de", "welcome", "Willkommen, {name}!");
+///
+/// let template = MessageTemplate {
+///     title_key: Some("welcome".to_string()),
+///     title_args: vec!["name".to_string()],
+///     ..Default::default()
+/// };
+///
+/// let mut context = HashMap::new();
+/// context.insert("name".to_string(), "Jane".to_string());
+///
+/// let message = template.render(Target::Token("token".to_string()), "de-AT", &context, &catalog).unwrap();
+/// assert_eq!(Some("Willkommen, Jane!".to_string()), message.notification.unwrap().title);
+///
+/// let template = MessageTemplate::literal("Hello, {name}!", "You have new messages.");
+/// let message = template.render(Target::Token("token".to_string()), "de", &context, &catalog).unwrap();
+/// assert_eq!(Some("Hello, Jane!".to_string()), message.notification.unwrap().title);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct MessageTemplate {
+    /// Literal template of the notification's title, used when `title_key` is not set.
+    pub title: Option<String>,
+
+    /// Catalog key of the notification's title.
+    pub title_key: Option<String>,
+
+    /// Context entries used as format arguments when the title is localized on the device.
+    pub title_args: Vec<String>,
+
+    /// Literal template of the notification's body text, used when `body_key` is not set.
+    pub body: Option<String>,
+
+    /// Catalog key of the notification's body text.
+    pub body_key: Option<String>,
+
+    /// Context entries used as format arguments when the body is localized on the device.
+    pub body_args: Vec<String>,
+
+    /// URL of the image displayed in the notification, may contain placeholders.
+    pub image: Option<String>,
+
+    /// Arbitrary key/value payload, values may contain placeholders.
+    pub data: BTreeMap<String, String>,
+}
+
+/// A text rendered on the server or left for the client to localize.
+enum Rendered {
+    Text(String),
+    ClientSide(Localized),
+}
+
+impl MessageTemplate {
+    /// A template with a literal `title` and `body`, rendered the same for every locale.
+    pub fn literal(title: &str, body: &str) -> MessageTemplate {
+        MessageTemplate {
+            title: Some(title.to_string()),
+            body: Some(body.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn render_text(
+        key: &str,
+        args: &[String],
+        locale: &str,
+        context: &HashMap<String, String>,
+        catalog: &Catalog,
+    ) -> Result<Rendered, TemplateError> {
+        if let Some(template) = catalog.get(locale, key) {
+            return interpolate(template, context).map(Rendered::Text);
+        }
+
+        let args = args
+            .iter()
+            .map(|arg| {
+                context
+                    .get(arg)
+                    .cloned()
+                    .ok_or_else(|| TemplateError::MissingVariable(arg.clone()))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Rendered::ClientSide(Localized {
+            key: key.to_string(),
+            args,
+        }))
+    }
+
+    /// Render a `Message` to `target` for `locale`, taking placeholder values from `context`.
+    pub fn render(
+        &self,
+        target: Target,
+        locale: &str,
+        context: &HashMap<String, String>,
+        catalog: &Catalog,
+    ) -> Result<Message, TemplateError> {
+        let mut notification = Notification::default();
+
+        if let Some(key) = &self.title_key {
+            match Self::render_text(key, &self.title_args, locale, context, catalog)? {
+                Rendered::Text(title) => notification.title = Some(title),
+                Rendered::ClientSide(localized) => notification.localized_title = Some(localized),
+            }
+        } else if let Some(title) = &self.title {
+            notification.title = Some(interpolate(title, context)?);
+        }
+
+        if let Some(key) = &self.body_key {
+            match Self::render_text(key, &self.body_args, locale, context, catalog)? {
+                Rendered::Text(body) => notification.body = Some(body),
+                Rendered::ClientSide(localized) => notification.localized_body = Some(localized),
+            }
+        } else if let Some(body) = &self.body {
+            notification.body = Some(interpolate(body, context)?);
+        }
+
+        notification.image = self
+            .image
+            .as_ref()
+            .map(|image| interpolate(image, context))
+            .transpose()?;
+
+        let data = if self.data.is_empty() {
+            None
+        } else {
+            let data = self
+                .data
+                .iter()
+                .map(|(key, value)| Ok((key.clone(), Value::String(interpolate(value, context)?))))
+                .collect::<Result<_, TemplateError>>()?;
+            Some(Value::Object(data))
+        };
+
+        let has_notification = notification.title.is_some()
+            || notification.body.is_some()
+            || notification.image.is_some()
+            || notification.localized_title.is_some()
+            || notification.localized_body.is_some();
+
+        Ok(Message {
+            data,
+            notification: has_notification.then_some(notification),
+            target,
+            android: None,
+            webpush: None,
+            apns: None,
+            fcm_options: None,
+        })
+    }
+}
@@ -0,0 +1,171 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{interpolate, Catalog, MessageTemplate, Target, TemplateError};
+use serde_json::json;
+
+fn context() -> HashMap<String, String> {
+    let mut context = HashMap::new();
+    context.insert("name".to_string(), "Jane".to_string());
+    context.insert("count".to_string(), "3".to_string());
+    context
+}
+
+fn catalog() -> Catalog {
+    let mut catalog = Catalog::new();
+    catalog
+        .load_str(
+            "en",
+            "# greetings\nwelcome.title = Welcome, {name}!\nwelcome.body = You have {count} new messages.\n",
+        )
+        .unwrap();
+    catalog.insert("fr", "welcome.title", "Bienvenue, {name} !");
+    catalog
+}
+
+fn template() -> MessageTemplate {
+    let mut data = BTreeMap::new();
+    data.insert("user".to_string(), "{name}".to_string());
+
+    MessageTemplate {
+        title_key: Some("welcome.title".to_string()),
+        title_args: vec!["name".to_string()],
+        body_key: Some("welcome.body".to_string()),
+        body_args: vec!["count".to_string()],
+        image: Some("https://my.image.com/{name}.jpg".to_string()),
+        data,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn should_interpolate_placeholders() {
+    assert_eq!(
+        Ok("Hi Jane, {literal}".to_string()),
+        interpolate("Hi {name}, {{literal}}", &context())
+    );
+    assert_eq!(
+        Err(TemplateError::MissingVariable("missing".to_string())),
+        interpolate("{missing}", &context())
+    );
+    assert_eq!(
+        Err(TemplateError::InvalidPlaceholder("{name".to_string())),
+        interpolate("{name", &context())
+    );
+}
+
+#[test]
+fn should_fall_back_to_the_language_of_a_regional_locale() {
+    let catalog = catalog();
+
+    assert_eq!(Some("Bienvenue, {name} !"), catalog.get("fr-CA", "welcome.title"));
+    assert_eq!(Some("Bienvenue, {name} !"), catalog.get("fr_CA", "welcome.title"));
+    assert_eq!(None, catalog.get("de", "welcome.title"));
+}
+
+#[test]
+fn should_render_server_side_translations() {
+    let message = template()
+        .render(Target::Token("token".to_string()), "en", &context(), &catalog())
+        .unwrap();
+
+    let expected_payload = json!({
+        "message": {
+            "data": {
+                "user": "Jane",
+            },
+            "notification": {
+                "title": "Welcome, Jane!",
+                "body": "You have 3 new messages.",
+                "image": "https://my.image.com/Jane.jpg",
+            },
+            "token": "token",
+        }
+    });
+
+    assert_eq!(expected_payload, message.to_request_json(false).unwrap());
+}
+
+#[test]
+fn should_fall_back_to_client_side_localization() {
+    let message = template()
+        .render(Target::Token("token".to_string()), "fr", &context(), &catalog())
+        .unwrap();
+
+    let expected_payload = json!({
+        "message": {
+            "data": {
+                "user": "Jane",
+            },
+            "notification": {
+                "title": "Bienvenue, Jane !",
+                "image": "https://my.image.com/Jane.jpg",
+            },
+            "android": {
+                "notification": {
+                    "body_loc_key": "welcome.body",
+                    "body_loc_args": ["3"],
+                },
+            },
+            "apns": {
+                "payload": {
+                    "aps": {
+                        "alert": {
+                            "loc-key": "welcome.body",
+                            "loc-args": ["3"],
+                        },
+                    },
+                },
+            },
+            "token": "token",
+        }
+    });
+
+    assert_eq!(expected_payload, message.to_request_json(false).unwrap());
+}
+
+#[test]
+fn should_require_client_side_arguments_in_the_context() {
+    let result = template().render(Target::Token("token".to_string()), "de", &HashMap::new(), &catalog());
+
+    assert_eq!(
+        Err(TemplateError::MissingVariable("name".to_string())),
+        result.map(|_| ())
+    );
+}
+
+#[test]
+fn should_render_literal_templates() {
+    let template = MessageTemplate::literal("Hi {name}", "You have {count} new {{messages}}.");
+
+    let message = template
+        .render(Target::Token("token".to_string()), "fr", &context(), &catalog())
+        .unwrap();
+
+    let expected_payload = json!({
+        "message": {
+            "notification": {
+                "title": "Hi Jane",
+                "body": "You have 3 new {messages}.",
+            },
+            "token": "token",
+        }
+    });
+
+    assert_eq!(expected_payload, message.to_request_json(false).unwrap());
+}
+
+#[test]
+fn should_prefer_catalog_keys_over_literal_templates() {
+    let template = MessageTemplate {
+        title_key: Some("welcome.title".to_string()),
+        ..MessageTemplate::literal("Hi {name}", "Bye {name}")
+    };
+
+    let message = template
+        .render(Target::Token("token".to_string()), "en", &context(), &catalog())
+        .unwrap();
+
+    let notification = message.notification.unwrap();
+    assert_eq!(Some("Welcome, Jane!".to_string()), notification.title);
+    assert_eq!(Some("Bye Jane".to_string()), notification.body);
+}
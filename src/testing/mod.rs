@@ -0,0 +1,442 @@
+//! An in-process mock of the FCM API for integration tests, available with the `testing` feature.
+//!
+//! The [`MockServer`] emulates `messages:send`, the OAuth token endpoint and the instance id topic
+//! endpoints on a local port, records every message it receives and can be scripted to answer with
+//! errors. Its instance id API knows the registration tokens that were ever (un)subscribed. It also
+//! accepts requests sent to it as a plain HTTP proxy.
+//!
+//! ```rust
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), fcm::Error> {
+//! use fcm::testing::{MockResponse, MockServer};
+//! use fcm::{ErrorCode, Message, Target};
+//!
+//! let server = MockServer::start();
+//! server.enqueue(MockResponse::unregistered());
+//!
+//! let client = server.client().await?;
+//! let message = Message {
+//!     data: None,
+//!     notification: None,
+//!     target: Target::Token("stale-token".to_string()),
+//!     android: None,
+//!     webpush: None,
+//!     apns: None,
+//!     fcm_options: None,
+//! };
+//!
+//! match client.send(&message).await {
+//!     Err(fcm::Error::Rejected(ErrorCode::Unregistered, _)) => {}
+//!     other => panic!("unexpected {:?}", other),
+//! }
+//! assert_eq!(1, server.messages().len());
+//! # Ok(())
+//! # }
+//! ```
+
+#[cfg(test)]
+mod tests;
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::client::response::FcmError;
+use crate::client::Client;
+
+const PRIVATE_KEY: &str = include_str!("mock_key.pem");
+const ACCESS_TOKEN: &str = "mock-access-token";
+
+/// The project id of the service account key written by the [`MockServer`].
+pub const PROJECT_ID: &str = "mock-project";
+
+/// A scripted answer of the mock `messages:send` endpoint.
+#[derive(Clone, Debug)]
+pub struct MockResponse {
+    status: u16,
+    body: Option<Value>,
+    retry_after: Option<String>,
+}
+
+impl MockResponse {
+    /// Accept the message.
+    pub fn success() -> MockResponse {
+        MockResponse {
+            status: 200,
+            body: None,
+            retry_after: None,
+        }
+    }
+
+    /// Answer with an FCM v1 error body carrying `error_code` (e.g. `"UNREGISTERED"`).
+    pub fn error(status: u16, error_code: &str, message: &str) -> MockResponse {
+        let body = json!({
+            "error": {
+                "code": status,
+                "message": message,
+                "status": error_code,
+                "details": [
+                    {
+                        "@type": "type.googleapis.com/google.firebase.fcm.v1.FcmError",
+                        "errorCode": error_code,
+                    }
+                ]
+            }
+        });
+
+        MockResponse {
+            status,
+            body: Some(body),
+            retry_after: None,
+        }
+    }
+
+    /// The registration token is no longer valid (404 `UNREGISTERED`).
+    pub fn unregistered() -> MockResponse {
+        MockResponse::error(404, "UNREGISTERED", "Requested entity was not found.")
+    }
+
+    /// The registration token is malformed (400 `INVALID_ARGUMENT` with a `message.token` field violation).
+    pub fn invalid_token() -> MockResponse {
+        let mut response = MockResponse::error(
+            400,
+            "INVALID_ARGUMENT",
+            "The registration token is not a valid FCM registration token",
+        );
+        if let Some(details) = response
+            .body
+            .as_mut()
+            .and_then(|body| body["error"]["details"].as_array_mut())
+        {
+            details.push(json!({
+                "@type": "type.googleapis.com/google.rpc.BadRequest",
+                "fieldViolations": [
+                    {
+                        "field": "message.token",
+                        "description": "The registration token is not a valid FCM registration token",
+                    }
+                ]
+            }));
+        }
+        response
+    }
+
+    /// The token belongs to another sender (403 `SENDER_ID_MISMATCH`).
+    pub fn sender_id_mismatch() -> MockResponse {
+        MockResponse::error(403, "SENDER_ID_MISMATCH", "SenderId mismatch")
+    }
+
+    /// The sending quota is exceeded (429 `QUOTA_EXCEEDED`), optionally with a `Retry-After` in seconds.
+    pub fn quota_exceeded(retry_after: Option<u64>) -> MockResponse {
+        let response = MockResponse::error(429, "QUOTA_EXCEEDED", "Quota exceeded.");
+        match retry_after {
+            Some(seconds) => response.with_retry_after(&seconds.to_string()),
+            None => response,
+        }
+    }
+
+    /// The server is overloaded (503 `UNAVAILABLE`).
+    pub fn unavailable() -> MockResponse {
+        MockResponse::error(503, "UNAVAILABLE", "The service is currently unavailable.")
+    }
+
+    /// Add a `Retry-After` header, either in seconds or as an HTTP date.
+    pub fn with_retry_after(mut self, retry_after: &str) -> MockResponse {
+        self.retry_after = Some(retry_after.to_string());
+        self
+    }
+}
+
+/// A request received by the mock `messages:send` endpoint.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReceivedMessage {
+    /// The project id from the request URL.
+    pub project_id: String,
+
+    /// Whether the request only validated the message.
+    pub validate_only: bool,
+
+    /// The `message` field of the request body.
+    pub message: Value,
+
+    /// The request headers, with lowercase names.
+    pub headers: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    responses: VecDeque<MockResponse>,
+    messages: Vec<ReceivedMessage>,
+    token_requests: usize,
+    topics: BTreeMap<String, BTreeSet<String>>,
+    tokens: BTreeSet<String>,
+}
+
+/// A local HTTP server emulating the FCM API. It is shut down when dropped.
+pub struct MockServer {
+    url: String,
+    key_path: PathBuf,
+    server: Arc<Server>,
+    state: Arc<Mutex<State>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MockServer {
+    /// Start a server on a free local port and write a matching service account key file.
+    pub fn start() -> MockServer {
+        let server = Arc::new(Server::http("127.0.0.1:0").expect("failed to bind mock server"));
+        let port = server
+            .server_addr()
+            .to_ip()
+            .expect("mock server is not bound to an ip")
+            .port();
+        let url = format!("http://127.0.0.1:{port}");
+
+        let key_path = std::env::temp_dir().join(format!("fcm-mock-{}-{port}.json", std::process::id()));
+        let key = json!({
+            "type": "service_account",
+            "project_id": PROJECT_ID,
+            "private_key_id": "mock",
+            "private_key": PRIVATE_KEY,
+            "client_email": format!("mock@{PROJECT_ID}.iam.gserviceaccount.com"),
+            "client_id": "0",
+            "auth_uri": format!("{url}/auth"),
+            "token_uri": format!("{url}/token"),
+            "auth_provider_x509_cert_url": format!("{url}/certs"),
+            "client_x509_cert_url": format!("{url}/certs/mock"),
+            "universe_domain": "googleapis.com",
+        });
+        std::fs::write(&key_path, key.to_string()).expect("failed to write mock service account key");
+
+        let state = Arc::new(Mutex::new(State::default()));
+        let thread = {
+            let server = Arc::clone(&server);
+            let state = Arc::clone(&state);
+            std::thread::spawn(move || {
+                for request in server.incoming_requests() {
+                    handle(request, &state);
+                }
+            })
+        };
+
+        MockServer {
+            url,
+            key_path,
+            server,
+            state,
+            thread: Some(thread),
+        }
+    }
+
+    /// Base URL of the server, to be used as FCM and instance id endpoint.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Path of the service account key file pointing at this server's token endpoint.
+    pub fn key_path(&self) -> &Path {
+        &self.key_path
+    }
+
+    /// Build a `Client` talking to this server.
+    pub async fn client(&self) -> Result<Client, FcmError> {
+        Client::builder()
+            .service_account_key_path(&self.key_path.to_string_lossy())
+            .fcm_endpoint(&self.url)
+            .iid_endpoint(&self.url)
+            .build()
+            .await
+    }
+
+    /// Answer the next `messages:send` request with `response`. Responses are used in the order
+    /// they were enqueued, messages are accepted once none are left.
+    pub fn enqueue(&self, response: MockResponse) {
+        self.state.lock().unwrap().responses.push_back(response);
+    }
+
+    /// All requests received by the `messages:send` endpoint so far.
+    pub fn messages(&self) -> Vec<ReceivedMessage> {
+        self.state.lock().unwrap().messages.clone()
+    }
+
+    /// Number of access tokens handed out by the OAuth token endpoint.
+    pub fn token_requests(&self) -> usize {
+        self.state.lock().unwrap().token_requests
+    }
+
+    /// Registration tokens currently subscribed to `topic`.
+    pub fn subscribers(&self, topic: &str) -> Vec<String> {
+        let state = self.state.lock().unwrap();
+        state
+            .topics
+            .get(topic)
+            .map(|tokens| tokens.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.server.unblock();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        let _ = std::fs::remove_file(&self.key_path);
+    }
+}
+
+/// A message with a small data payload for the registration `token`, shared by the crate's tests.
+#[cfg(test)]
+pub(crate) fn message(token: &str) -> crate::Message {
+    crate::Message {
+        data: Some(json!({ "foo": "bar" })),
+        notification: None,
+        target: crate::Target::Token(token.to_string()),
+        android: None,
+        webpush: None,
+        apns: None,
+        fcm_options: None,
+    }
+}
+
+fn json_response(status: u16, body: &Value) -> Response<std::io::Cursor<Vec<u8>>> {
+    let content_type = Header::from_bytes("Content-Type", "application/json").unwrap();
+    Response::from_data(body.to_string().into_bytes())
+        .with_status_code(status)
+        .with_header(content_type)
+}
+
+fn is_authorized(request: &Request) -> bool {
+    request
+        .headers()
+        .iter()
+        .any(|header| header.field.equiv("Authorization") && header.value.as_str() == format!("Bearer {ACCESS_TOKEN}"))
+}
+
+/// `segment` with percent-encoded bytes decoded.
+fn percent_decode(segment: &str) -> String {
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|hex| std::str::from_utf8(hex).ok());
+        match hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+            Some(byte) if bytes[i] == b'%' => {
+                decoded.push(byte);
+                i += 3;
+            }
+            _ => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn handle(mut request: Request, state: &Mutex<State>) {
+    let mut body = String::new();
+    let _ = request.as_reader().read_to_string(&mut body);
+    let body: Value = serde_json::from_str(&body).unwrap_or(Value::Null);
+    let url = request.url().to_string();
+    let path = url.split('?').next().unwrap_or_default();
+    // requests sent through the server as HTTP proxy carry the absolute URL
+    let path = match path.strip_prefix("http://") {
+        Some(absolute) => absolute.find('/').map_or("/", |start| &absolute[start..]),
+        None => path,
+    }
+    .to_string();
+
+    let unauthorized =
+        json!({ "error": { "code": 401, "message": "Request had invalid authentication credentials." } });
+    let not_found = json!({ "error": { "code": 404, "message": "Not found." } });
+
+    let response = match (request.method(), path.as_str()) {
+        (Method::Post, "/token") => {
+            state.lock().unwrap().token_requests += 1;
+            json_response(
+                200,
+                &json!({ "access_token": ACCESS_TOKEN, "expires_in": 3600, "token_type": "Bearer" }),
+            )
+        }
+        (_, _) if !is_authorized(&request) => json_response(401, &unauthorized),
+        (Method::Post, path) if path.starts_with("/v1/projects/") && path.ends_with("/messages:send") => {
+            let project_id = path["/v1/projects/".len()..path.len() - "/messages:send".len()].to_string();
+            let mut state = state.lock().unwrap();
+            state.messages.push(ReceivedMessage {
+                project_id: project_id.clone(),
+                validate_only: body["validate_only"].as_bool().unwrap_or(false),
+                message: body["message"].clone(),
+                headers: request
+                    .headers()
+                    .iter()
+                    .map(|header| (header.field.as_str().as_str().to_lowercase(), header.value.to_string()))
+                    .collect(),
+            });
+            let message_id = state.messages.len();
+
+            let scripted = state.responses.pop_front().unwrap_or_else(MockResponse::success);
+            let body = scripted
+                .body
+                .unwrap_or_else(|| json!({ "name": format!("projects/{project_id}/messages/{message_id}") }));
+            let mut response = json_response(scripted.status, &body);
+            if let Some(retry_after) = scripted.retry_after {
+                response.add_header(Header::from_bytes("Retry-After", retry_after).unwrap());
+            }
+            response
+        }
+        (Method::Post, "/iid/v1:batchAdd") | (Method::Post, "/iid/v1:batchRemove") => {
+            let topic = body["to"].as_str().unwrap_or_default();
+            let topic = topic.strip_prefix("/topics/").unwrap_or(topic).to_string();
+            let tokens: Vec<String> = body["registration_tokens"]
+                .as_array()
+                .map(|tokens| tokens.iter().filter_map(|t| t.as_str().map(str::to_string)).collect())
+                .unwrap_or_default();
+
+            let mut state = state.lock().unwrap();
+            state.tokens.extend(tokens.iter().cloned());
+            let subscribers = state.topics.entry(topic).or_default();
+            for token in &tokens {
+                if path == "/iid/v1:batchAdd" {
+                    subscribers.insert(token.clone());
+                } else {
+                    subscribers.remove(token);
+                }
+            }
+
+            let results: Vec<Value> = tokens.iter().map(|_| json!({})).collect();
+            json_response(200, &json!({ "results": results }))
+        }
+        (Method::Get, path) if path.starts_with("/iid/info/") => {
+            let token = percent_decode(&path["/iid/info/".len()..]);
+            let state = state.lock().unwrap();
+            let topics: serde_json::Map<String, Value> = state
+                .topics
+                .iter()
+                .filter(|(_, tokens)| tokens.contains(&token))
+                .map(|(topic, _)| (topic.clone(), json!({ "addDate": "2024-01-01" })))
+                .collect();
+
+            if state.tokens.contains(&token) {
+                json_response(
+                    200,
+                    &json!({
+                        "application": "com.example.mock",
+                        "authorizedEntity": PROJECT_ID,
+                        "platform": "ANDROID",
+                        "rel": { "topics": topics },
+                    }),
+                )
+            } else {
+                json_response(404, &json!({ "error": "No information found about this instance id." }))
+            }
+        }
+        _ => json_response(404, &not_found),
+    };
+
+    let _ = request.respond(response);
+}
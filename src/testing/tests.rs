@@ -0,0 +1,68 @@
+use crate::testing::{message, MockResponse, MockServer, PROJECT_ID};
+use crate::{Error, ErrorCode, RetryAfter};
+use serde_json::json;
+use time::Duration;
+
+#[tokio::test]
+async fn should_record_sent_messages() {
+    let server = MockServer::start();
+    let client = server.client().await.unwrap();
+
+    let response = client.send(&message("token")).await.unwrap();
+    client.validate(&message("other")).await.unwrap();
+
+    assert_eq!(Some(format!("projects/{PROJECT_ID}/messages/1")), response.name);
+    assert_eq!(1, server.token_requests());
+
+    let messages = server.messages();
+    assert_eq!(2, messages.len());
+    assert_eq!(PROJECT_ID, messages[0].project_id);
+    assert!(!messages[0].validate_only);
+    assert_eq!(
+        json!({ "data": { "foo": "bar" }, "token": "token" }),
+        messages[0].message
+    );
+    assert!(messages[1].validate_only);
+}
+
+#[tokio::test]
+async fn should_return_scripted_errors() {
+    let server = MockServer::start();
+    server.enqueue(MockResponse::unregistered());
+    server.enqueue(MockResponse::quota_exceeded(Some(30)));
+    server.enqueue(MockResponse::unavailable());
+    server.enqueue(MockResponse::invalid_token());
+    server.enqueue(MockResponse::error(
+        400,
+        "INVALID_ARGUMENT",
+        "Invalid value at 'message.data'",
+    ));
+    let client = server.client().await.unwrap();
+
+    match client.send(&message("token")).await {
+        Err(Error::Rejected(ErrorCode::Unregistered, _)) => {}
+        other => panic!("unexpected {:?}", other),
+    }
+    assert_eq!(
+        Err(Error::QuotaExceeded(Some(RetryAfter::Delay(Duration::seconds(30))))),
+        client.send(&message("token")).await.map(|_| ())
+    );
+    assert_eq!(
+        Err(Error::ServerError(None)),
+        client.send(&message("token")).await.map(|_| ())
+    );
+    assert_eq!(
+        Err(Error::InvalidToken(
+            "The registration token is not a valid FCM registration token".to_string()
+        )),
+        client.send(&message("token")).await.map(|_| ())
+    );
+    assert_eq!(
+        Err(Error::Rejected(
+            ErrorCode::InvalidArgument,
+            "Invalid value at 'message.data'".to_string()
+        )),
+        client.send(&message("token")).await.map(|_| ())
+    );
+    assert!(client.send(&message("token")).await.is_ok());
+}
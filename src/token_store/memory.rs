@@ -0,0 +1,64 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use time::OffsetDateTime;
+
+use super::{Registration, TokenStore, TokenStoreError};
+
+/// A `TokenStore` keeping registrations in memory, for tests and single process deployments.
+#[derive(Debug, Default)]
+pub struct InMemoryTokenStore {
+    registrations: Mutex<HashMap<String, Registration>>,
+}
+
+impl InMemoryTokenStore {
+    /// Get an empty store.
+    pub fn new() -> InMemoryTokenStore {
+        InMemoryTokenStore::default()
+    }
+}
+
+#[async_trait]
+impl TokenStore for InMemoryTokenStore {
+    async fn register(&self, registration: Registration) -> Result<(), TokenStoreError> {
+        let registration = Registration {
+            stale: false,
+            ..registration
+        };
+        self.registrations
+            .lock()
+            .unwrap()
+            .insert(registration.token.clone(), registration);
+        Ok(())
+    }
+
+    async fn registrations(&self, user_id: &str) -> Result<Vec<Registration>, TokenStoreError> {
+        let mut registrations: Vec<Registration> = self
+            .registrations
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|registration| registration.user_id == user_id)
+            .cloned()
+            .collect();
+        registrations.sort_by(|a, b| a.token.cmp(&b.token));
+        Ok(registrations)
+    }
+
+    async fn remove(&self, token: &str) -> Result<(), TokenStoreError> {
+        self.registrations.lock().unwrap().remove(token);
+        Ok(())
+    }
+
+    async fn mark_stale_before(&self, cutoff: OffsetDateTime) -> Result<usize, TokenStoreError> {
+        let mut marked = 0;
+        for registration in self.registrations.lock().unwrap().values_mut() {
+            if !registration.stale && registration.last_seen < cutoff {
+                registration.stale = true;
+                marked += 1;
+            }
+        }
+        Ok(marked)
+    }
+}
@@ -0,0 +1,114 @@
+pub mod memory;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+#[cfg(test)]
+mod tests;
+
+use std::error::Error;
+use std::fmt;
+
+use async_trait::async_trait;
+use time::{Duration, OffsetDateTime};
+
+use crate::message::platform::Platform;
+
+/// A registration token of a user's device, as reported by the app.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Registration {
+    /// The user the device belongs to.
+    pub user_id: String,
+
+    /// The FCM registration token of the app installation.
+    pub token: String,
+
+    /// The platform of the device.
+    pub platform: Platform,
+
+    /// The version of the app that registered the token.
+    pub app_version: Option<String>,
+
+    /// When the app last reported the token.
+    pub last_seen: OffsetDateTime,
+
+    /// Whether the token was not seen for too long, see [`TokenStore::mark_stale`].
+    pub stale: bool,
+}
+
+impl Registration {
+    /// A registration of `token` for `user_id`, seen now.
+    pub fn new(user_id: &str, token: &str, platform: Platform) -> Registration {
+        Registration {
+            user_id: user_id.to_string(),
+            token: token.to_string(),
+            platform,
+            app_version: None,
+            last_seen: OffsetDateTime::now_utc(),
+            stale: false,
+        }
+    }
+
+    /// Set the version of the app that registered the token.
+    pub fn with_app_version(mut self, app_version: &str) -> Registration {
+        self.app_version = Some(app_version.to_string());
+        self
+    }
+}
+
+/// Errors of a `TokenStore` backend.
+#[derive(Debug, PartialEq)]
+pub enum TokenStoreError {
+    /// The storage backend failed.
+    Backend(String),
+
+    /// The client was built without a token store, see
+    /// [`ClientBuilder::token_store`](crate::ClientBuilder::token_store).
+    NotConfigured,
+}
+
+impl Error for TokenStoreError {}
+
+impl fmt::Display for TokenStoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenStoreError::Backend(error) => write!(f, "token store failed: {error}"),
+            TokenStoreError::NotConfigured => write!(f, "no token store configured"),
+        }
+    }
+}
+
+/// Storage of the registration tokens of every user.
+///
+/// Given to [`ClientBuilder::token_store`](crate::ClientBuilder::token_store), tokens FCM reports
+/// as invalid are removed automatically and [`Client::send_to_user`](crate::Client::send_to_user)
+/// sends to every token of a user.
+#[async_trait]
+pub trait TokenStore: Send + Sync {
+    /// Record a registration, replacing an earlier one of the same token and clearing its stale flag.
+    async fn register(&self, registration: Registration) -> Result<(), TokenStoreError>;
+
+    /// All registrations of `user_id`, including stale ones.
+    async fn registrations(&self, user_id: &str) -> Result<Vec<Registration>, TokenStoreError>;
+
+    /// Forget `token`.
+    async fn remove(&self, token: &str) -> Result<(), TokenStoreError>;
+
+    /// Mark every token last seen before `cutoff` as stale, returning how many were newly marked.
+    async fn mark_stale_before(&self, cutoff: OffsetDateTime) -> Result<usize, TokenStoreError>;
+
+    /// Mark every token not seen for `max_age` as stale, returning how many were newly marked.
+    async fn mark_stale(&self, max_age: Duration) -> Result<usize, TokenStoreError> {
+        self.mark_stale_before(OffsetDateTime::now_utc() - max_age).await
+    }
+
+    /// The tokens of `user_id` that are not stale.
+    async fn active_tokens(&self, user_id: &str) -> Result<Vec<String>, TokenStoreError> {
+        Ok(self
+            .registrations(user_id)
+            .await?
+            .into_iter()
+            .filter(|registration| !registration.stale)
+            .map(|registration| registration.token)
+            .collect())
+    }
+}
@@ -0,0 +1,151 @@
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use rusqlite::{params, Connection};
+use time::OffsetDateTime;
+
+use super::{Registration, TokenStore, TokenStoreError};
+use crate::message::platform::Platform;
+
+/// A `TokenStore` persisting registrations in a SQLite database, available with the `sqlite` feature.
+///
+/// Queries run on tokio's blocking thread pool, so they never stall the runtime's workers.
+#[derive(Debug)]
+pub struct SqliteTokenStore {
+    connection: Arc<Mutex<Connection>>,
+}
+
+fn backend(error: rusqlite::Error) -> TokenStoreError {
+    TokenStoreError::Backend(error.to_string())
+}
+
+fn platform_name(platform: Platform) -> &'static str {
+    match platform {
+        Platform::Android => "android",
+        Platform::Ios => "ios",
+        Platform::Web => "web",
+    }
+}
+
+fn parse_platform(name: &str) -> Platform {
+    match name {
+        "ios" => Platform::Ios,
+        "web" => Platform::Web,
+        _ => Platform::Android,
+    }
+}
+
+impl SqliteTokenStore {
+    /// Open or create the database at `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<SqliteTokenStore, TokenStoreError> {
+        SqliteTokenStore::with_connection(Connection::open(path).map_err(backend)?)
+    }
+
+    /// Use a database that only lives in memory.
+    pub fn open_in_memory() -> Result<SqliteTokenStore, TokenStoreError> {
+        SqliteTokenStore::with_connection(Connection::open_in_memory().map_err(backend)?)
+    }
+
+    fn with_connection(connection: Connection) -> Result<SqliteTokenStore, TokenStoreError> {
+        connection
+            .execute_batch(
+                "CREATE TABLE IF NOT EXISTS fcm_tokens (
+                    token TEXT PRIMARY KEY,
+                    user_id TEXT NOT NULL,
+                    platform TEXT NOT NULL,
+                    app_version TEXT,
+                    last_seen INTEGER NOT NULL,
+                    stale INTEGER NOT NULL DEFAULT 0
+                );
+                CREATE INDEX IF NOT EXISTS fcm_tokens_user_id ON fcm_tokens (user_id);",
+            )
+            .map_err(backend)?;
+
+        Ok(SqliteTokenStore {
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    /// Run `query` on the blocking thread pool.
+    async fn blocking<T, F>(&self, query: F) -> Result<T, TokenStoreError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Connection) -> rusqlite::Result<T> + Send + 'static,
+    {
+        let connection = Arc::clone(&self.connection);
+        tokio::task::spawn_blocking(move || query(&connection.lock().unwrap()).map_err(backend))
+            .await
+            .map_err(|err| TokenStoreError::Backend(err.to_string()))?
+    }
+}
+
+#[async_trait]
+impl TokenStore for SqliteTokenStore {
+    async fn register(&self, registration: Registration) -> Result<(), TokenStoreError> {
+        self.blocking(move |connection| {
+            connection.execute(
+                "INSERT INTO fcm_tokens (token, user_id, platform, app_version, last_seen, stale)
+                 VALUES (?1, ?2, ?3, ?4, ?5, 0)
+                 ON CONFLICT (token) DO UPDATE SET
+                    user_id = excluded.user_id,
+                    platform = excluded.platform,
+                    app_version = excluded.app_version,
+                    last_seen = excluded.last_seen,
+                    stale = 0",
+                params![
+                    registration.token,
+                    registration.user_id,
+                    platform_name(registration.platform),
+                    registration.app_version,
+                    registration.last_seen.unix_timestamp(),
+                ],
+            )
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn registrations(&self, user_id: &str) -> Result<Vec<Registration>, TokenStoreError> {
+        let user_id = user_id.to_string();
+        self.blocking(move |connection| {
+            let mut statement = connection.prepare(
+                "SELECT token, user_id, platform, app_version, last_seen, stale
+                 FROM fcm_tokens WHERE user_id = ?1 ORDER BY token",
+            )?;
+
+            let rows = statement.query_map(params![user_id], |row| {
+                let platform: String = row.get(2)?;
+                let last_seen: i64 = row.get(4)?;
+                Ok(Registration {
+                    token: row.get(0)?,
+                    user_id: row.get(1)?,
+                    platform: parse_platform(&platform),
+                    app_version: row.get(3)?,
+                    last_seen: OffsetDateTime::from_unix_timestamp(last_seen).unwrap_or(OffsetDateTime::UNIX_EPOCH),
+                    stale: row.get(5)?,
+                })
+            })?;
+
+            rows.collect()
+        })
+        .await
+    }
+
+    async fn remove(&self, token: &str) -> Result<(), TokenStoreError> {
+        let token = token.to_string();
+        self.blocking(move |connection| connection.execute("DELETE FROM fcm_tokens WHERE token = ?1", params![token]))
+            .await?;
+        Ok(())
+    }
+
+    async fn mark_stale_before(&self, cutoff: OffsetDateTime) -> Result<usize, TokenStoreError> {
+        self.blocking(move |connection| {
+            connection.execute(
+                "UPDATE fcm_tokens SET stale = 1 WHERE stale = 0 AND last_seen < ?1",
+                params![cutoff.unix_timestamp()],
+            )
+        })
+        .await
+    }
+}
@@ -0,0 +1,105 @@
+use std::sync::Arc;
+
+use serde_json::json;
+use time::{Duration, OffsetDateTime};
+
+use crate::testing::{message, MockResponse, MockServer};
+use crate::token_store::memory::InMemoryTokenStore;
+use crate::token_store::{Registration, TokenStore, TokenStoreError};
+use crate::{AdaptiveConcurrency, Client, Platform};
+
+async fn check_lifecycle(store: &dyn TokenStore) {
+    let long_ago = OffsetDateTime::now_utc() - Duration::days(90);
+    store
+        .register(Registration {
+            last_seen: long_ago,
+            ..Registration::new("alice", "old", Platform::Ios)
+        })
+        .await
+        .unwrap();
+    store
+        .register(Registration::new("alice", "phone", Platform::Android).with_app_version("2.1.0"))
+        .await
+        .unwrap();
+    store
+        .register(Registration::new("bob", "laptop", Platform::Web))
+        .await
+        .unwrap();
+
+    assert_eq!(1, store.mark_stale(Duration::days(30)).await.unwrap());
+    assert_eq!(0, store.mark_stale(Duration::days(30)).await.unwrap());
+
+    let registrations = store.registrations("alice").await.unwrap();
+    assert_eq!(2, registrations.len());
+    assert!(registrations[0].stale);
+    assert_eq!(Platform::Android, registrations[1].platform);
+    assert_eq!(Some("2.1.0".to_string()), registrations[1].app_version);
+    assert_eq!(vec!["phone".to_string()], store.active_tokens("alice").await.unwrap());
+
+    store
+        .register(Registration::new("alice", "old", Platform::Ios))
+        .await
+        .unwrap();
+    assert_eq!(2, store.active_tokens("alice").await.unwrap().len());
+
+    store.remove("old").await.unwrap();
+    assert_eq!(vec!["phone".to_string()], store.active_tokens("alice").await.unwrap());
+    assert_eq!(vec!["laptop".to_string()], store.active_tokens("bob").await.unwrap());
+}
+
+#[tokio::test]
+async fn test_in_memory_lifecycle() {
+    check_lifecycle(&InMemoryTokenStore::new()).await;
+}
+
+#[cfg(feature = "sqlite")]
+#[tokio::test]
+async fn test_sqlite_lifecycle() {
+    let store = crate::token_store::sqlite::SqliteTokenStore::open_in_memory().unwrap();
+    check_lifecycle(&store).await;
+}
+
+#[tokio::test]
+async fn test_send_to_user_removes_invalid_tokens() {
+    let server = MockServer::start();
+    server.enqueue(MockResponse::success());
+    server.enqueue(MockResponse::unregistered());
+    let store = Arc::new(InMemoryTokenStore::new());
+    store
+        .register(Registration::new("alice", "a", Platform::Android))
+        .await
+        .unwrap();
+    store
+        .register(Registration::new("alice", "b", Platform::Ios))
+        .await
+        .unwrap();
+
+    let client = Client::builder()
+        .service_account_key_path(&server.key_path().to_string_lossy())
+        .fcm_endpoint(server.url())
+        .adaptive_concurrency(AdaptiveConcurrency::fixed(1))
+        .token_store(store.clone())
+        .build()
+        .await
+        .unwrap();
+
+    let results = client.send_to_user("alice", &message("unused")).await.unwrap();
+
+    assert_eq!("a", results[0].0);
+    assert!(results[0].1.is_ok());
+    assert_eq!("b", results[1].0);
+    assert!(results[1].1.is_err());
+    assert_eq!(vec!["a".to_string()], store.active_tokens("alice").await.unwrap());
+    assert_eq!(json!("b"), server.messages()[1].message["token"]);
+}
+
+#[tokio::test]
+async fn test_send_to_user_requires_store() {
+    let server = MockServer::start();
+    let client = server.client().await.unwrap();
+
+    assert_eq!(
+        Some(TokenStoreError::NotConfigured),
+        client.send_to_user("alice", &message("unused")).await.err()
+    );
+}
@@ -1,4 +1,4 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
 use super::webpush_fcm_options::{WebpushFcmOptions, WebpushFcmOptionsInternal};
@@ -24,7 +24,7 @@ pub(crate) struct WebpushConfigInternal<'m> {
     fcm_options: Option<WebpushFcmOptionsInternal<'m>>,
 }
 
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
 /// https://firebase.google.com/docs/reference/fcm/rest/v1/projects.messages?authuser=0#webpushconfig
 pub struct WebpushConfig {
     /// HTTP headers defined in webpush protocol.
@@ -42,7 +42,7 @@ pub struct WebpushConfig {
 }
 
 impl WebpushConfig {
-    pub(crate) fn finalize(&self) -> WebpushConfigInternal {
+    pub(crate) fn finalize(&self) -> WebpushConfigInternal<'_> {
         WebpushConfigInternal {
             headers: self.headers.as_ref(),
             data: self.data.as_ref(),
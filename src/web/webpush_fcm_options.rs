@@ -1,4 +1,4 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Debug)]
 /// https://firebase.google.com/docs/reference/fcm/rest/v1/projects.messages?authuser=0#webpushfcmoptions
@@ -10,7 +10,7 @@ pub(crate) struct WebpushFcmOptionsInternal<'m> {
     analytics_label: &'m str,
 }
 
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
 /// https://firebase.google.com/docs/reference/fcm/rest/v1/projects.messages?authuser=0#webpushfcmoptions
 pub struct WebpushFcmOptions {
     /// The link to open when the user clicks on the notification.
@@ -21,7 +21,7 @@ pub struct WebpushFcmOptions {
 }
 
 impl WebpushFcmOptions {
-    pub(crate) fn finalize(&self) -> WebpushFcmOptionsInternal {
+    pub(crate) fn finalize(&self) -> WebpushFcmOptionsInternal<'_> {
         WebpushFcmOptionsInternal {
             link: &self.link,
             analytics_label: &self.analytics_label,